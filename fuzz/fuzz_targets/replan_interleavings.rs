@@ -0,0 +1,26 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// See smooth1d::fuzz for the input format
+fuzz_target!(|data: &[u8]| {
+	if let Err(err) = smooth1d::fuzz::run_interleaving(data) {
+		panic!("{}", err);
+	}
+});
@@ -0,0 +1,49 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+#ifndef SMOOTH1D_H
+#define SMOOTH1D_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Smooth1d Smooth1d;
+
+/*
+ * limits: { MAX_ACC } for an acc-limited path or { MAX_ACC, JERK } for a
+ * jerk-limited path. Returns NULL if count is not 1 or 2, or a limit is
+ * not a finite value above zero.
+ */
+Smooth1d *smooth1d_new(const double *limits, size_t count);
+void smooth1d_free(Smooth1d *path);
+
+void smooth1d_replan(Smooth1d *path, double s_target, double v_limit);
+void smooth1d_stop(Smooth1d *path);
+void smooth1d_update(Smooth1d *path, double dt);
+
+/* Any of pos, vel and acc may be NULL */
+void smooth1d_state(const Smooth1d *path, double *pos, double *vel, double *acc);
+bool smooth1d_is_active(const Smooth1d *path);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SMOOTH1D_H */
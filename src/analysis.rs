@@ -0,0 +1,136 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+/*
+ * Frequency content of a planned move, to check before running it that it
+ * stays clear of a known machine resonance. The acceleration over the
+ * remaining plan is sampled and its Fourier transform evaluated at the
+ * bins k/T, T being the plan duration. Direct sums, O(samples*bins): fine
+ * for checking a plan, not for every control cycle.
+ */
+
+use crate::math;
+use crate::path::Path;
+
+/*
+ * Magnitude of the Fourier transform of the acceleration, |A(f)| (in
+ * acceleration units times seconds), at each of freqs (Hz).
+ */
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Spectrum {
+	pub freqs: Vec<f64>,
+	pub magnitude: Vec<f64>,
+}
+
+impl Spectrum {
+	// Share of the energy (sum of squared magnitudes) between f_lo and f_hi
+	pub fn band_fraction(&self, f_lo: f64, f_hi: f64) -> f64 {
+		let energy = |in_band: &dyn Fn(f64) -> bool| self.freqs.iter().zip(&self.magnitude)
+			.filter(|(freq, _)| in_band(**freq))
+			.map(|(_, mag)| mag*mag)
+			.sum::<f64>();
+		let total = energy(&|_| true);
+		if total == 0.0 {
+			return 0.0;
+		}
+		energy(&|freq| freq >= f_lo && freq <= f_hi)/total
+	}
+
+	// (freq, magnitude) of the largest magnitude between f_lo and f_hi, if any bin is there
+	pub fn peak_in(&self, f_lo: f64, f_hi: f64) -> Option<(f64, f64)> {
+		self.freqs.iter().zip(&self.magnitude)
+			.filter(|(freq, _)| **freq >= f_lo && **freq <= f_hi)
+			.map(|(freq, mag)| (*freq, *mag))
+			.fold(None, |best: Option<(f64, f64)>, bin| if best.is_none_or(|best| bin.1 > best.1) { Some(bin) } else { best })
+	}
+}
+
+/*
+ * Spectrum of the acceleration of the remaining plan, sampled at
+ * sample_rate (at the middle of each sample interval, so no sample falls
+ * on a jump), up to max_freq or the Nyquist frequency, whichever is lower.
+ * The plan must end.
+ */
+pub fn acceleration_spectrum<const DEGREE: usize>(path: &Path<DEGREE>, sample_rate: f64, max_freq: f64) -> Spectrum {
+	assert!(sample_rate > 0.0 && max_freq >= 0.0);
+	let acc = path.piecewise_poly().derivative().derivative();
+	let duration = *acc.breaks.last().unwrap();
+	assert!(duration.is_finite());
+	if duration == 0.0 {
+		return Spectrum::default();
+	}
+
+	let count = (duration*sample_rate).ceil() as usize;
+	let dt = duration/count as f64;
+	let samples: Vec<(f64, f64)> = (0..count).map(|n| {
+		let t = (n as f64 + 0.5)*dt;
+		(t, acc.eval(t))
+	}).collect();
+
+	let mut spectrum = Spectrum::default();
+	let max_freq = max_freq.min(0.5*sample_rate);
+	let mut k = 0;
+	while k as f64/duration <= max_freq {
+		let freq = k as f64/duration;
+		let omega = 2.0*std::f64::consts::PI*freq;
+		let (mut re, mut im) = (0.0, 0.0);
+		for (t, a) in &samples {
+			re += a*math::cos(omega*t);
+			im -= a*math::cos(omega*t - std::f64::consts::FRAC_PI_2);
+		}
+		spectrum.freqs.push(freq);
+		spectrum.magnitude.push(dt*(re*re + im*im).sqrt());
+		k += 1;
+	}
+	spectrum
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::acceleration_spectrum;
+	use crate::path::{Limits, Path, Profile};
+	use crate::scenario::check_eq;
+
+	#[test]
+	fn trapezoid_and_smoothstep() -> Result<(), String> {
+		// Acceleration pulses of ±0.5 for 0.2 s, 0.4 s apart
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		let spectrum = acceleration_spectrum(&path, 1000.0, 100.0);
+		check_eq(0.0, spectrum.freqs[1], 1.0/0.6)?;
+		// 0.5*0.2*|sinc(0.2*f)|*|1 - exp(-2πi*f*0.4)| at f = 1/0.6
+		check_eq(0.0, (spectrum.magnitude[1]*1e3).round()/1e3, 0.143)?;
+
+		// The smoothstep has no jumps in the acceleration, so less of its energy is high up
+		let limits = Limits { vel: Some(0.1), ..Limits::acc_limited(0.5) };
+		let mut smooth = Path::<2>::with_profile(limits, Profile::Smoothstep);
+		smooth.replan(0.04, 0.1);
+		// No net change in velocity; 0.04/0.75*|∫(60τ - 180τ² + 120τ³)exp(-2πiτ)dτ| at f = 1/0.75
+		let smooth_spectrum = acceleration_spectrum(&smooth, 1000.0, 100.0);
+		check_eq(0.0, (smooth_spectrum.magnitude[0]*1e6).round()/1e6, 0.0)?;
+		check_eq(0.0, (smooth_spectrum.magnitude[1]*1e3).round()/1e3, 0.155)?;
+		let high = |path: &Path<2>| acceleration_spectrum(path, 1000.0, 100.0).band_fraction(10.0, 100.0);
+		if high(&smooth) >= high(&path) {
+			return Err(format!("High-frequency share {} for smoothstep, {} for trapezoid", high(&smooth), high(&path)));
+		}
+		if spectrum.peak_in(0.0, 100.0).is_none() || spectrum.peak_in(200.0, 300.0).is_some() {
+			return Err(format!("Peaks: {:?}", spectrum.peak_in(0.0, 100.0)));
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/*
+ * Print the plan of a single move, to sanity-check a set of limits:
+ *
+ * smooth1d-plan --acc 0.5 [--jerk 5] --vel 0.1 [--start POS[,VEL[,ACC]]] --target 0.04 [--csv DT] [--json]
+ *
+ * Prints each phase and the total duration, or with --json the plan as
+ * PlanReport::to_json() (needs the serde feature). --csv DT adds
+ * "t,pos,vel,acc" samples every DT seconds.
+ */
+
+use smooth1d::path::{Limits, Path};
+use smooth1d::report::PlanReport;
+use smooth1d::segment::Segment;
+
+#[derive(Default)]
+struct Args {
+	acc: Option<f64>,
+	jerk: Option<f64>,
+	vel: Option<f64>,
+	start: (f64, f64, f64),
+	target: Option<f64>,
+	csv: Option<f64>,
+	json: bool,
+}
+
+const USAGE: &str = "Usage: smooth1d-plan --acc A [--jerk J] --vel V [--start POS[,VEL[,ACC]]] --target S [--csv DT] [--json]";
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+	let mut parsed = Args::default();
+	while let Some(arg) = args.next() {
+		if arg == "--json" {
+			parsed.json = true;
+			continue;
+		}
+
+		let val = args.next().ok_or(format!("Missing value for {}", arg))?;
+		let num = |val: &str| val.trim().parse::<f64>().map_err(|err| format!("Bad value '{}' for {}: {}", val, arg, err));
+		match arg.as_str() {
+			"--acc" => parsed.acc = Some(num(&val)?),
+			"--jerk" => parsed.jerk = Some(num(&val)?),
+			"--vel" => parsed.vel = Some(num(&val)?),
+			"--target" => parsed.target = Some(num(&val)?),
+			"--csv" => parsed.csv = Some(num(&val)?),
+			"--start" => {
+				let vals = val.split(',').map(num).collect::<Result<Vec<f64>, String>>()?;
+				if vals.is_empty() || vals.len() > 3 {
+					return Err(format!("Expected 1 to 3 values for --start, got {}", vals.len()));
+				}
+				let get = |n: usize| vals.get(n).copied().unwrap_or(0.0);
+				parsed.start = (get(0), get(1), get(2));
+			},
+			_ => return Err(format!("Unknown option {}", arg)),
+		}
+	}
+	Ok(parsed)
+}
+
+fn plan<const DEGREE: usize>(limits: Limits, args: &Args, target: f64, vel: f64) -> Vec<Segment> {
+	Path::<DEGREE>::with_limits(limits).plan_from(args.start, target, vel)
+}
+
+fn print_csv(segments: &[Segment], dt: f64) {
+	println!("t,pos,vel,acc");
+	let mut t_start = 0.0;
+	let mut n = 0;
+	for seg in segments {
+		loop {
+			let t = n as f64*dt - t_start;
+			if t > seg.get_duration() {
+				break;
+			}
+			let state = seg.get_state_at(t);
+			let pos_index = state.len() - 1;
+			println!("{},{},{},{}", n as f64*dt, state[pos_index], state[pos_index - 1], state[pos_index - 2]);
+			n += 1;
+		}
+		t_start += seg.get_duration();
+	}
+
+	// The end, if rounding left it out
+	if let Some(last) = segments.last() {
+		if (n as f64 - 1.0)*dt < t_start - 1e-9 {
+			let state = last.get_end_state();
+			let pos_index = state.len() - 1;
+			println!("{},{},{},{}", t_start, state[pos_index], state[pos_index - 1], state[pos_index - 2]);
+		}
+	}
+}
+
+fn run() -> Result<(), String> {
+	let args = parse_args(std::env::args().skip(1))?;
+	let (acc, vel, target) = match (args.acc, args.vel, args.target) {
+		(Some(acc), Some(vel), Some(target)) => (acc, vel, target),
+		_ => return Err("--acc, --vel and --target are required".to_string()),
+	};
+	if acc <= 0.0 || vel <= 0.0 || args.jerk.is_some_and(|jerk| jerk <= 0.0) || args.csv.is_some_and(|dt| dt <= 0.0) {
+		return Err("Limits and the sample interval must be positive".to_string());
+	}
+
+	let segments = match args.jerk {
+		None => plan::<2>(Limits::acc_limited(acc), &args, target, vel),
+		Some(jerk) => plan::<3>(Limits::jerk_limited(acc, jerk), &args, target, vel),
+	};
+	let report = PlanReport::new(segments.clone());
+
+	if args.json {
+		#[cfg(feature = "serde")]
+		println!("{}", report.to_json());
+		#[cfg(not(feature = "serde"))]
+		return Err("--json needs the serde feature".to_string());
+	} else {
+		for (n, phase) in report.phases.iter().enumerate() {
+			println!("{}: {:<10} {:.6} s, from {:?} to {:?}", n, phase.label(), phase.duration, phase.start, phase.end);
+		}
+		if let Some(overshoot) = report.overshoot {
+			println!("Overshoot: {} past the target at {:.6} s", overshoot.distance, overshoot.time);
+		}
+		println!("Duration: {:.6} s", report.get_duration());
+	}
+
+	if let Some(dt) = args.csv {
+		print_csv(&segments, dt);
+	}
+	Ok(())
+}
+
+fn main() {
+	if let Err(err) = run() {
+		eprintln!("{}\n{}", err, USAGE);
+		std::process::exit(1);
+	}
+}
@@ -0,0 +1,161 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::VecDeque;
+use crate::path::Path;
+
+/*
+ * One cycle's worth of CiA 402 cyclic synchronous position (CSP) objects:
+ * target position (0x607A) and velocity offset (0x60B1), in drive counts.
+ * Positions wrap around like the 32-bit drive position does.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Setpoint {
+	pub position: i32,
+	pub velocity: i32,		// Counts per second; feedforward for the drive's velocity loop
+}
+
+/*
+ * The CSP setpoint stream from a Path, for an EtherCAT or CANopen master
+ * sending one setpoint per bus cycle:
+ *
+ * let mut stream = CspStream::new(Path::acc_limited(0.5), 0.001, 4, 1e6);
+ * // Application task, at its own rate:
+ * stream.path_mut().replan(0.04, 0.1);
+ * stream.fill();
+ * // Bus cycle task, every 1 ms:
+ * let setpoint = stream.next_setpoint();
+ *
+ * The path is sampled at the cycle times, so the setpoints are exact at
+ * any application rate. Taken setpoints are committed, so the stream runs
+ * the path ahead by the buffered depth: a replan made after the latest
+ * fill() starts from the end of the buffer, at most depth cycles late,
+ * and continues smoothly from the committed setpoints. If the buffer runs
+ * dry the setpoint is made on the spot and counted in underruns().
+ */
+#[derive(Clone, Debug)]
+pub struct CspStream<const DEGREE: usize> {
+	path: Path<DEGREE>,
+	cycle_time: f64,
+	depth: usize,
+	counts_per_unit: f64,
+	buffer: VecDeque<Setpoint>,
+	underruns: usize,
+}
+
+impl<const DEGREE: usize> CspStream<DEGREE> {
+	pub fn new(path: Path<DEGREE>, cycle_time: f64, depth: usize, counts_per_unit: f64) -> Self {
+		assert!(cycle_time > 0.0);
+		assert!(depth > 0);
+		assert!(counts_per_unit != 0.0);
+		Self {
+			path,
+			cycle_time,
+			depth,
+			counts_per_unit,
+			buffer: VecDeque::with_capacity(depth),
+			underruns: 0,
+		}
+	}
+
+	pub fn path(&self) -> &Path<DEGREE> {
+		&self.path
+	}
+
+	// For replan(), stop() etc; the path is at the time of the last buffered setpoint
+	pub fn path_mut(&mut self) -> &mut Path<DEGREE> {
+		&mut self.path
+	}
+
+	// Top up the buffer to depth setpoints
+	pub fn fill(&mut self) {
+		while self.buffer.len() < self.depth {
+			let setpoint = self.sample();
+			self.buffer.push_back(setpoint);
+		}
+	}
+
+	// The setpoint for this bus cycle
+	pub fn next_setpoint(&mut self) -> Setpoint {
+		match self.buffer.pop_front() {
+			Some(setpoint) => setpoint,
+			None => {
+				self.underruns += 1;
+				self.sample()
+			},
+		}
+	}
+
+	// Setpoints ready to send
+	pub fn buffered(&self) -> usize {
+		self.buffer.len()
+	}
+
+	// Number of cycles the buffer was empty
+	pub fn underruns(&self) -> usize {
+		self.underruns
+	}
+
+	fn sample(&mut self) -> Setpoint {
+		self.path.update(self.cycle_time);
+		let (pos, vel, _) = self.path.get_state();
+		Setpoint {
+			position: (pos*self.counts_per_unit).round() as i64 as i32,
+			velocity: (vel*self.counts_per_unit).round() as i32,
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::CspStream;
+	use crate::path::Path;
+
+	#[test]
+	fn setpoints_with_late_replan() -> Result<(), String> {
+		const COUNTS: f64 = 1e6;
+		let mut stream = CspStream::new(Path::acc_limited(0.5), 0.001, 4, COUNTS);
+		stream.path_mut().replan(0.04, 0.1);
+
+		// Velocity counts per cycle, with rounding
+		let max_step = (0.1*0.001*COUNTS) as i32 + 1;
+		let max_step_change = (0.5*0.001*0.001*COUNTS) as i32 + 2;
+		let mut prev = (0, 0);
+		for cycle in 0..1200 {
+			// The application only gets to run every 10 cycles
+			if cycle % 10 == 0 {
+				if cycle == 300 {
+					stream.path_mut().replan(0.02, 0.1);
+				}
+				stream.fill();
+			}
+			let setpoint = stream.next_setpoint();
+			let step = setpoint.position - prev.0;
+			if step.abs() > max_step || (step - prev.1).abs() > max_step_change {
+				return Err(format!("Cycle {}: step {} after {}", cycle, step, prev.1));
+			}
+			prev = (setpoint.position, step);
+		}
+		if prev.0 != 20000 || stream.path().is_active() {
+			return Err(format!("Ended at {} counts", prev.0));
+		}
+		if stream.underruns() != 6*1200/10 {
+			return Err(format!("{} underruns", stream.underruns()));
+		}
+		Ok(())
+	}
+}
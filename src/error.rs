@@ -0,0 +1,84 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+use std::fmt;
+
+// Properties of a plan checked by Path::checked_invariants()
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum Invariant {
+	CurrentState,	// The state is where the plan says it should be
+	Continuity,	// Each segment starts where the one before ends
+	Velocity,
+	Acceleration,
+	Jerk,
+	Termination,	// The plan ends at rest at the target, or holds a constant input
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum Error {
+	// A finished move ended further than tolerance from its target state
+	EndOfMoveDeviation { deviation: f64, tolerance: f64 },
+	// Segment (0 is the current one) breaks invariant with value, allowed up to limit
+	InvariantViolation { invariant: Invariant, segment: usize, value: f64, limit: f64 },
+	// Path::stretch_to() couldn't reach the requested duration within the limits
+	InfeasibleDuration { requested: f64, achieved: f64 },
+	// Path::set_limits() would leave the current state, or the acceleration bias (value), beyond limit
+	StateBeyondLimits { value: f64, limit: f64 },
+	// An idle path drifted further than tolerance from its target, see Path::enable_hold_correction()
+	HoldDrift { deviation: f64, tolerance: f64 },
+	// Path::replan_directional() can't reach s_target in the allowed direction, only reachable
+	ForbiddenDirection { s_target: f64, reachable: f64 },
+	// A plan needed more segments than the limit, see Path::set_max_plan_segments()
+	PlanTooLong { segments: usize, limit: usize },
+	// Path::queue_move() refused a move with the queue already at depth, see Path::set_max_queue_depth()
+	QueueFull { depth: usize },
+	// A plan to s_target, exactly where it started at rest, took its direction from TieBreak::Error
+	DirectionTie { s_target: f64 },
+	// The limit by name ("vel", "acc", ...) is set to value, which isn't above zero, or not finite, see Limits::validate()
+	InvalidLimit { name: &'static str, value: f64 },
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::EndOfMoveDeviation { deviation, tolerance } =>
+				write!(f, "End of move deviates {} from target (tolerance {})", deviation, tolerance),
+			Error::InvariantViolation { invariant, segment, value, limit } =>
+				write!(f, "{:?} invariant violated in segment {}: {} (limit {})", invariant, segment, value, limit),
+			Error::InfeasibleDuration { requested, achieved } =>
+				write!(f, "Duration {} not feasible within limits, planned {}", requested, achieved),
+			Error::StateBeyondLimits { value, limit } =>
+				write!(f, "Current state {} beyond new limit {}", value, limit),
+			Error::HoldDrift { deviation, tolerance } =>
+				write!(f, "Idle state drifted {} from target (tolerance {})", deviation, tolerance),
+			Error::ForbiddenDirection { s_target, reachable } =>
+				write!(f, "Target {} needs motion in the forbidden direction, stopping at {}", s_target, reachable),
+			Error::PlanTooLong { segments, limit } =>
+				write!(f, "Plan needs {} segments, limit {}", segments, limit),
+			Error::QueueFull { depth } =>
+				write!(f, "Move queue full at {} moves", depth),
+			Error::DirectionTie { s_target } =>
+				write!(f, "Direction of the plan to {} decided by a tie at zero distance", s_target),
+			Error::InvalidLimit { name, value } =>
+				write!(f, "Invalid {} limit {}", name, value),
+		}
+	}
+}
+
+impl std::error::Error for Error {}
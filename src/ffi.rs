@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+/*
+ * C interface, built with the "ffi" feature. See include/smooth1d.h.
+ *
+ * A path is an opaque handle from smooth1d_new(), to be released with
+ * smooth1d_free(). All other functions must be given a handle that came
+ * from smooth1d_new() and hasn't been freed, and must not be called on the
+ * same handle from several threads at once. Invalid input parameters abort
+ * the process, like assertions fail in the Rust API.
+ */
+
+#![allow(clippy::missing_safety_doc)]
+
+use crate::path::{DynPath, Limits};
+
+pub struct Smooth1d {
+	path: DynPath,
+}
+
+/*
+ * limits points to count values: (MAX_)ACC, (JERK).
+ * Returns NULL if count is not 1 or 2, or a limit is not a finite value
+ * above zero.
+ */
+#[no_mangle]
+pub unsafe extern "C" fn smooth1d_new(limits: *const f64, count: usize) -> *mut Smooth1d {
+	if limits.is_null() || !(1..=2).contains(&count) {
+		return std::ptr::null_mut();
+	}
+
+	let limits = std::slice::from_raw_parts(limits, count);
+	match DynPath::try_with_limits(Limits::from_slice(limits)) {
+		Ok(path) => Box::into_raw(Box::new(Smooth1d { path })),
+		Err(_) => std::ptr::null_mut(),
+	}
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smooth1d_free(handle: *mut Smooth1d) {
+	if !handle.is_null() {
+		drop(Box::from_raw(handle));
+	}
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smooth1d_replan(handle: *mut Smooth1d, s_target: f64, v_limit: f64) {
+	(*handle).path.replan(s_target, v_limit);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smooth1d_stop(handle: *mut Smooth1d) {
+	(*handle).path.stop();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smooth1d_update(handle: *mut Smooth1d, dt: f64) {
+	(*handle).path.update(dt);
+}
+
+// Any of pos, vel and acc may be NULL if that value isn't wanted
+#[no_mangle]
+pub unsafe extern "C" fn smooth1d_state(handle: *const Smooth1d, pos: *mut f64, vel: *mut f64, acc: *mut f64) {
+	let state = (*handle).path.get_state();
+	for (out, val) in [(pos, state.0), (vel, state.1), (acc, state.2)].iter() {
+		if !out.is_null() {
+			**out = *val;
+		}
+	}
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn smooth1d_is_active(handle: *const Smooth1d) -> bool {
+	(*handle).path.is_active()
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ffi_move() {
+		unsafe {
+			assert!(smooth1d_new([0.5, 5.0, 1.0].as_ptr(), 3).is_null());
+
+			let handle = smooth1d_new([0.5].as_ptr(), 1);
+			assert!(!handle.is_null());
+			smooth1d_replan(handle, 0.04, 0.1);
+			assert!(smooth1d_is_active(handle));
+			for _ in 0..610 {
+				smooth1d_update(handle, 0.001);
+			}
+			assert!(!smooth1d_is_active(handle));
+
+			let mut pos = 0.0;
+			let mut vel = 1.0;
+			smooth1d_state(handle, &mut pos, &mut vel, std::ptr::null_mut());
+			assert_eq!((pos, vel), (0.04, 0.0));
+			smooth1d_free(handle);
+		}
+	}
+}
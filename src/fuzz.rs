@@ -0,0 +1,97 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/*
+ * Fuzzing driver, built with the "fuzz" feature and run by the cargo-fuzz
+ * target in fuzz/:
+ *
+ * cargo fuzz run replan_interleavings
+ *
+ * The input bytes give the path type and limits (3 bytes), then one call
+ * per 3 bytes: replan(), stop(), update() or set_overshoot_policy().
+ * Path::checked_invariants() must hold after each call, and once the calls
+ * run out the path must come to rest within the remaining planned time.
+ * The same input always gives the same run.
+ */
+
+use crate::path::{Path, OvershootPolicy};
+
+pub fn run_interleaving(data: &[u8]) -> Result<(), String> {
+	if data.len() < 3 {
+		return Ok(());
+	}
+
+	let acc = 0.1 + data[1] as f64/16.0;
+	let jerk = 1.0 + data[2] as f64;
+	if data[0].is_multiple_of(2) {
+		run(Path::acc_limited(acc), &data[3..])
+	} else {
+		run(Path::jerk_limited(acc, jerk), &data[3..])
+	}
+}
+
+fn run<const DEGREE: usize>(mut path: Path<DEGREE>, calls: &[u8]) -> Result<(), String> {
+	for (n, call) in calls.chunks_exact(3).enumerate() {
+		let arg = |index: usize| call[index] as f64/255.0;
+		match call[0] % 4 {
+			0 => path.replan(2.0*arg(1) - 1.0, 0.01 + arg(2)),
+			1 => path.stop(),
+			2 => {
+				path.update(0.1*arg(1)*arg(2));
+			},
+			_ => path.set_overshoot_policy(if call[1].is_multiple_of(2) { OvershootPolicy::Allow } else { OvershootPolicy::Forbid }),
+		}
+		path.checked_invariants().map_err(|err| format!("Call {} ({:?}): {}", n, call, err))?;
+	}
+
+	let remaining = path.update(0.0).remaining;
+	if !remaining.is_finite() {
+		return Err(format!("Plan never ends: {}", path));
+	}
+	let dt = 0.01;
+	for _ in 0..(remaining/dt) as usize + 2 {
+		path.update(dt);
+		path.checked_invariants().map_err(|err| format!("Running out the plan: {}", err))?;
+	}
+	if path.is_active() {
+		return Err(format!("Still active after the planned {} s: {}", remaining, path));
+	}
+	Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::run_interleaving;
+
+	#[test]
+	fn random_interleavings() -> Result<(), String> {
+		// xorshift64, for the same inputs on every run
+		let mut seed = 0x2545_f491_4f6c_dd1du64;
+		let mut next = move || {
+			seed ^= seed << 13;
+			seed ^= seed >> 7;
+			seed ^= seed << 17;
+			seed as u8
+		};
+
+		for _ in 0..500 {
+			let data: Vec<u8> = (0..3 + 3*20).map(|_| next()).collect();
+			run_interleaving(&data).map_err(|err| format!("Input {:?}: {}", data, err))?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,76 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+/*
+ * Debug output from the planner on stderr, with the debug-log feature.
+ * Otherwise, and always in a WebAssembly build where there is no process
+ * to print from, it compiles to nothing: replanning is meant for control
+ * loops, and PlanTrace covers debugging the planner's decisions there.
+ */
+#[cfg(all(feature = "debug-log", not(feature = "wasm")))]
+macro_rules! log {
+	($($arg:tt)*) => { eprintln!($($arg)*) };
+}
+#[cfg(not(all(feature = "debug-log", not(feature = "wasm"))))]
+macro_rules! log {
+	($($arg:tt)*) => { { let _ = format_args!($($arg)*); } };
+}
+
+pub mod error;
+mod math;
+pub mod segment;
+pub mod poly;
+pub mod path;
+pub mod recorder;
+pub mod report;
+pub mod trace;
+pub mod trapezoid;
+pub mod sync;
+pub mod shared;
+pub mod tuning;
+pub mod reparam;
+pub mod cia402;
+pub mod scenario;
+pub mod sim;
+pub mod analysis;
+pub mod stream;
+pub mod program;
+pub mod verify;
+pub mod sampling;
+pub mod presets;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "async")]
+pub mod notify;
+#[cfg(any(test, feature = "fuzz"))]
+pub mod fuzz;
+
+/*
+ * The types most programs need, in one import:
+ *
+ *   use smooth1d::prelude::*;
+ *
+ * These stay where they are across releases; the rest of the API is in
+ * the modules.
+ */
+pub mod prelude {
+	pub use crate::error::Error;
+	pub use crate::path::{DynPath, Limits, Path, Progress, State};
+	pub use crate::report::PlanReport;
+}
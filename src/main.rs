@@ -14,9 +14,6 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
 */
 
-pub mod segment;
-pub mod poly;
-pub mod path;
 
 fn main() {
 	println!("Nothing to see here, take a look at the tests in path.rs instead! (Or run 'cargo test').");
@@ -0,0 +1,174 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/*
+ * The non-elementary float functions used by the planner.
+ *
+ * +, -, *, / and sqrt() are correctly rounded by IEEE 754 and give the same
+ * result on every platform (Rust never contracts a*b + c into an fma on its
+ * own). powi(), powf(), acos(), cos() and friends are not: they end up in
+ * the platform's libm or in LLVM's own expansions, which differ in the last
+ * bits between e.g. x86 and ARM.
+ *
+ * With the "deterministic" feature these are instead computed here from the
+ * correctly rounded operations only, in a fixed order, so the same inputs
+ * give bit-identical plans everywhere. The results are within a few ulp of
+ * the std versions.
+ */
+
+#[cfg(not(feature = "deterministic"))]
+pub(crate) fn powi(x: f64, n: i32) -> f64 {
+	x.powi(n)
+}
+
+#[cfg(feature = "deterministic")]
+pub(crate) fn powi(x: f64, n: i32) -> f64 {
+	let mut res = 1.0;
+	for _ in 0..n.unsigned_abs() {
+		res *= x;
+	}
+	if n < 0 { 1.0/res } else { res }
+}
+
+#[cfg(not(feature = "deterministic"))]
+pub(crate) fn cbrt(x: f64) -> f64 {
+	x.cbrt()
+}
+
+#[cfg(feature = "deterministic")]
+pub(crate) fn cbrt(x: f64) -> f64 {
+	if x == 0.0 || !x.is_finite() {
+		return x;
+	}
+
+	// First guess from the exponent bits (within a few %), then Newton
+	let abs = x.abs();
+	let mut y = f64::from_bits(abs.to_bits()/3 + 0x2a9f_7893_782d_a1ce);
+	for _ in 0..6 {
+		y -= (y - abs/(y*y))/3.0;
+	}
+	if x < 0.0 { -y } else { y }
+}
+
+#[cfg(not(feature = "deterministic"))]
+pub(crate) fn cos(x: f64) -> f64 {
+	x.cos()
+}
+
+#[cfg(feature = "deterministic")]
+pub(crate) fn cos(x: f64) -> f64 {
+	// pi/2 split in two parts so that x - k*pi/2 stays exact for small k
+	let pio2_hi = f64::from_bits(0x3ff9_21fb_5440_0000);
+	let pio2_lo = f64::from_bits(0x3dd0_b461_1a62_6331);
+
+	let k = (x/std::f64::consts::FRAC_PI_2).round();
+	let r = (x - k*pio2_hi) - k*pio2_lo;
+	match (k as i64).rem_euclid(4) {
+		0 => cos_kernel(r),
+		1 => -sin_kernel(r),
+		2 => -cos_kernel(r),
+		_ => sin_kernel(r),
+	}
+}
+
+// Taylor series for |x| <= pi/4
+#[cfg(feature = "deterministic")]
+fn cos_kernel(x: f64) -> f64 {
+	let x2 = x*x;
+	let mut term = 1.0;
+	let mut sum = 1.0;
+	for n in 1..=10 {
+		term *= -x2/((2*n - 1) as f64*(2*n) as f64);
+		sum += term;
+	}
+	sum
+}
+
+#[cfg(feature = "deterministic")]
+fn sin_kernel(x: f64) -> f64 {
+	let x2 = x*x;
+	let mut term = x;
+	let mut sum = x;
+	for n in 1..=10 {
+		term *= -x2/((2*n) as f64*(2*n + 1) as f64);
+		sum += term;
+	}
+	sum
+}
+
+#[cfg(not(feature = "deterministic"))]
+pub(crate) fn acos(x: f64) -> f64 {
+	x.acos()
+}
+
+#[cfg(feature = "deterministic")]
+pub(crate) fn acos(x: f64) -> f64 {
+	if x <= -1.0 {
+		return if x == -1.0 { std::f64::consts::PI } else { f64::NAN };
+	}
+	2.0*atan(((1.0 - x)/(1.0 + x)).sqrt())
+}
+
+// atan() for x >= 0
+#[cfg(feature = "deterministic")]
+fn atan(x: f64) -> f64 {
+	if x > 1.0 {
+		return std::f64::consts::FRAC_PI_2 - atan(1.0/x);
+	}
+	// Reduce to |x| <= tan(pi/8) for the series to converge quickly
+	if x > std::f64::consts::SQRT_2 - 1.0 {
+		return std::f64::consts::FRAC_PI_4 + atan_kernel((x - 1.0)/(x + 1.0));
+	}
+	atan_kernel(x)
+}
+
+#[cfg(feature = "deterministic")]
+fn atan_kernel(x: f64) -> f64 {
+	let x2 = x*x;
+	let mut pow = x;
+	let mut sum = x;
+	for n in 1..=22 {
+		pow *= -x2;
+		sum += pow/(2*n + 1) as f64;
+	}
+	sum
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::{powi, cbrt, cos, acos};
+
+	#[test]
+	fn close_to_std() -> Result<(), String> {
+		let check = |name: &str, x: f64, val: f64, goal: f64| {
+			if (val - goal).abs() > 1e-14*goal.abs().max(1.0) {
+				return Err(format!("{}({}) = {}, expected {}", name, x, val, goal));
+			}
+			Ok(())
+		};
+
+		for n in -40..=40 {
+			let x = 0.37*n as f64;
+			check("powi", x, powi(x, 3), x.powi(3))?;
+			check("cbrt", x, cbrt(x), x.cbrt())?;
+			check("cos", x, cos(x), x.cos())?;
+			let x = n as f64/40.0;
+			check("acos", x, acos(x), x.acos())?;
+		}
+		Ok(())
+	}
+}
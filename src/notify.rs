@@ -0,0 +1,237 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/*
+ * Futures for async code watching a Path, built with the "async" feature.
+ * They only use std::future, so they work with any executor (tokio,
+ * async-std, embassy, ...). The Path itself is still driven by whoever
+ * calls update() or update_to(); each call publishes the new state and
+ * wakes the waiting tasks:
+ *
+ *   let done = path.wait_done();
+ *   let mut states = path.watch_state();
+ *   // In other tasks:
+ *   done.await;
+ *   let (pos, vel, acc) = states.changed().await;
+ */
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+#[derive(Default)]
+struct Shared {
+	moves_done: u64,	// Number of moves run through to the end
+	state: (f64, f64, f64),
+	version: u64,		// Number of states published
+	wakers: Vec<Waker>,
+}
+
+impl Shared {
+	fn wait(&mut self, waker: &Waker) {
+		if !self.wakers.iter().any(|w| w.will_wake(waker)) {
+			self.wakers.push(waker.clone());
+		}
+	}
+}
+
+fn lock(shared: &Mutex<Shared>) -> std::sync::MutexGuard<'_, Shared> {
+	// The data is plain values, valid even if a holder panicked
+	shared.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+pub(crate) struct Notifier {
+	shared: Arc<Mutex<Shared>>,
+}
+
+impl Notifier {
+	pub(crate) fn new() -> Self {
+		Self {
+			shared: Arc::new(Mutex::new(Shared::default())),
+		}
+	}
+
+	pub(crate) fn publish(&self, state: (f64, f64, f64), finished: bool) {
+		let mut shared = lock(&self.shared);
+		if finished {
+			shared.moves_done += 1;
+		}
+		shared.state = state;
+		shared.version += 1;
+		for waker in shared.wakers.drain(..) {
+			waker.wake();
+		}
+	}
+
+	pub(crate) fn done(&self, active: bool) -> MoveDone {
+		let moves_done = lock(&self.shared).moves_done;
+		MoveDone {
+			shared: self.shared.clone(),
+			target: if active { Some(moves_done + 1) } else { None },
+		}
+	}
+
+	pub(crate) fn receiver(&self, state: (f64, f64, f64)) -> StateReceiver {
+		let mut shared = lock(&self.shared);
+		if shared.version == 0 {
+			shared.state = state;
+		}
+		StateReceiver {
+			shared: self.shared.clone(),
+			seen: shared.version,
+		}
+	}
+}
+
+// A clone of a Path is a separate path, with its own listeners
+impl Clone for Notifier {
+	fn clone(&self) -> Self {
+		Self::new()
+	}
+}
+
+impl fmt::Debug for Notifier {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Notifier")
+	}
+}
+
+/*
+ * Resolves when the move that was active when it was created has been run
+ * through to its end, or at once if there was none, see Path::wait_done().
+ */
+pub struct MoveDone {
+	shared: Arc<Mutex<Shared>>,
+	target: Option<u64>,
+}
+
+impl Future for MoveDone {
+	type Output = ();
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+		let mut shared = lock(&self.shared);
+		match self.target {
+			Some(target) if shared.moves_done < target => {
+				shared.wait(cx.waker());
+				Poll::Pending
+			},
+			_ => Poll::Ready(()),
+		}
+	}
+}
+
+/*
+ * Watch-channel style receiver of the (pos, vel, acc) state published by
+ * each update(). Intermediate states are skipped for a slow receiver;
+ * changed() always gives the latest one.
+ */
+#[derive(Clone)]
+pub struct StateReceiver {
+	shared: Arc<Mutex<Shared>>,
+	seen: u64,
+}
+
+impl StateReceiver {
+	// The latest state, without waiting
+	pub fn state(&self) -> (f64, f64, f64) {
+		lock(&self.shared).state
+	}
+
+	// Wait for a state newer than the last one seen through changed()
+	pub fn changed(&mut self) -> Changed<'_> {
+		Changed {
+			receiver: self,
+		}
+	}
+}
+
+pub struct Changed<'a> {
+	receiver: &'a mut StateReceiver,
+}
+
+impl Future for Changed<'_> {
+	type Output = (f64, f64, f64);
+
+	fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<(f64, f64, f64)> {
+		let receiver = &mut *self.receiver;
+		let mut shared = lock(&receiver.shared);
+		if shared.version > receiver.seen {
+			receiver.seen = shared.version;
+			return Poll::Ready(shared.state);
+		}
+		shared.wait(cx.waker());
+		Poll::Pending
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use std::future::Future;
+	use std::pin::Pin;
+	use std::sync::Arc;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::task::{Context, Poll, Wake, Waker};
+	use crate::path::Path;
+
+	struct CountingWaker(AtomicUsize);
+
+	impl Wake for CountingWaker {
+		fn wake(self: Arc<Self>) {
+			self.0.fetch_add(1, Ordering::SeqCst);
+		}
+	}
+
+	fn poll<F: Future + Unpin>(fut: &mut F, waker: &Waker) -> Poll<F::Output> {
+		Pin::new(fut).poll(&mut Context::from_waker(waker))
+	}
+
+	#[test]
+	fn wait_done_and_watch_state() -> Result<(), String> {
+		let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+		let waker = Waker::from(wakes.clone());
+
+		let mut path = Path::acc_limited(0.5);
+		if poll(&mut path.wait_done(), &waker).is_pending() {
+			return Err("Not done at rest".to_string());
+		}
+
+		path.replan(0.04, 0.1);
+		let mut done = path.wait_done();
+		let mut states = path.watch_state();
+		if poll(&mut done, &waker).is_ready() || poll(&mut states.changed(), &waker).is_ready() {
+			return Err("Expected both to be pending".to_string());
+		}
+
+		path.update(0.3);
+		if wakes.0.load(Ordering::SeqCst) != 1 {
+			return Err(format!("{} wakes after one update", wakes.0.load(Ordering::SeqCst)));
+		}
+		if poll(&mut states.changed(), &waker) != Poll::Ready(path.get_state()) || poll(&mut done, &waker).is_ready() {
+			return Err("Expected a new state, and the move still running".to_string());
+		}
+
+		while path.is_active() {
+			path.update(0.01);
+		}
+		if poll(&mut done, &waker).is_pending() || states.state() != (0.04, 0.0, 0.0) {
+			return Err(format!("Move not done, state {:?}", states.state()));
+		}
+		Ok(())
+	}
+}
@@ -15,583 +15,5783 @@
 */
 
 use std::collections::VecDeque;
-use crate::segment::Segment;
-use crate::poly::Poly;
+use std::fmt;
+use std::ops::Range;
+use crate::segment::{Segment, MAX_STATE_LEN};
+use crate::poly::{Polynomial, PiecewisePoly};
+use crate::report::PlanReport;
+use crate::trace::{Branch, PlanTrace};
+#[cfg(feature = "async")]
+use crate::notify::{Notifier, MoveDone, StateReceiver};
+use crate::error::{Error, Invariant};
+use crate::math;
 
-const CLOSE_ENOUGH: f64 = 1e-12;
+// Enough for any single replan() or stop()
+const DEFAULT_MAX_SEGMENTS: usize = 8;
+// Guardrails against inputs that would make plans or the queue grow without bound
+const DEFAULT_MAX_PLAN_SEGMENTS: usize = 256;
+const DEFAULT_MAX_QUEUE_DEPTH: usize = 1024;
+pub(crate) const CLOSE_ENOUGH: f64 = 1e-12;
 const TINY_DURATION: f64 = 1e-12;
 
-pub struct Path {
-	limits: Vec<f64>,	// Highest-derivative first: limits[0] is jerk (or acc).
-	segments: VecDeque<Segment>,
-	time: f64,
-	state: Vec<f64>,	// Highest-derivative first: state[0] is jerk (or acc).
+/*
+ * What to do when a replan commands a target that lies within the current
+ * stopping distance, i.e. a target that can't be reached without passing it.
+ *
+ * Allow: time-optimal plan; brake through zero velocity and head straight
+ * back to the target.
+ *
+ * Forbid: brake to a full stop as early as possible, then plan a separate
+ * move back to the target from rest. The excursion past the target is
+ * bounded by the stopping distance at the time of the replan. Slower than
+ * Allow, most of all for jerk-limited paths, where reversing otherwise
+ * swings the acceleration straight through zero velocity. The excursion is
+ * reported by plan_report().overshoot, and as "excursion" in the StopFirst
+ * trace step of a jerk-limited path.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OvershootPolicy {
+	Allow,
+	Forbid,
+}
+
+/*
+ * What update() does with the state when the last segment of a move has
+ * been run through. The analytic end state can differ slightly from the
+ * target due to accumulated numerical inaccuracies.
+ *
+ * SnapToTarget: set the state to the target, at rest.
+ *
+ * HoldLastState: keep the end position, velocity (and acceleration for a
+ * jerk-limited path) of the last segment as is.
+ *
+ * ErrorIfDeviationExceeds(tolerance): snap to the target if no state value
+ * deviates more than tolerance from it; otherwise hold the last state and
+ * latch an Error::EndOfMoveDeviation, see Path::take_error().
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EndOfMovePolicy {
+	SnapToTarget,
+	HoldLastState,
+	ErrorIfDeviationExceeds(f64),
+}
+
+/*
+ * How moves queued with Path::queue_move() follow each other.
+ *
+ * StopBetween: run each move to its end, at rest, before starting the next.
+ *
+ * Blend: start the next move as soon as the current one starts its final
+ * braking, so it runs on without stopping if it continues in the same
+ * direction.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum QueuePolicy {
+	StopBetween,
+	Blend,
+}
+
+/*
+ * What stretch_to() does when the requested duration is too short to keep
+ * the plan within the limits.
+ *
+ * Strict: leave the plan as it was, and report Error::InfeasibleDuration.
+ *
+ * BestEffort: stretch to the shortest duration within the limits instead,
+ * and report Error::InfeasibleDuration.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Strictness {
+	Strict,
+	BestEffort,
+}
+
+/*
+ * Which way a plan goes when the distance left is exactly zero, which
+ * signum() would take from the sign bit of the zero, depending on how the
+ * distance was computed (-0.0 - 0.0 is -0.0). A path that is still moving
+ * always turns back, the only way to end at rest where it is; these rules
+ * are for a path at rest, where they decide the signs of the (empty) plan
+ * and its trace.
+ *
+ * PreferPositive: the positive direction, for either zero.
+ *
+ * PreferPrevious: the direction of the latest move that went anywhere;
+ * positive before the first one.
+ *
+ * Error: as PreferPositive, but also latch an Error::DirectionTie, see
+ * Path::take_error().
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TieBreak {
+	PreferPositive,
+	PreferPrevious,
+	Error,
+}
+
+/*
+ * Shape of the moves planned by replan(), chosen when the Path is made.
+ *
+ * TimeOptimal: the fastest move within the limits, with the highest
+ * derivative switching between its limits and zero.
+ *
+ * Smoothstep: a single quintic from the current state to rest at the
+ * target, the 3-4-5 polynomial s0 + (s1 - s0)*(10τ³ - 15τ⁴ + 6τ⁵) when
+ * starting at rest. Slower (1.875 times the mean velocity at the peak
+ * instead of up to 2), but with every derivative continuous, for less
+ * high-frequency content. The duration is the shortest within the
+ * limits. stop() is still time-optimal.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Profile {
+	TimeOptimal,
+	Smoothstep,
+}
+
+/*
+ * Change of position coordinates for Path::apply_transform(): new
+ * position = Mirror: 2*about - pos; Offset: pos + offset; Scale: k*pos,
+ * e.g. 1000.0 from meters to millimeters.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Transform {
+	Mirror(f64),
+	Offset(f64),
+	Scale(f64),
+}
+
+impl Transform {
+	// (k, b) of new position = k*pos + b
+	fn affine(self) -> (f64, f64) {
+		match self {
+			Transform::Mirror(about) => (-1.0, 2.0*about),
+			Transform::Offset(offset) => (1.0, offset),
+			Transform::Scale(k) => (k, 0.0),
+		}
+	}
+}
+
+/*
+ * Direction a move from replan_directional() may move in, e.g. to never
+ * retract toward a workpiece. Braking from motion already under way in
+ * the other direction can't be helped.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+	OnlyPositive,
+	OnlyNegative,
+	Either,
+}
+
+/*
+ * What the path is doing, see Path::status(). A fault latches until
+ * Path::clear_fault(): the path stops when it is raised, and replan() and
+ * the other calls that start a move are ignored until then.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum PathStatus {
+	Idle,
+	Moving,
+	Stopping,	// Running a plan from stop()
+	Faulted(FaultKind),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum FaultKind {
+	InvalidInput,		// A NaN or infinite target, negative velocity limit, or time going backwards
+	InfeasibleReplan,	// A move that can't be planned as asked, e.g. by replan_directional()
+	NumericalFailure,	// A plan that came out non-finite
+	Panicked,		// A call panicked midway with a SharedPath locked, see SharedPath
+}
+
+// Position, velocity and acceleration at one instant
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct State {
+	pub pos: f64,
+	pub vel: f64,
+	pub acc: f64,
+}
+
+impl From<(f64, f64, f64)> for State {
+	fn from(state: (f64, f64, f64)) -> Self {
+		Self {
+			pos: state.0,
+			vel: state.1,
+			acc: state.2,
+		}
+	}
+}
+
+impl From<State> for (f64, f64, f64) {
+	fn from(state: State) -> Self {
+		(state.pos, state.vel, state.acc)
+	}
+}
+
+/*
+ * Limits of a Path. jerk must be set for a jerk-limited path and None for
+ * an acc-limited one. vel, if set, caps the v_limit given to each replan().
+ * snap is for a future 4th-degree path and must be None. None is the
+ * "unlimited" setting: that constraint is skipped, and DynPath picks the
+ * acc-limited path for a jerk of None. Set limits must be above zero, and
+ * acc and jerk finite, see validate().
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Limits {
+	pub vel: Option<f64>,
+	pub acc: f64,
+	pub jerk: Option<f64>,
+	pub snap: Option<f64>,
+}
+
+impl Limits {
+	pub fn acc_limited(acc: f64) -> Self {
+		Self {
+			vel: None,
+			acc,
+			jerk: None,
+			snap: None,
+		}
+	}
+
+	pub fn jerk_limited(acc: f64, jerk: f64) -> Self {
+		Self {
+			jerk: Some(jerk),
+			..Self::acc_limited(acc)
+		}
+	}
+
+	// Error::InvalidLimit for the first set limit that is zero, negative, NaN or (but vel) infinite
+	pub fn validate(&self) -> Result<(), Error> {
+		let valid = |val: f64, may_be_infinite: bool| val > 0.0 && (may_be_infinite || val.is_finite());
+		let checks = [("vel", self.vel, true), ("acc", Some(self.acc), false), ("jerk", self.jerk, false), ("snap", self.snap, false)];
+		for (name, limit, may_be_infinite) in checks.iter() {
+			if let Some(value) = limit {
+				if !valid(*value, *may_be_infinite) {
+					return Err(Error::InvalidLimit { name, value: *value });
+				}
+			}
+		}
+		Ok(())
+	}
+
+	// Limits as given to the old Path::new(): (MAX_)ACC, (JERK)
+	pub(crate) fn from_slice(limits: &[f64]) -> Self {
+		assert!(!limits.is_empty() && limits.len() <= 2, "Expected 1 or 2 limits, got {}", limits.len());
+		Self {
+			jerk: limits.get(1).copied(),
+			..Self::acc_limited(limits[0])
+		}
+	}
+}
+
+/*
+ * Peak value in the plan as a fraction of the corresponding limit; 1.0 means
+ * the limit is reached. The acceleration figure is for the actuator, i.e.
+ * excluding any acceleration bias.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[non_exhaustive]
+pub struct LimitMargins {
+	pub vel: f64,
+	pub acc: f64,
+	pub jerk: Option<f64>,
+}
+
+/*
+ * Result of update()/update_to(): the number of segments run through during
+ * the call, whether the move finished during the call (true only once per
+ * move), the time left of the plan, the tag of the current segment and
+ * when the call left the path.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[non_exhaustive]
+pub struct Progress {
+	pub completed_segments: usize,
+	pub finished: bool,
+	pub remaining: f64,
+	pub tag: Option<u32>,	// Tag of the move running after the call, see Path::replan_tagged()
+	pub time: f64,		// Timestamp at the end of the call, see Path::timestamp()
+}
+
+/*
+ * Running totals since the Path was made, see Path::metrics(). Overshoot
+ * segments are the planned segments that reach past the target, away from
+ * where the move started. The planned time excludes segments that never
+ * end.
+ */
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[non_exhaustive]
+pub struct Metrics {
+	pub replans: u64,
+	pub segments: u64,
+	pub overshoot_segments: u64,
+	pub planned_time: f64,
+	pub max_segments_queued: usize,
+	pub cache_hits: u64,	// Plans reused from the cache, see Path::set_cache()
+	pub cache_misses: u64,
+}
+
+impl Metrics {
+	// Fraction of the cached replans that were hits, if any were made
+	pub fn cache_hit_rate(&self) -> Option<f64> {
+		let total = self.cache_hits + self.cache_misses;
+		if total == 0 { None } else { Some(self.cache_hits as f64/total as f64) }
+	}
+}
+
+/*
+ * Gains for Path::feedforward(), from the planned velocity, acceleration
+ * and jerk to the drive's feed-forward inputs (Kvff, Kaff, Kjff). The
+ * defaults pass the planned values through.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FeedforwardGains {
+	pub vel: f64,
+	pub acc: f64,
+	pub jerk: f64,
+}
+
+impl Default for FeedforwardGains {
+	fn default() -> Self {
+		Self {
+			vel: 1.0,
+			acc: 1.0,
+			jerk: 1.0,
+		}
+	}
+}
+
+/*
+ * When the phases of the current plan end, in plan time from its start
+ * like Path::seek(): the speed-up ends and the cruise starts at the first
+ * segment at constant speed (or, without one, at the peak speed), and the
+ * braking ends with the plan. elapsed is the time into the plan now, so
+ * e.g. the time to the braking is cruise_end - elapsed.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[non_exhaustive]
+pub struct PhaseTimes {
+	pub elapsed: f64,
+	pub accel_end: f64,
+	pub cruise_start: f64,
+	pub cruise_end: f64,
+	pub decel_end: f64,
+}
+
+/*
+ * Measured against commanded motion, from Path::report_measured(): the
+ * errors are measured minus commanded. The settle error is that of the
+ * position at the first report after the latest move ended, against its
+ * target.
+ */
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[non_exhaustive]
+pub struct TrackingStats {
+	pub samples: u64,
+	pub max_pos_error: f64,		// Largest absolute position error
+	pub max_vel_error: f64,
+	pub sum_sq_pos_error: f64,
+	pub settle_error: Option<f64>,
+	pub corrections: u64,		// Corrective replans, see Path::set_settle_correction()
+}
+
+impl TrackingStats {
+	pub fn rms_pos_error(&self) -> f64 {
+		if self.samples == 0 { 0.0 } else { (self.sum_sq_pos_error/self.samples as f64).sqrt() }
+	}
+}
+
+/*
+ * Everything besides the distance that a plan depends on: the start state
+ * with the position zeroed, the limits and the settings the planner reads.
+ */
+#[derive(Clone, PartialEq, Debug)]
+struct CacheKey {
+	state: [f64; MAX_STATE_LEN],
+	limits: [f64; MAX_STATE_LEN],
+	path_limits: Limits,
+	overshoot_policy: OvershootPolicy,
+	acc_bias: f64,
+	approach_acc: Option<f64>,
+	max_dec: Option<f64>,
+}
+
+/*
+ * The latest cacheable plan, as the start state (position zeroed) and
+ * duration of each segment. The end position is linear in the duration of
+ * the cruise segment, so the same plan reaches any target further along in
+ * the same direction with only that duration changed.
+ */
+#[derive(Clone, Debug, Default)]
+struct PlanCache {
+	key: Option<CacheKey>,
+	s_diff: f64,
+	phases: Vec<([f64; MAX_STATE_LEN], f64)>,
+	cruise: usize,		// Index in phases of the cruise segment
+	v_cruise: f64,
+}
+
+
+/*
+ * DEGREE is the degree of the position polynomials: 2 for an acc-limited
+ * path, 3 for a jerk-limited path.
+ */
+#[derive(Clone, Debug)]
+pub struct Path<const DEGREE: usize> {
+	limits: Limits,
+	segments: VecDeque<Segment>,	// Preallocated for max_segments, see push_segment()
+	played: Vec<Segment>,	// Segments of the current plan already run through, for seek()
+	max_segments: usize,
+	max_plan_segments: usize,	// See set_max_plan_segments()
+	max_queue_depth: usize,
+	time: f64,		// Time into segments[0]
+	time_carry: f64,	// Rounding error of time, see add_time()
+	odometer: f64,		// Distance run through since construction, see odometer()
+	handed_off: usize,	// Segments from segments[0] on given out by take_next_segment()
+	now: f64,		// Caller's clock, as given to update()/update_to()
+	last_dt: f64,		// Caller's time between the latest two updates
+	time_scale: f64,	// Plan seconds per second of the caller's clock
+	plan_start: f64,	// Value of now when the current plan was made
+	consumed: f64,		// Total duration of segments already popped from the current plan
+	state: [f64; MAX_STATE_LEN],	// Highest-derivative first: state[0] is jerk (or acc). Only the first STATE_LEN are used.
 	target: f64,		// Position target. Used to zero inaccuracies at end of move.
+	v_limit: f64,		// Velocity limit of the latest replan
+	overshoot_policy: OvershootPolicy,
+	acc_bias: f64,		// Constant external acceleration, e.g. gravity
+	approach_acc: Option<f64>,	// Max acceleration for the final braking phase
+	max_dec: Option<f64>,		// Braking limit, if different from the acceleration limit
+	min_replan_interval: f64,
+	command_latency: f64,	// Time from replan() until the drive acts on the new plan
+	last_replan: Option<f64>,	// Value of now at the latest replan
+	epoch: f64,		// Timestamp of now = 0.0, see set_epoch()
+	pending_replan: Option<(f64, f64)>,	// (s_target, v_limit) waiting for min_replan_interval
+	residual: f64,		// Accumulated state jumps between consecutive segments
+	residual_bound: Option<f64>,
+	end_of_move_policy: EndOfMovePolicy,
+	queue: VecDeque<(f64, f64, Option<u32>)>,	// (s_target, v_limit, tag) of moves to run after the current one
+	move_tag: Option<u32>,	// Tag for the segments of the next plan(), see replan_tagged()
+	queue_policy: QueuePolicy,
+	strictness: Strictness,
+	tie_break: TieBreak,
+	last_direction: f64,	// Direction of the latest move that went anywhere, see TieBreak
+	profile: Profile,
+	min_jerk: Option<f64>,	// Duration of the next plan, if it is a replan_min_jerk()
+	travel_limits: Option<(f64, f64)>,	// (min, max) position for jog()
+	follow_hysteresis: f64,
+	splice_snap: Option<f64>,	// Rate of change of jerk at the start of a jerk-limited plan
+	soft_landing: Option<(f64, f64)>,	// (window, scale), see set_soft_landing()
+	velocity_zones: Vec<(Range<f64>, f64)>,	// (positions, v_max), see set_velocity_zones()
+	hold_correction: f64,	// Allowed drift from target while idle, see enable_hold_correction()
+	tracking: TrackingStats,
+	settle_target: Option<f64>,	// Where the measured position should settle, until checked
+	settle_correction: Option<f64>,	// Settle error threshold, see set_settle_correction()
+	error: Option<Error>,
+	fault: Option<FaultKind>,
+	stopping: bool,		// The plan is from stop()
+	limit_free: bool,	// The plan is from replan_min_jerk(), which ignores the limits
+	trace: Option<PlanTrace>,	// Decisions of the latest plan, if tracing
+	cache: Option<PlanCache>,	// See set_cache()
+	normalize: bool,	// Plan with the acceleration limit as the unit, see set_normalization()
+	metrics: Metrics,
+	history: Option<VecDeque<[f64; 4]>>,	// Ring buffer of (timestamp, pos, vel, acc), see enable_history()
+	output_resolution: Option<f64>,
+	output_steps: i64,	// Quantized output position, in steps of output_resolution
+	feedforward_gains: FeedforwardGains,
+	#[cfg(feature = "async")]
+	notifier: Notifier,
+}
+
+impl Path<2> {
+	pub fn acc_limited(max_acc: f64) -> Self {
+		Self::with_limits(Limits::acc_limited(max_acc))
+	}
+
+	/*
+	 * The old Path::new(), with the limits excluding the velocity limit
+	 * (specific to each move): (MAX_)ACC, (JERK). It chose the degree from
+	 * their number, so this gives the DynPath that still does. Only here,
+	 * on one degree, so that Path::new() resolves without naming it.
+	 */
+	#[deprecated(note = "use Path::with_limits(), or DynPath::with_limits() for the degree chosen at runtime")]
+	#[allow(clippy::new_ret_no_self)]
+	pub fn new(limits: Vec<f64>) -> DynPath {
+		DynPath::with_limits(Limits::from_slice(&limits))
+	}
 }
 
-impl Path {
+impl Path<3> {
+	pub fn jerk_limited(max_acc: f64, jerk: f64) -> Self {
+		Self::with_limits(Limits::jerk_limited(max_acc, jerk))
+	}
+
+	/*
+	 * A replan makes the jerk jump from that of the old plan to that of the
+	 * new one, which can excite resonances. With a splice snap the jerk
+	 * instead ramps over at that rate, in a transitional segment, and the
+	 * new plan starts from the end of it. The transition follows neither
+	 * plan, so keep it short: the snap well above the jerk limit times the
+	 * shortest phase you care about.
+	 */
+	pub fn set_splice_snap(&mut self, snap: Option<f64>) {
+		if let Some(snap) = snap {
+			assert!(snap > 0.0);
+		}
+		self.splice_snap = snap;
+	}
+
 	/*
-	 * Parameter limits are from highest order to lowest, excluding velocity limit
-	 * (since velocity target is specific to each move): (MAX_)ACC, (JERK)
+	 * Ramp the acceleration to a_target at the jerk limit and then hold it,
+	 * for when acceleration (force, torque) is the controlled quantity, e.g.
+	 * pressing or tensioning. Velocity and position follow freely and the
+	 * path stays active until the next replan() or stop().
 	 */
-	pub fn new(mut limits: Vec<f64>) -> Self {
-		// Assure get_state() can return pos, vel and acc
-		assert!(limits.len() >= 1);
-		// We can only handle 2nd- and 3rd-degree paths
-		assert!(limits.len() <= 2);
+	pub fn ramp_acceleration_to(&mut self, a_target: f64) {
+		log!("Path::ramp_acceleration_to(), state {:?}, a_target {}", self.state(), a_target);
+		assert!((a_target - self.acc_bias).abs() <= self.limits.acc);
+		if self.fault.is_some() {
+			return;
+		}
+
+		self.pending_replan = None;
+		self.restart_clock();
+		self.segments.clear();
+
+		let mut state = self.get_end_state();
+		let a_diff = a_target - state[1];
+		let t0 = a_diff.abs()/self.jerk_limit();
+		if t0 > TINY_DURATION {
+			state[0] = a_diff.signum()*self.jerk_limit();
+			self.push_segment(Segment::new(&state[..Self::STATE_LEN], t0, Self::STATE_LEN));
+			state = self.get_end_state();
+		}
+
+		state[0] = 0.0;
+		state[1] = a_target;
+		self.push_segment(Segment::new(&state[..Self::STATE_LEN], f64::INFINITY, Self::STATE_LEN));
+	}
+}
+
+impl<const DEGREE: usize> Path<DEGREE> {
+	// We can only handle 2nd- and 3rd-degree paths
+	const VALID_DEGREE: () = assert!(DEGREE == 2 || DEGREE == 3);
+	const STATE_LEN: usize = DEGREE + 1;
+
+	pub fn with_limits(limits: Limits) -> Self {
+		#[allow(clippy::let_unit_value)]
+		let () = Self::VALID_DEGREE;
+		Self::check_limits(&limits);
 
-		let degree = limits.len() + 1;
-		limits.reverse();
 		Self {
-			limits: limits,
-			segments: VecDeque::new(),
+			limits,
+			segments: VecDeque::with_capacity(DEFAULT_MAX_SEGMENTS),
+			played: Vec::with_capacity(DEFAULT_MAX_SEGMENTS),
+			max_segments: DEFAULT_MAX_SEGMENTS,
+			max_plan_segments: DEFAULT_MAX_PLAN_SEGMENTS,
+			max_queue_depth: DEFAULT_MAX_QUEUE_DEPTH,
 			time: 0.0,
-			state: vec![0.0; degree + 1],
+			time_carry: 0.0,
+			odometer: 0.0,
+			handed_off: 0,
+			now: 0.0,
+			last_dt: 0.0,
+			time_scale: 1.0,
+			plan_start: 0.0,
+			consumed: 0.0,
+			state: [0.0; MAX_STATE_LEN],
 			target: 0.0,
+			v_limit: 0.0,
+			overshoot_policy: OvershootPolicy::Allow,
+			acc_bias: 0.0,
+			approach_acc: None,
+			max_dec: None,
+			min_replan_interval: 0.0,
+			command_latency: 0.0,
+			last_replan: None,
+			epoch: 0.0,
+			pending_replan: None,
+			residual: 0.0,
+			residual_bound: None,
+			end_of_move_policy: EndOfMovePolicy::SnapToTarget,
+			queue: VecDeque::new(),
+			move_tag: None,
+			queue_policy: QueuePolicy::StopBetween,
+			strictness: Strictness::Strict,
+			tie_break: TieBreak::PreferPositive,
+			last_direction: 1.0,
+			profile: Profile::TimeOptimal,
+			min_jerk: None,
+			travel_limits: None,
+			follow_hysteresis: 0.0,
+			splice_snap: None,
+			soft_landing: None,
+			velocity_zones: Vec::new(),
+			hold_correction: f64::INFINITY,
+			tracking: TrackingStats::default(),
+			settle_target: None,
+			settle_correction: None,
+			error: None,
+			fault: None,
+			stopping: false,
+			limit_free: false,
+			trace: None,
+			cache: None,
+			normalize: false,
+			metrics: Metrics::default(),
+			history: None,
+			output_resolution: None,
+			output_steps: 0,
+			feedforward_gains: FeedforwardGains::default(),
+			#[cfg(feature = "async")]
+			notifier: Notifier::new(),
 		}
 	}
 
-	pub fn replan(&mut self, s_target: f64, v_limit: f64) {
-		let mut limits = self.limits.clone();
-		limits.push(v_limit);
+	// At rest at initial_pos, which is also the target until the first move
+	pub fn new_at(limits: Limits, initial_pos: f64) -> Self {
+		assert!(initial_pos.is_finite());
+		let mut path = Self::with_limits(limits);
+		path.state[Self::STATE_LEN - 1] = initial_pos;
+		path.target = initial_pos;
+		path
+	}
+
+	fn check_limits(limits: &Limits) {
+		assert_eq!(limits.jerk.is_some(), DEGREE == 3, "Jerk limit must be set exactly for a jerk-limited path");
+		assert!(limits.snap.is_none(), "Snap-limited paths are not supported");
+		if let Err(err) = limits.validate() {
+			panic!("{}", err);
+		}
+	}
+
+	// with_limits(), but limits failing Limits::validate() give its error instead of a panic
+	pub fn try_with_limits(limits: Limits) -> Result<Self, Error> {
+		limits.validate()?;
+		Ok(Self::with_limits(limits))
+	}
+
+	/*
+	 * Change the limits on the fly, e.g. for a new payload. A move in
+	 * progress is replanned to the same target under the new limits, at
+	 * the velocity limit of the latest replan() (capped by the new
+	 * one); a plan that never ends (jog() etc) runs on until the next
+	 * replan(). The replanned move keeps its tag. The approach acceleration
+	 * is capped by the new acceleration limit. A jerk-limited path can't be
+	 * replanned from an acceleration above the new limit, so that gives
+	 * Error::StateBeyondLimits, as does an acceleration bias not below it;
+	 * either, like limits failing Limits::validate(), leaves the limits as
+	 * they were.
+	 */
+	pub fn set_limits(&mut self, limits: Limits) -> Result<(), Error> {
+		limits.validate()?;
+		Self::check_limits(&limits);
+		if self.acc_bias.abs() >= limits.acc {
+			return Err(Error::StateBeyondLimits { value: self.acc_bias, limit: limits.acc });
+		}
+		if DEGREE == 3 {
+			let acc = self.state[1] - self.acc_bias;
+			if acc.abs() > limits.acc + CLOSE_ENOUGH {
+				return Err(Error::StateBeyondLimits { value: acc, limit: limits.acc });
+			}
+		}
+
+		self.limits = limits;
+		self.approach_acc = self.approach_acc.map(|acc| acc.min(limits.acc));
+		if self.segments.back().is_some_and(|seg| seg.get_duration().is_finite()) {
+			self.move_tag = self.current_tag();
+			self.plan_move(self.target, self.v_limit);
+		}
+		Ok(())
+	}
+
+	pub fn with_profile(limits: Limits, profile: Profile) -> Self {
+		let mut path = Self::with_limits(limits);
+		path.profile = profile;
+		path
+	}
+
+	pub fn limits(&self) -> Limits {
+		self.limits
+	}
+
+	fn jerk_limit(&self) -> f64 {
+		self.limits.jerk.expect("Not a jerk-limited path")
+	}
+
+	/*
+	 * Limits in the order calc_path_2() and calc_path_3() work with:
+	 * highest derivative first, ending with v_limit
+	 */
+	fn ordered_limits(&self, v_limit: f64) -> [f64; MAX_STATE_LEN] {
+		let mut limits = [0.0; MAX_STATE_LEN];
+		if DEGREE == 3 {
+			limits[0] = self.jerk_limit();
+		}
+		limits[DEGREE - 2] = self.limits.acc;
+		limits[DEGREE - 1] = v_limit;
+		limits
+	}
+
+	pub fn set_overshoot_policy(&mut self, policy: OvershootPolicy) {
+		// A jerk-limited stop and the move back don't fit the default pool
+		if DEGREE == 3 && policy == OvershootPolicy::Forbid && self.max_segments < 2*DEFAULT_MAX_SEGMENTS {
+			self.set_max_segments(2*DEFAULT_MAX_SEGMENTS);
+		}
+		self.overshoot_policy = policy;
+	}
+
+	pub fn set_end_of_move_policy(&mut self, policy: EndOfMovePolicy) {
+		self.end_of_move_policy = policy;
+	}
+
+	pub fn set_strictness(&mut self, strictness: Strictness) {
+		self.strictness = strictness;
+	}
+
+	pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+		self.tie_break = tie_break;
+	}
+
+	/*
+	 * Run through the plan k times faster than the caller's clock passes in
+	 * update()/update_to(), e.g. 0.1 for slow-motion playback in a
+	 * simulator. The plan itself and its limits are unchanged.
+	 */
+	pub fn set_time_scale(&mut self, k: f64) {
+		assert!(k > 0.0 && k.is_finite());
+		// Rebase the plan start so that update_to() continues from here
+		self.plan_start = self.now - (self.consumed + self.time)/k;
+		self.time_scale = k;
+	}
+
+	pub fn set_feedforward_gains(&mut self, gains: FeedforwardGains) {
+		assert!(gains.vel.is_finite() && gains.acc.is_finite() && gains.jerk.is_finite());
+		self.feedforward_gains = gains;
+	}
+
+	// (min, max) positions that jog() stops at, if any
+	pub fn set_travel_limits(&mut self, limits: Option<(f64, f64)>) {
+		if let Some((min, max)) = limits {
+			assert!(min <= max);
+		}
+		self.travel_limits = limits;
+	}
+
+	// Band around the current target within which follow_target() ignores new targets
+	pub fn set_follow_hysteresis(&mut self, band: f64) {
+		assert!(band >= 0.0);
+		self.follow_hysteresis = band;
+	}
+
+	/*
+	 * replan() for a streamed, noisy target, e.g. from a vision system:
+	 * only replans if pos is further than the hysteresis band from the
+	 * current target, or the velocity limit changes. Returns true if it
+	 * replanned.
+	 */
+	pub fn follow_target(&mut self, pos: f64, v_limit: f64) -> bool {
+		let capped = self.limits.vel.map_or(v_limit, |vel| v_limit.min(vel));
+		if (pos - self.target).abs() <= self.follow_hysteresis && capped == self.v_limit {
+			return false;
+		}
+		self.replan(pos, v_limit);
+		true
+	}
+
+	/*
+	 * Overwrite the state with one from outside the path, e.g. the measured
+	 * position of the axis. Any plan in progress is dropped; the target is
+	 * kept, so a replan() or the hold correction brings the axis back to it.
+	 */
+	pub fn set_state(&mut self, state: (f64, f64, f64)) {
+		log!("Path::set_state(), state {:?}, new state {:?}", self.state(), state);
+		self.pending_replan = None;
+		self.restart_clock();
+		self.segments.clear();
+		self.state = [0.0; MAX_STATE_LEN];
+		let pos_index = Self::STATE_LEN - 1;
+		self.state[pos_index] = state.0;
+		self.state[pos_index - 1] = state.1;
+		self.state[pos_index - 2] = state.2;
+	}
+
+	/*
+	 * Watch for drift while idle: if update() or update_to() finds the path
+	 * without a plan and the position or velocity (e.g. from set_state())
+	 * further than tolerance from rest at the target, it latches an
+	 * Error::HoldDrift (see take_error()) and, if there has been a move to
+	 * take the velocity limit from, replans a correction move back to the
+	 * target. f64::INFINITY turns it off.
+	 */
+	pub fn enable_hold_correction(&mut self, tolerance: f64) {
+		assert!(tolerance >= 0.0);
+		self.hold_correction = tolerance;
+	}
+
+	fn check_hold(&mut self) {
+		if !self.segments.is_empty() || self.pending_replan.is_some() || self.fault.is_some() {
+			return;
+		}
+		let (pos, vel, _) = self.get_state();
+		let deviation = (pos - self.target).abs().max(vel.abs());
+		let tolerance = self.hold_correction;
+		if deviation <= tolerance {
+			return;
+		}
+		self.error = Some(Error::HoldDrift { deviation, tolerance });
+		if self.v_limit > 0.0 {
+			self.plan_move(self.target, self.v_limit);
+		}
+	}
+
+	/*
+	 * Feedback from the axis, for tracking statistics (see tracking_stats())
+	 * only: the commanded plan doesn't change, except for the correction of
+	 * set_settle_correction(). Compared with the commanded state as of the
+	 * latest update(), so report the measurement taken at that time.
+	 */
+	pub fn report_measured(&mut self, pos: f64, vel: f64) {
+		let (cmd_pos, cmd_vel, _) = self.get_state();
+		let stats = &mut self.tracking;
+		let pos_error = pos - cmd_pos;
+		stats.samples += 1;
+		stats.max_pos_error = stats.max_pos_error.max(pos_error.abs());
+		stats.max_vel_error = stats.max_vel_error.max((vel - cmd_vel).abs());
+		stats.sum_sq_pos_error += pos_error*pos_error;
+
+		if self.is_active() || self.fault.is_some() {
+			return;
+		}
+		if let Some(target) = self.settle_target.take() {
+			let settle_error = pos - target;
+			self.tracking.settle_error = Some(settle_error);
+			if self.settle_correction.is_some_and(|threshold| settle_error.abs() > threshold) && self.v_limit > 0.0 {
+				self.plan_move(self.target - settle_error, self.v_limit);
+				self.settle_target = Some(target);
+				self.tracking.corrections += 1;
+			}
+		}
+	}
+
+	pub fn tracking_stats(&self) -> TrackingStats {
+		self.tracking
+	}
+
+	/*
+	 * When a move has ended and the first report_measured() after it finds
+	 * the measured position further than threshold from the target, replan
+	 * a correction commanding the target offset by that error, so that an
+	 * axis lagging or leading by a steady amount settles at the target.
+	 * Repeats after each correction until within threshold. None turns it
+	 * off.
+	 */
+	pub fn set_settle_correction(&mut self, threshold: Option<f64>) {
+		if let Some(threshold) = threshold {
+			assert!(threshold > 0.0);
+		}
+		self.settle_correction = threshold;
+	}
+
+	pub fn set_queue_policy(&mut self, policy: QueuePolicy) {
+		self.queue_policy = policy;
+	}
+
+	/*
+	 * Add a move to run after the current one (and any moves queued before
+	 * it), with no further calls needed: update() and update_to() start
+	 * each queued move with replan() when its turn comes, see QueuePolicy.
+	 * If the path is idle, the move starts right away. The queue allocates
+	 * as it grows, up to set_max_queue_depth(). stop() clears the queue;
+	 * replan() doesn't.
+	 */
+	pub fn queue_move(&mut self, s_target: f64, v_limit: f64) {
+		self.push_queue(s_target, v_limit, None);
+	}
+
+	// queue_move() with a tag for its segments, see replan_tagged()
+	pub fn queue_move_tagged(&mut self, s_target: f64, v_limit: f64, tag: u32) {
+		self.push_queue(s_target, v_limit, Some(tag));
+	}
+
+	fn push_queue(&mut self, s_target: f64, v_limit: f64, tag: Option<u32>) {
+		if self.queue.len() >= self.max_queue_depth {
+			self.error = Some(Error::QueueFull { depth: self.max_queue_depth });
+			return;
+		}
+		self.queue.push_back((s_target, v_limit, tag));
+		self.run_queue();
+	}
+
+	/*
+	 * Moves waiting in the queue beyond depth are refused, latching an
+	 * Error::QueueFull (see take_error()). 1024 by default.
+	 */
+	pub fn set_max_queue_depth(&mut self, depth: usize) {
+		self.max_queue_depth = depth;
+	}
+
+	pub fn clear_queue(&mut self) {
+		self.queue.clear();
+	}
+
+	pub fn queued_moves(&self) -> usize {
+		self.queue.len()
+	}
+
+	fn run_queue(&mut self) {
+		if self.fault.is_some() {
+			return;
+		}
+		let (s_target, v_limit, tag) = match self.queue.front() {
+			Some(&next) => next,
+			None => return,
+		};
+		let start = match self.queue_policy {
+			QueuePolicy::StopBetween => self.segments.is_empty(),
+			QueuePolicy::Blend => self.segments.is_empty() || self.braking(),
+		};
+		if start {
+			self.queue.pop_front();
+			self.move_tag = tag;
+			self.plan_move(s_target, v_limit);
+		}
+	}
+
+	// True if the speed only goes down from here to the end of the plan
+	fn braking(&self) -> bool {
+		let vel_index = Self::STATE_LEN - 2;
+		self.segments.iter().all(|seg| seg.end_state()[vel_index].abs() < seg.state_at(0.0)[vel_index].abs())
+	}
+
+	// Return and clear the latest error, if any
+	pub fn take_error(&mut self) -> Option<Error> {
+		self.error.take()
+	}
+
+	pub fn status(&self) -> PathStatus {
+		if let Some(kind) = self.fault {
+			PathStatus::Faulted(kind)
+		} else if self.segments.is_empty() {
+			PathStatus::Idle
+		} else if self.stopping {
+			PathStatus::Stopping
+		} else {
+			PathStatus::Moving
+		}
+	}
+
+	// Latch a fault and stop
+	fn raise_fault(&mut self, kind: FaultKind) {
+		log!("Path::raise_fault(), state {:?}, {:?}", self.state(), kind);
+		self.stop();
+		self.fault = Some(kind);
+	}
+
+	// A call panicked midway: drop the plan it may have left half made, and stop from the current state
+	pub(crate) fn recover_from_panic(&mut self) {
+		self.raise_fault(FaultKind::Panicked);
+	}
+
+	// Accept new moves again after a fault. The stop it caused keeps running.
+	pub fn clear_fault(&mut self) {
+		self.fault = None;
+	}
+
+	/*
+	 * Set a constant external acceleration acting on the axis, e.g. -9.81
+	 * for gravity on a vertical axis pointing up. The acceleration limit
+	 * then applies to what the actuator has to supply, so the planned
+	 * acceleration ranges from bias - MAX_ACC to bias + MAX_ACC.
+	 */
+	pub fn set_acc_bias(&mut self, bias: f64) {
+		assert!(bias.abs() < self.limits.acc);
+		self.acc_bias = bias;
+	}
+
+	/*
+	 * Safe approach mode: brake towards the target using at most acc
+	 * instead of MAX_ACC. This keeps the velocity at or below
+	 * sqrt(2*acc*d) all the way in, where d is the remaining distance, so
+	 * that a target that suddenly moves closer (sensor-guided approach)
+	 * can still be reached by braking harder. None turns it off.
+	 */
+	pub fn set_approach_acc(&mut self, acc: Option<f64>) {
+		if let Some(acc) = acc {
+			assert!(acc > 0.0);
+			assert!(acc <= self.limits.acc);
+		}
+		self.approach_acc = acc;
+	}
+
+	/*
+	 * Separate limit for braking, for axes that can brake harder than they
+	 * can speed up, or the other way around. MAX_ACC then only applies when
+	 * speeding up. None uses MAX_ACC for both.
+	 */
+	pub fn set_max_dec(&mut self, dec: Option<f64>) {
+		if let Some(dec) = dec {
+			assert!(dec > self.acc_bias.abs());
+		}
+		self.max_dec = dec;
+	}
+
+	/*
+	 * Coalesce replans that come in faster than this, e.g. from a joystick:
+	 * a replan() within interval seconds of the previous one only updates
+	 * the target, and the actual replan is made by the first update() or
+	 * update_to() after the interval has passed. stop() is never delayed.
+	 * 0.0 (the default) replans immediately.
+	 */
+	pub fn set_min_replan_interval(&mut self, interval: f64) {
+		assert!(interval >= 0.0);
+		self.min_replan_interval = interval;
+	}
+
+	/*
+	 * Known delay between the planner and the drive, e.g. a fieldbus
+	 * cycle: replan() keeps the next latency seconds of the current plan,
+	 * which the drive will run anyway, and plans the new move from the
+	 * state at the end of them. stop() is not delayed. Since a plan can
+	 * then hold the kept segments as well, this doubles the segment pool
+	 * (see set_max_segments()) if needed.
+	 */
+	pub fn set_command_latency(&mut self, latency: f64) {
+		assert!(latency >= 0.0);
+		if latency > 0.0 && self.max_segments < 2*DEFAULT_MAX_SEGMENTS {
+			self.set_max_segments(2*DEFAULT_MAX_SEGMENTS);
+		}
+		self.command_latency = latency;
+	}
+
+	/*
+	 * Each segment starts from the planned end state of the one before,
+	 * rounded differently than where that one actually ends. The rounding
+	 * errors are summed up at each segment change to give the residual;
+	 * with a bound set, passing it re-chains the rest of the plan from the
+	 * actual state and clears the residual.
+	 */
+	pub fn set_residual_bound(&mut self, bound: Option<f64>) {
+		if let Some(bound) = bound {
+			assert!(bound >= 0.0);
+		}
+		self.residual_bound = bound;
+	}
+
+	pub fn numerical_residual(&self) -> f64 {
+		self.residual
+	}
+
+	/*
+	 * Soft landing for fragile loads: the last window of each replan() move
+	 * is braked with the acceleration (and jerk) limits scaled by scale. The
+	 * move is planned as a time-optimal approach to the start of the window,
+	 * arriving at the speed that the gentle braking stops from within it,
+	 * followed by the landing itself. A move that starts inside the window
+	 * is planned with the scaled limits throughout. Both parts are planned
+	 * like plan_between(), without acceleration bias or braking limits.
+	 * Moves that would have to reverse, and stop(), keep the full limits.
+	 * Raises max_segments to 16, see set_max_segments(). None turns it off.
+	 */
+	pub fn set_soft_landing(&mut self, landing: Option<(f64, f64)>) {
+		if let Some((window, scale)) = landing {
+			assert!(window > 0.0 && scale > 0.0 && scale <= 1.0);
+			if self.max_segments < 2*DEFAULT_MAX_SEGMENTS {
+				self.set_max_segments(2*DEFAULT_MAX_SEGMENTS);
+			}
+		}
+		self.soft_landing = landing;
+	}
+
+	/*
+	 * Position-dependent velocity limits, e.g. a slow zone near an operator
+	 * or an end stop: while inside range, the speed stays at or below v_max
+	 * (or the v_limit of the replan, if lower). Each replan() move is split
+	 * at the zone boundaries it crosses, with the crossing speeds the
+	 * highest from which the limits further along can still be met, and
+	 * the parts planned like plan_between(), without acceleration bias or
+	 * braking limits. A move that can't slow down enough before a zone
+	 * enters it braking as hard as it can. Moves that would have to reverse,
+	 * and stop(), ignore the zones. Raises max_segments to fit the split
+	 * plans, see set_max_segments(). An empty slice turns the zones off.
+	 */
+	pub fn set_velocity_zones(&mut self, zones: &[(Range<f64>, f64)]) {
+		for (range, v_max) in zones {
+			assert!(range.start < range.end && *v_max > 0.0);
+		}
+		let max_segments = ((2*zones.len() + 1)*(2*DEGREE + 2)).min(self.max_plan_segments);
+		if self.max_segments < max_segments {
+			self.set_max_segments(max_segments);
+		}
+		self.velocity_zones = zones.to_vec();
+	}
+
+	/*
+	 * Upper bound on the segments of one plan made of parts, such as with
+	 * velocity zones or a soft landing: a plan that would need more isn't
+	 * made (the move is planned without the parts) and an
+	 * Error::PlanTooLong is latched, see take_error(). Also caps how far
+	 * set_velocity_zones() raises max_segments. 256 by default.
+	 */
+	pub fn set_max_plan_segments(&mut self, limit: usize) {
+		self.max_plan_segments = limit;
+	}
+
+	// Check that count more segments fit the plan, latching an error if not
+	fn fits_plan(&mut self, count: usize) -> bool {
+		let limit = self.max_plan_segments.min(self.max_segments - self.segments.len());
+		if count > limit {
+			self.error = Some(Error::PlanTooLong { segments: count, limit });
+			return false;
+		}
+		true
+	}
+
+	/*
+	 * Segments are kept in a pool allocated up front, so that replan(),
+	 * stop() and update() don't allocate. The default of 8 fits any single
+	 * plan; a plan that doesn't fit panics. Forbidding overshoot on a
+	 * jerk-limited path doubles it, for a full stop and the move back (see
+	 * set_overshoot_policy()).
+	 */
+	pub fn set_max_segments(&mut self, max_segments: usize) {
+		assert!(max_segments >= self.segments.len());
+		self.segments.reserve(max_segments - self.segments.len());
+		self.played.reserve(max_segments.saturating_sub(self.played.len()));
+		self.max_segments = max_segments;
+	}
+
+	/*
+	 * Record the decisions made by each replan() and stop(), for debugging
+	 * the planner, see plan_trace(). Tracing allocates.
+	 */
+	pub fn set_trace(&mut self, enabled: bool) {
+		self.trace = if enabled { Some(PlanTrace::new()) } else { None };
+	}
+
+	/*
+	 * Plan in units of the acceleration limit, so that the planner's
+	 * tolerances, absolute values around 1e-12, fit positions and
+	 * velocities given in very small units such as nanometers or
+	 * microdegrees. The path state and the plan are scaled back to the
+	 * caller's units; only plan_trace() shows the scaled values. stop()
+	 * and the other plans that don't go through replan() are unaffected.
+	 */
+	pub fn set_normalization(&mut self, enabled: bool) {
+		self.normalize = enabled;
+	}
+
+	/*
+	 * Reuse the latest plan when a replan starts from the same state (bar
+	 * the position) with the same limits and settings, e.g. each target of
+	 * a stream while cruising, and the target lies further along the cruise
+	 * of that plan. Only the cruise duration is recomputed. Applies to
+	 * time-optimal plans without soft landing or splice snap; a reused plan
+	 * leaves no trace steps. Hits and misses are counted in metrics().
+	 */
+	pub fn set_cache(&mut self, enabled: bool) {
+		self.cache = if enabled { Some(PlanCache::default()) } else { None };
+	}
+
+	/*
+	 * Keep the latest n states emitted by update(), update_to() and
+	 * pop_completed_segments(), with the caller's time, to compare against
+	 * measured positions when tuning. Allocates once, here; 0 turns the
+	 * history off.
+	 */
+	pub fn enable_history(&mut self, n: usize) {
+		self.history = if n > 0 { Some(VecDeque::with_capacity(n)) } else { None };
+	}
+
+	/*
+	 * Round the emitted position to whole steps of an encoder or stepper
+	 * resolution, see get_output_state(). Each update() moves the output
+	 * by the whole steps the carry (exact minus output position) has grown
+	 * to, rounded, so the output never lags more than half a step and
+	 * steps downstream don't alias. The state used for planning stays
+	 * exact. None turns it off.
+	 */
+	pub fn set_output_resolution(&mut self, step: Option<f64>) {
+		if let Some(step) = step {
+			assert!(step > 0.0);
+			self.output_steps = (self.get_state().0/step).round() as i64;
+		}
+		self.output_resolution = step;
+	}
+
+	/*
+	 * The state as emitted by update(), update_to() and
+	 * pop_completed_segments(): get_state() with the position quantized if
+	 * an output resolution is set. The history and watch_state() get this
+	 * one too.
+	 */
+	pub fn get_output_state(&self) -> (f64, f64, f64) {
+		let (pos, vel, acc) = self.get_state();
+		match self.output_resolution {
+			Some(step) => (self.output_steps as f64*step, vel, acc),
+			None => (pos, vel, acc),
+		}
+	}
+
+	// Output steps at the latest update, see set_output_resolution()
+	pub fn output_steps(&self) -> i64 {
+		self.output_steps
+	}
+
+	/*
+	 * Put the path on a timeline shared with other axes or devices: its
+	 * time zero (when it was made, or update_to()'s clock zero) is at t0.
+	 * The timestamps in history(), Progress and plan_report() are then
+	 * t0 plus the path's own time, so logs from several paths line up.
+	 */
+	pub fn set_epoch(&mut self, t0: f64) {
+		assert!(t0.is_finite());
+		self.epoch = t0;
+	}
+
+	// The current time on the timeline set by set_epoch()
+	pub fn timestamp(&self) -> f64 {
+		self.epoch + self.now
+	}
+
+	// The recorded (time, (pos, vel, acc)), oldest first
+	pub fn history(&self) -> impl Iterator<Item = (f64, (f64, f64, f64))> + '_ {
+		self.history.iter().flatten().map(|frame| (frame[0], (frame[1], frame[2], frame[3])))
+	}
+
+	// The decisions behind the current plan, if tracing
+	pub fn plan_trace(&self) -> Option<&PlanTrace> {
+		self.trace.as_ref()
+	}
+
+	fn trace(&mut self, branch: Branch, quantities: &[(&'static str, f64)]) {
+		if let Some(trace) = &mut self.trace {
+			trace.push(branch, quantities);
+		}
+	}
+
+	fn clear_trace(&mut self) {
+		if let Some(trace) = &mut self.trace {
+			trace.steps.clear();
+		}
+	}
+
+	// Braking limit to use given acceleration limit a_max
+	fn dec_limit(&self, a_max: f64) -> f64 {
+		self.max_dec.unwrap_or(a_max)
+	}
+
+	/*
+	 * Same as limits, but with the acceleration limit replaced by the
+	 * braking limit, lowered for approach
+	 */
+	fn brake_limits(&self, limits: &[f64]) -> [f64; MAX_STATE_LEN] {
+		let mut brake_limits = [0.0; MAX_STATE_LEN];
+		brake_limits[..DEGREE].copy_from_slice(limits);
+		let acc_index = DEGREE - 2;
+		brake_limits[acc_index] = self.dec_limit(limits[acc_index]);
+		if let Some(acc) = self.approach_acc {
+			brake_limits[acc_index] = brake_limits[acc_index].min(acc);
+		}
+		brake_limits
+	}
+
+	// Magnitude of the acceleration available in the direction of sign
+	fn acc_limit(&self, a_max: f64, sign: f64) -> f64 {
+		a_max + sign*self.acc_bias
+	}
+
+	/*
+	 * Plan a move to s_target, ending at rest. A v_limit of 0.0 means hold:
+	 * no motion towards s_target is allowed, so this is the same as stop().
+	 */
+	pub fn replan(&mut self, s_target: f64, v_limit: f64) {
+		self.move_tag = None;
+		self.plan_move(s_target, v_limit);
+	}
+
+	/*
+	 * replan() with a tag carried by the segments of the move, e.g. the
+	 * G-code line or job it came from, to map the running motion back to
+	 * it: see Segment::tag(), Progress::tag and current_tag(). The segments
+	 * of stop() and of untagged moves have no tag.
+	 */
+	pub fn replan_tagged(&mut self, s_target: f64, v_limit: f64, tag: u32) {
+		self.move_tag = Some(tag);
+		self.plan_move(s_target, v_limit);
+	}
+
+	/*
+	 * Slow the move in progress down to cruise at v_new on to the same
+	 * target, keeping its tag: the same as replanning to the target of
+	 * the latest replan() at v_new. A v_new of 0.0 stops, like replan().
+	 * Ignored at rest, or if the move isn't faster than v_new.
+	 */
+	pub fn slow_to(&mut self, v_new: f64) {
+		if !self.is_active() || v_new >= self.v_limit {
+			return;
+		}
+		self.move_tag = self.current_tag();
+		self.plan_move(self.target, v_new);
+	}
+
+	/*
+	 * The classic minimum-jerk move for this move only: the quintic from
+	 * the current state to rest at s_target taking exactly duration, with
+	 * the limits ignored. From rest it is the 3-4-5 polynomial of
+	 * Profile::Smoothstep, peaking at 1.875 times the mean velocity and
+	 * 5.77*h/T² acceleration for a distance h.
+	 */
+	pub fn replan_min_jerk(&mut self, s_target: f64, duration: f64) {
+		if self.fault.is_some() {
+			return;
+		}
+		if !s_target.is_finite() || !duration.is_finite() || duration <= 0.0 {
+			self.raise_fault(FaultKind::InvalidInput);
+			return;
+		}
+		log!("Path::replan_min_jerk(), state {:?}, s_target {}, duration {}", self.state(), s_target, duration);
+		self.move_tag = None;
+		self.min_jerk = Some(duration);
+		let limits = self.ordered_limits(self.v_limit);
+		self.plan(&limits[..DEGREE], s_target, self.command_latency);
+		self.settle_target = Some(s_target);
+	}
+
+	// Tag of the segment running now
+	pub fn current_tag(&self) -> Option<u32> {
+		self.segments.front().and_then(|seg| seg.tag())
+	}
+
+	// replan() for the move tagged move_tag
+	fn plan_move(&mut self, s_target: f64, v_limit: f64) {
+		if self.fault.is_some() {
+			return;
+		}
+		if !s_target.is_finite() || v_limit.is_nan() || v_limit < 0.0 {
+			self.raise_fault(FaultKind::InvalidInput);
+			return;
+		}
+		let v_limit = self.limits.vel.map_or(v_limit, |vel| v_limit.min(vel));
+		let limits = self.ordered_limits(v_limit);
+		let limits = &limits[..DEGREE];
+
+		log!("Path::replan(), state {:?}, s_target {}, limits {:?}", self.state(), s_target, limits);
+		if v_limit == 0.0 {
+			self.stop();
+			return;
+		}
+
+		if let Some(last_replan) = self.last_replan {
+			if self.now - last_replan < self.min_replan_interval {
+				self.pending_replan = Some((s_target, v_limit));
+				return;
+			}
+		}
+		self.snap_state(v_limit);
+		self.plan(limits, s_target, self.command_latency);
+		self.v_limit = v_limit;
+		self.settle_target = Some(s_target);
+	}
+
+	/*
+	 * replan(), but only moving in the allowed direction. A target that
+	 * can't be reached without going the other way, i.e. one behind where
+	 * the path can stop (overshooting it and coming back), is refused: the
+	 * path stops as soon as it can instead, the closest it gets to the
+	 * target, latches FaultKind::InfeasibleReplan and returns
+	 * Error::ForbiddenDirection.
+	 */
+	pub fn replan_directional(&mut self, s_target: f64, v_limit: f64, direction: Direction) -> Result<(), Error> {
+		let dir = match direction {
+			Direction::OnlyPositive => 1.0,
+			Direction::OnlyNegative => -1.0,
+			Direction::Either => {
+				self.replan(s_target, v_limit);
+				return Ok(());
+			},
+		};
+		let stop = self.stop_from(self.get_state());
+		let s_stop = stop.last().map_or(self.get_state().0, |seg| *seg.get_end_state().last().unwrap());
+		if dir*(s_target - s_stop) < -CLOSE_ENOUGH {
+			self.raise_fault(FaultKind::InfeasibleReplan);
+			return Err(Error::ForbiddenDirection { s_target, reachable: s_stop });
+		}
+		self.replan(s_target, v_limit);
+		Ok(())
+	}
+
+	/*
+	 * replan() to delta from the commanded target rather than from where
+	 * the path is now, e.g. for incremental (G91-style) moves: relative
+	 * moves made during a move stack up, so two moves of 0.01 end 0.02
+	 * past the target they started from. A replan held back by
+	 * min_replan_interval counts as the commanded target. After a plan
+	 * that never ends (jog(), replan_tracking(), ramp_acceleration_to())
+	 * the move is from the current position.
+	 */
+	pub fn move_relative(&mut self, delta: f64, v_limit: f64) {
+		let holds = self.segments.back().is_some_and(|seg| !seg.get_duration().is_finite());
+		let base = match self.pending_replan {
+			Some((s_target, _)) => s_target,
+			None if holds => self.get_state().0,
+			None => self.target,
+		};
+		self.replan(base + delta, v_limit);
+	}
+
+	/*
+	 * Snap a velocity (and acceleration) within CLOSE_ENOUGH of zero or a
+	 * limit to exactly that value, e.g. 0.09999999999999998 at a velocity
+	 * limit of 0.1. Planning from the unsnapped value gives segments of
+	 * near-zero duration that only remove the difference, with 1e-7-type
+	 * accelerations. replan() does this first; the velocity limit here is
+	 * that of the latest replan(). The next update() takes the state from
+	 * the plan again.
+	 */
+	pub fn normalize_state(&mut self) {
+		self.snap_state(self.v_limit);
+	}
+
+	fn snap_state(&mut self, v_limit: f64) {
+		let snap = |val: f64, to: &[f64]| to.iter().copied().find(|to| (val - to).abs() < CLOSE_ENOUGH).unwrap_or(val);
+		let vel_index = Self::STATE_LEN - 2;
+		self.state[vel_index] = snap(self.state[vel_index], &[0.0, v_limit, -v_limit]);
+		if DEGREE == 3 {
+			let (bias, acc, dec) = (self.acc_bias, self.limits.acc, self.dec_limit(self.limits.acc));
+			self.state[1] = snap(self.state[1], &[0.0, bias, bias + acc, bias - acc, bias + dec, bias - dec]);
+		}
+	}
+
+	/*
+	 * Replace the plan with a move from the current state, after the first
+	 * keep seconds of the current plan
+	 */
+	fn plan(&mut self, limits: &[f64], s_target: f64, keep: f64) {
+		if !self.normalize {
+			self.plan_in_units(limits, s_target, keep);
+			return;
+		}
+
+		let unit = self.limits.acc;
+		let mut scaled = [0.0; MAX_STATE_LEN];
+		for (to, limit) in scaled.iter_mut().zip(limits) {
+			*to = limit/unit;
+		}
+		let saved = (self.limits, self.acc_bias, self.approach_acc, self.max_dec, self.splice_snap, self.soft_landing);
+		let zones = self.velocity_zones.clone();
+
+		self.rescale(1.0/unit);
+		self.snap_state(scaled[DEGREE - 1]);
+		self.plan_in_units(&scaled[..DEGREE], s_target/unit, keep);
+		self.rescale(unit);
+
+		(self.limits, self.acc_bias, self.approach_acc, self.max_dec, self.splice_snap, self.soft_landing) = saved;
+		self.velocity_zones = zones;
+		if self.fault.is_none() {
+			self.target = s_target;
+		}
+	}
+
+	/*
+	 * Move the path to new position coordinates: the state, the remaining
+	 * plan, the target and the targets of queued and pending moves, and
+	 * the position-dependent settings (travel limits, velocity zones,
+	 * acceleration bias, ...). Scale also scales the limits, so that the
+	 * motion is the same in the new units. The transform applies once;
+	 * later replan() targets are taken as given, in the new coordinates.
+	 */
+	pub fn apply_transform(&mut self, transform: Transform) {
+		let (k, b) = transform.affine();
+		assert!(k != 0.0 && k.is_finite() && b.is_finite());
+		let map = |pos: f64| k*pos + b;
+		let (sign, scale) = (k.signum(), k.abs());
+
+		// Scale first, then mirror and offset what rescale() scaled
+		self.rescale(scale);
+		for seg in self.segments.iter_mut() {
+			*seg = seg.scaled_positions(sign).offset_positions(b);
+		}
+		for val in self.state[..Self::STATE_LEN - 1].iter_mut() {
+			*val *= sign;
+		}
+		self.state[Self::STATE_LEN - 1] = sign*self.state[Self::STATE_LEN - 1] + b;
+		self.target = sign*self.target + b;
+		self.acc_bias *= sign;
+		self.last_direction *= sign;
+		for (range, _) in self.velocity_zones.iter_mut() {
+			let (start, end) = (sign*range.start + b, sign*range.end + b);
+			*range = start.min(end)..start.max(end);
+		}
+
+		for (s_target, v_limit, _) in self.queue.iter_mut() {
+			*s_target = map(*s_target);
+			*v_limit *= scale;
+		}
+		if let Some((s_target, v_limit)) = &mut self.pending_replan {
+			*s_target = map(*s_target);
+			*v_limit *= scale;
+		}
+		self.v_limit *= scale;
+		self.travel_limits = self.travel_limits.map(|(min, max)| (map(min).min(map(max)), map(min).max(map(max))));
+		self.follow_hysteresis *= scale;
+		self.hold_correction *= scale;
+		self.residual *= scale;
+		self.residual_bound = self.residual_bound.map(|bound| bound*scale);
+		if let Some(step) = self.output_resolution {
+			let step = step*scale;
+			self.output_resolution = Some(step);
+			self.output_steps = (self.get_state().0/step).round() as i64;
+		}
+	}
+
+	// Multiply every position in the plan, the state and the settings plan() reads by factor
+	fn rescale(&mut self, factor: f64) {
+		for seg in self.segments.iter_mut() {
+			*seg = seg.scaled_positions(factor);
+		}
+		for val in self.state.iter_mut() {
+			*val *= factor;
+		}
+		self.target *= factor;
+		self.limits = Limits {
+			vel: self.limits.vel.map(|vel| vel*factor),
+			acc: self.limits.acc*factor,
+			jerk: self.limits.jerk.map(|jerk| jerk*factor),
+			snap: None,
+		};
+		self.acc_bias *= factor;
+		self.approach_acc = self.approach_acc.map(|acc| acc*factor);
+		self.max_dec = self.max_dec.map(|dec| dec*factor);
+		self.splice_snap = self.splice_snap.map(|snap| snap*factor);
+		self.soft_landing = self.soft_landing.map(|(window, scale)| (window*factor, scale));
+		for (range, v_max) in self.velocity_zones.iter_mut() {
+			*range = range.start*factor..range.end*factor;
+			*v_max *= factor;
+		}
+	}
+
+	// See plan(); limits, s_target and the path state in the same units
+	fn plan_in_units(&mut self, limits: &[f64], s_target: f64, keep: f64) {
+		self.pending_replan = None;
+		self.last_replan = Some(self.now);
+
+		self.truncate_plan(keep);
+		self.restart_clock();
+		self.clear_trace();
+		let kept = self.segments.len();
+		let s_start = self.get_end_state()[Self::STATE_LEN - 1];
+		if let Some(snap) = self.splice_snap {
+			self.splice_jerk(limits, s_target, snap);
+		}
+
+		let cache_key = self.cache_key(limits);
+		let cached = cache_key.as_ref().is_some_and(|key| self.plan_cached(key, s_target));
+		if let Some(duration) = self.min_jerk.take() {
+			let seg = self.min_jerk_segment(s_target, duration);
+			self.push_segment(seg);
+			self.limit_free = true;
+		} else if cached {
+			// Same plan as last time, with a new cruise duration
+		} else if self.profile == Profile::Smoothstep {
+			self.calc_smoothstep(limits[DEGREE - 1], s_target);
+		} else if !self.velocity_zones.is_empty() && self.calc_zoned(limits[DEGREE - 1], s_target) {
+			// Planned part by part between the zone boundaries
+		} else if self.soft_landing.is_some_and(|landing| self.calc_soft_landing(limits[DEGREE - 1], s_target, landing)) {
+			// Planned with the approach and the landing
+		} else if DEGREE == 2 {
+			// Acc-limited path
+			self.calc_path_2(limits, s_target, self.overshoot_policy);
+		} else /* DEGREE == 3 */ {
+			// Jerk-limited path
+			if self.overshoot_policy == OvershootPolicy::Forbid {
+				self.stop_first_3(s_target);
+			}
+			self.calc_path_3(limits, s_target);
+		}
+
+		if !self.get_end_state().iter().all(|val| val.is_finite()) {
+			self.segments.clear();
+			self.raise_fault(FaultKind::NumericalFailure);
+			return;
+		}
+		if let (Some(key), false) = (cache_key, cached) {
+			self.store_plan(key, kept, s_target - s_start);
+		}
+		let tag = self.move_tag;
+		for seg in self.segments.iter_mut().skip(kept) {
+			*seg = seg.clone().with_tag(tag);
+		}
+		if s_target != s_start {
+			self.last_direction = (s_target - s_start).signum();
+		}
+		self.target = s_target;
+		self.metrics.replans += 1;
+		self.count_segments(kept, s_start);
+	}
+
+	/*
+	 * See set_soft_landing(). The landing speed v_w is the one the scaled
+	 * limits stop from within the window w: v_w² = 2*a*w acc-limited, and
+	 * w = v_w*(v_w/a + a/j)/2 (or v_w*sqrt(v_w/j) if v_w is below a²/j)
+	 * jerk-limited. Returns false, planning nothing, if the move can't be
+	 * made without reversing.
+	 */
+	fn calc_soft_landing(&mut self, v_limit: f64, s_target: f64, (window, scale): (f64, f64)) -> bool {
+		let end = self.get_end_state();
+		let pos_index = Self::STATE_LEN - 1;
+		let start = (end[pos_index], end[pos_index - 1], if DEGREE == 3 { end[pos_index - 2] } else { 0.0 });
+		let dir = (s_target - start.0).signum();
+
+		let scaled = Limits {
+			vel: Some(v_limit),
+			acc: self.limits.acc*scale,
+			jerk: self.limits.jerk.map(|jerk| jerk*scale),
+			snap: None,
+		};
+		let rest = State { pos: s_target, ..State::default() };
+
+		let mut segments = Vec::new();
+		if dir*(s_target - start.0) <= window {
+			segments.extend(Self::plan_between(start.into(), rest, scaled));
+		} else {
+			let a = scaled.acc;
+			let v_w = match scaled.jerk {
+				None => (2.0*a*window).sqrt(),
+				Some(j) => {
+					let a_j = a*a/j;
+					let v_w = 0.5*(-a_j + (a_j*a_j + 8.0*a*window).sqrt());
+					if v_w >= a_j { v_w } else { math::cbrt(window*window*j) }
+				},
+			}.min(v_limit);
+			let entry = State { pos: s_target - dir*window, vel: dir*v_w, acc: 0.0 };
+			segments.extend(Self::plan_between(start.into(), entry, Limits { vel: Some(v_limit), ..self.limits }));
+			segments.extend(Self::plan_between(entry, rest, scaled));
+		}
+
+		let reverses = segments.iter().any(|seg| {
+			let (min, max) = seg.get_range(1, 0.0, seg.get_duration());
+			if dir > 0.0 { min < -CLOSE_ENOUGH } else { max > CLOSE_ENOUGH }
+		});
+		if reverses || !self.fits_plan(segments.len()) {
+			return false;
+		}
+		for seg in segments {
+			self.push_segment(seg);
+		}
+		true
+	}
+
+	/*
+	 * See set_velocity_zones(). The move is split into parts at the zone
+	 * boundaries, each with the lowest velocity limit of the zones it lies
+	 * in. The crossing speeds come from a backward pass (the highest speed
+	 * that can still slow down to the next crossing) and a forward pass
+	 * (no higher than can be reached from the previous one, no lower than
+	 * braking all the way gets down to). Returns false, planning nothing,
+	 * if the move can't be made without reversing.
+	 */
+	fn calc_zoned(&mut self, v_limit: f64, s_target: f64) -> bool {
+		let end = self.get_end_state();
+		let pos_index = Self::STATE_LEN - 1;
+		let start = State {
+			pos: end[pos_index],
+			vel: end[pos_index - 1],
+			acc: if DEGREE == 3 { end[pos_index - 2] } else { 0.0 },
+		};
+		let dir = (s_target - start.pos).signum();
+		if dir == 0.0 || dir*start.vel < 0.0 {
+			return false;
+		}
+
+		let mut bounds = vec![start.pos];
+		let ahead = |pos: f64| dir*(pos - start.pos) > CLOSE_ENOUGH && dir*(s_target - pos) > CLOSE_ENOUGH;
+		for (range, _) in &self.velocity_zones {
+			bounds.extend([range.start, range.end].iter().copied().filter(|pos| ahead(*pos)));
+		}
+		bounds.push(s_target);
+		bounds.sort_by(|a, b| (dir*a).total_cmp(&(dir*b)));
+		bounds.dedup();
+
+		let caps: Vec<f64> = bounds.windows(2).map(|part| {
+			let mid = 0.5*(part[0] + part[1]);
+			self.velocity_zones.iter().filter(|(range, _)| range.contains(&mid)).fold(v_limit, |cap, (_, v_max)| cap.min(*v_max))
+		}).collect();
+		let dists: Vec<f64> = bounds.windows(2).map(|part| (part[1] - part[0]).abs()).collect();
+
+		// Crossing speeds, at the start of each part and at the target
+		let limits = self.limits;
+		let n = caps.len();
+		let mut speeds = vec![0.0; n + 1];
+		for i in (1..n).rev() {
+			speeds[i] = caps[i - 1].min(caps[i]).min(Self::reachable_speed(speeds[i + 1], dists[i], caps[i], limits));
+		}
+		speeds[0] = dir*start.vel;
+		for i in 1..=n {
+			let fastest = Self::reachable_speed(speeds[i - 1], dists[i - 1], caps[i - 1].max(speeds[i - 1]), limits);
+			let slowest = Self::braked_speed(speeds[i - 1], dists[i - 1], limits);
+			speeds[i] = speeds[i].min(fastest).max(slowest);
+		}
+		if speeds[n] > CLOSE_ENOUGH {
+			return false;
+		}
+		speeds[n] = 0.0;
+
+		let mut segments = Vec::new();
+		let mut from = start;
+		for i in 0..n {
+			let to = State { pos: bounds[i + 1], vel: dir*speeds[i + 1], acc: 0.0 };
+			let cap = caps[i].max(speeds[i]).max(speeds[i + 1]);
+			segments.extend(Self::plan_between(from, to, Limits { vel: Some(cap), ..limits }));
+			from = to;
+		}
+
+		let reverses = segments.iter().any(|seg| {
+			let (min, max) = seg.get_range(1, 0.0, seg.get_duration());
+			if dir > 0.0 { min < -CLOSE_ENOUGH } else { max > CLOSE_ENOUGH }
+		});
+		if reverses || !self.fits_plan(segments.len()) {
+			return false;
+		}
+		for seg in segments {
+			self.push_segment(seg);
+		}
+		true
+	}
+
+	/* Distance to change speed (both with zero acceleration) between v0 and
+	 * v1, at the acceleration and jerk limits. */
+	fn speed_change_distance(v0: f64, v1: f64, limits: Limits) -> f64 {
+		let (a, v_diff) = (limits.acc, (v1 - v0).abs());
+		let t = match limits.jerk {
+			None => v_diff/a,
+			Some(j) if v_diff >= a*a/j => v_diff/a + a/j,
+			Some(j) => 2.0*(v_diff/j).sqrt(),
+		};
+		0.5*(v0 + v1)*t
+	}
+
+	// Highest speed, up to cap, that can change to or from v0 within dist
+	fn reachable_speed(v0: f64, dist: f64, cap: f64, limits: Limits) -> f64 {
+		if cap <= v0 || Self::speed_change_distance(v0, cap, limits) <= dist {
+			return cap.max(v0);
+		}
+		let (mut lo, mut hi) = (v0, cap);
+		for _ in 0..100 {
+			let mid = 0.5*(lo + hi);
+			if Self::speed_change_distance(v0, mid, limits) <= dist { lo = mid; } else { hi = mid; }
+		}
+		lo
+	}
+
+	// Lowest speed that braking from v0 gets down to within dist
+	fn braked_speed(v0: f64, dist: f64, limits: Limits) -> f64 {
+		if Self::speed_change_distance(0.0, v0, limits) <= dist {
+			return 0.0;
+		}
+		let (mut lo, mut hi) = (0.0, v0);
+		for _ in 0..100 {
+			let mid = 0.5*(lo + hi);
+			if Self::speed_change_distance(mid, v0, limits) <= dist { hi = mid; } else { lo = mid; }
+		}
+		hi
+	}
+
+	// None if caching is off or doesn't apply
+	fn cache_key(&self, limits: &[f64]) -> Option<CacheKey> {
+		if self.cache.is_none() || self.profile != Profile::TimeOptimal || self.soft_landing.is_some() || self.splice_snap.is_some()
+			|| !self.velocity_zones.is_empty() || self.min_jerk.is_some() {
+			return None;
+		}
+		let mut state = self.get_end_state();
+		state[Self::STATE_LEN - 1] = 0.0;
+		let mut key_limits = [0.0; MAX_STATE_LEN];
+		key_limits[..DEGREE].copy_from_slice(limits);
+		Some(CacheKey {
+			state,
+			limits: key_limits,
+			path_limits: self.limits,
+			overshoot_policy: self.overshoot_policy,
+			acc_bias: self.acc_bias,
+			approach_acc: self.approach_acc,
+			max_dec: self.max_dec,
+		})
+	}
+
+	// See set_cache(). Plans from the cache and returns true on a hit.
+	fn plan_cached(&mut self, key: &CacheKey, s_target: f64) -> bool {
+		let cache = self.cache.take().unwrap();
+		let s_diff = s_target - self.get_end_state()[Self::STATE_LEN - 1];
+		let t_cruise = cache.phases.get(cache.cruise).map_or(-1.0, |phase| phase.1 + (s_diff - cache.s_diff)/cache.v_cruise);
+		let hit = cache.key.as_ref() == Some(key) && s_diff*cache.v_cruise > 0.0 && t_cruise >= 0.0;
+		if hit {
+			for (n, (start, duration)) in cache.phases.iter().enumerate() {
+				let duration = if n == cache.cruise { t_cruise } else { *duration };
+				if duration <= TINY_DURATION {
+					continue;
+				}
+				let mut state = *start;
+				state[Self::STATE_LEN - 1] = self.get_end_state()[Self::STATE_LEN - 1];
+				self.push_segment(Segment::new(&state[..Self::STATE_LEN], duration, Self::STATE_LEN));
+			}
+			self.metrics.cache_hits += 1;
+		} else {
+			self.metrics.cache_misses += 1;
+		}
+		self.cache = Some(cache);
+		hit
+	}
+
+	// Keep the plan from segments[first] on for plan_cached(), if it has a cruise
+	fn store_plan(&mut self, key: CacheKey, first: usize, s_diff: f64) {
+		let segments = &self.segments;
+		let cache = match &mut self.cache {
+			Some(cache) => cache,
+			None => return,
+		};
+		cache.key = None;
+		cache.phases.clear();
+		for seg in segments.iter().skip(first) {
+			let mut start = seg.state_at(0.0);
+			start[Self::STATE_LEN - 1] = 0.0;
+			cache.phases.push((start, seg.get_duration()));
+		}
+		let vel_index = Self::STATE_LEN - 2;
+		let cruise = cache.phases.iter().position(|(start, duration)| {
+			duration.is_finite() && start[vel_index] != 0.0 && start[..vel_index].iter().all(|val| *val == 0.0)
+		});
+		if let Some(cruise) = cruise {
+			cache.v_cruise = cache.phases[cruise].0[vel_index];
+			cache.cruise = cruise;
+			cache.s_diff = s_diff;
+			cache.key = Some(key);
+		}
+	}
+
+	// From the end state, ramp the jerk at snap to the jerk a plan from there starts with
+	fn splice_jerk(&mut self, limits: &[f64], s_target: f64, snap: f64) {
+		if DEGREE != 3 || self.profile != Profile::TimeOptimal {
+			return;
+		}
+
+		let len = self.segments.len();
+		self.calc_path_3(limits, s_target);
+		let j1 = self.segments.get(len).map_or(0.0, |seg| seg.state_at(0.0)[0]);
+		self.segments.truncate(len);
+		self.clear_trace();
+
+		let state = self.get_end_state();
+		let j_diff = j1 - state[0];
+		if j_diff.abs() < CLOSE_ENOUGH {
+			return;
+		}
+		let t = j_diff.abs()/snap;
+		let pos = [state[3], state[2], 0.5*state[1], state[0]/6.0, j_diff.signum()*snap/24.0];
+		self.push_segment(Segment::from_poly(&pos, t, Self::STATE_LEN));
+	}
+
+	// Add the segments from segments[first] on, of a move from s_start to target, to the metrics
+	fn count_segments(&mut self, first: usize, s_start: f64) {
+		let dir = (self.target - s_start).signum();
+		for seg in self.segments.iter().skip(first) {
+			self.metrics.segments += 1;
+			if !seg.get_duration().is_finite() {
+				continue;
+			}
+			self.metrics.planned_time += seg.get_duration();
+			let (min, max) = seg.get_range(0, 0.0, seg.get_duration());
+			if (dir >= 0.0 && max > self.target + CLOSE_ENOUGH) || (dir <= 0.0 && min < self.target - CLOSE_ENOUGH) {
+				self.metrics.overshoot_segments += 1;
+			}
+		}
+		self.metrics.max_segments_queued = self.metrics.max_segments_queued.max(self.segments.len());
+	}
+
+	pub fn metrics(&self) -> Metrics {
+		self.metrics
+	}
+
+	// Cut the plan down to its next duration seconds, starting now
+	fn truncate_plan(&mut self, duration: f64) {
+		if duration <= 0.0 || self.segments.is_empty() {
+			self.segments.clear();
+			return;
+		}
+
+		let first = &self.segments[0];
+		self.segments[0] = first.part(self.time, first.get_duration());
+		let mut kept = 0.0;
+		let mut count = 0;
+		while count < self.segments.len() && kept < duration {
+			let seg = &self.segments[count];
+			if kept + seg.get_duration() > duration {
+				self.segments[count] = seg.part(0.0, duration - kept);
+			}
+			kept += self.segments[count].get_duration();
+			count += 1;
+		}
+		self.segments.truncate(count);
+	}
+
+	/*
+	 * Rendezvous with a target that is at s_target now and moves at the
+	 * constant frame_vel, e.g. a part on a conveyor: reach it with velocity
+	 * frame_vel, then keep following it at that velocity until the next
+	 * replan() or stop(). v_limit must be above |frame_vel| to catch up,
+	 * or the path latches FaultKind::InvalidInput.
+	 *
+	 * The move is planned as a regular move in the frame moving with the
+	 * target, with the velocity limit lowered by frame_vel in the direction
+	 * of the move so that it holds in absolute terms. Not subject to
+	 * min_replan_interval.
+	 */
+	pub fn replan_tracking(&mut self, s_target: f64, frame_vel: f64, v_limit: f64) {
+		let v_limit = self.limits.vel.map_or(v_limit, |vel| v_limit.min(vel));
+		log!("Path::replan_tracking(), state {:?}, s_target {}, frame_vel {}, v_limit {}", self.state(), s_target, frame_vel, v_limit);
+		if self.fault.is_some() {
+			return;
+		}
+		if !s_target.is_finite() || !frame_vel.is_finite() || v_limit.is_nan() || v_limit <= frame_vel.abs() {
+			self.raise_fault(FaultKind::InvalidInput);
+			return;
+		}
+		self.move_tag = None;
+
+		let pos_index = Self::STATE_LEN - 1;
+		let s_diff = s_target - self.state[pos_index];
+		let limits = self.ordered_limits(v_limit - s_diff.signum()*frame_vel);
+
+		// Plan relative to the frame...
+		let v0 = self.state[pos_index - 1];
+		self.state[pos_index - 1] = v0 - frame_vel;
+		self.plan(&limits[..DEGREE], s_target, 0.0);
+		self.state[pos_index - 1] = v0;
+		self.v_limit = v_limit;
+
+		// ...and add the frame motion back in
+		let mut t = 0.0;
+		for seg in self.segments.iter_mut() {
+			*seg = seg.with_frame_motion(frame_vel*t, frame_vel);
+			t += seg.get_duration();
+		}
+
+		let mut state = self.get_end_state();
+		state[..pos_index - 1].fill(0.0);
+		state[pos_index - 1] = frame_vel;
+		self.push_segment(Segment::new(&state[..Self::STATE_LEN], f64::INFINITY, Self::STATE_LEN));
+		if self.segments.len() == 1 {
+			self.state = self.segments[0].state_at(0.0);
+		}
+	}
+
+	pub fn stop(&mut self) {
+		log!("Path::stop(), state {:?}", self.state());
+
+		self.move_tag = None;
+		self.pending_replan = None;
+		self.settle_target = None;
+		self.queue.clear();
+		self.restart_clock();
+		self.segments.clear();
+		self.clear_trace();
+		let state = self.get_state();
+		self.trace(Branch::Stop, &[("v0", state.1), ("a0", state.2)]);
+
+		if DEGREE == 2 {
+			// Acc-limited path
+			self.calc_path_1(0.0);
+		} else /* DEGREE == 3 */ {
+			// Jerk-limited path
+			self.calc_stop_3();
+		}
+
+		// Accept whatever position we end up at as target
+		self.target = self.get_end_state()[Self::STATE_LEN - 1];
+		self.count_segments(0, self.target);
+		self.stopping = true;
+	}
+
+	/*
+	 * Move in direction (its sign) at speed until jog_release(), e.g. while
+	 * a jog button is held. With travel limits set this is a move to the
+	 * limit ahead, so the path stops there even if the release never
+	 * comes; without them the speed is held indefinitely. Calling jog()
+	 * again changes direction or speed on the fly.
+	 */
+	pub fn jog(&mut self, direction: f64, speed: f64) {
+		log!("Path::jog(), state {:?}, direction {}, speed {}", self.state(), direction, speed);
+		assert!(speed >= 0.0);
+		if self.fault.is_some() {
+			return;
+		}
+		let speed = self.limits.vel.map_or(speed, |vel| speed.min(vel));
+		let dir = direction.signum();
+		if speed == 0.0 || direction == 0.0 {
+			self.stop();
+			return;
+		}
+
+		if let Some((min, max)) = self.travel_limits {
+			let limit = if dir > 0.0 { max } else { min };
+			if (limit - self.get_state().0)*dir <= 0.0 {
+				// Already at or past the limit ahead
+				self.stop();
+			} else {
+				self.replan(limit, speed);
+			}
+			return;
+		}
+
+		self.pending_replan = None;
+		self.queue.clear();
+		self.restart_clock();
+		self.segments.clear();
+		self.clear_trace();
+		self.v_limit = speed;
+		self.change_velocity(dir*speed);
+		let mut state = self.get_end_state();
+		let vel_index = Self::STATE_LEN - 2;
+		state[..vel_index].fill(0.0);
+		state[vel_index] = dir*speed;
+		self.push_segment(Segment::new(&state[vel_index..Self::STATE_LEN], f64::INFINITY, Self::STATE_LEN));
+		if self.segments.len() == 1 {
+			self.state = self.segments[0].state_at(0.0);
+		}
+	}
+
+	// The jog button was let go: stop, jerk-limited on a jerk-limited path
+	pub fn jog_release(&mut self) {
+		self.stop();
+	}
+
+	fn restart_clock(&mut self) {
+		self.stopping = false;
+		self.limit_free = false;
+		self.time = 0.0;
+		self.time_carry = 0.0;
+		self.handed_off = 0;
+		self.plan_start = self.now;
+		self.consumed = 0.0;
+		self.played.clear();
+	}
+
+	pub fn update(&mut self, dt: f64) -> Progress {
+		if !dt.is_finite() || dt < 0.0 {
+			return self.invalid_update();
+		}
+		self.now += dt;
+		self.last_dt = dt;
+		let mut progress = self.no_progress();
+		if !self.segments.is_empty() {
+			let t_from = self.time;
+			self.add_time(self.time_scale*dt);
+			self.count_distance(t_from, self.time);
+			progress = self.advance();
+		}
+		self.replan_pending();
+		self.run_queue();
+		self.check_hold();
+		progress.remaining = self.remaining_time();
+		progress.tag = self.current_tag();
+		progress.time = self.timestamp();
+		self.emit(progress.finished);
+		progress
+	}
+
+	/*
+	 * Like update(), but takes the caller's (monotonic) clock instead of a
+	 * time delta. The position in the plan is computed from the time the
+	 * plan was made, so jitter in the caller's update rate doesn't
+	 * accumulate. A plan made by replan() or stop() starts at the timestamp
+	 * of the latest update_to() call.
+	 */
+	pub fn update_to(&mut self, t: f64) -> Progress {
+		if !t.is_finite() || t < self.now {
+			return self.invalid_update();
+		}
+		self.last_dt = t - self.now;
+		self.now = t;
+		let mut progress = self.no_progress();
+		if !self.segments.is_empty() {
+			let t_from = self.time;
+			self.time = self.time_scale*(t - self.plan_start) - self.consumed;
+			self.time_carry = 0.0;
+			self.count_distance(t_from, self.time);
+			progress = self.advance();
+		}
+		self.replan_pending();
+		self.run_queue();
+		self.check_hold();
+		progress.remaining = self.remaining_time();
+		progress.tag = self.current_tag();
+		progress.time = self.timestamp();
+		self.emit(progress.finished);
+		progress
+	}
+
+	/*
+	 * Jump to t seconds of plan time into the current plan, forward or
+	 * back, e.g. to scrub through it in a simulator. The state is the
+	 * plan's at t, and update() and update_to() go on from there. Seeking
+	 * to the end or past it finishes the move, as update() would. Segments
+	 * handed out by take_next_segment() start over from the new time.
+	 * Without an active plan there is nothing to seek in.
+	 */
+	pub fn seek(&mut self, t: f64) -> Progress {
+		assert!(t >= 0.0);
+		let mut progress = self.no_progress();
+		if !self.segments.is_empty() {
+			self.time = t - self.consumed;
+			self.time_carry = 0.0;
+			// Take back the segments run through since t
+			while self.time < 0.0 {
+				match self.played.pop() {
+					Some(seg) => {
+						self.time += seg.get_duration();
+						self.consumed -= seg.get_duration();
+						self.segments.push_front(seg);
+					},
+					None => self.time = 0.0,
+				}
+			}
+			self.handed_off = 0;
+			self.plan_start = self.now - t/self.time_scale;
+			progress = self.advance();
+		}
+		self.run_queue();
+		progress.remaining = self.remaining_time();
+		progress.tag = self.current_tag();
+		progress.time = self.timestamp();
+		self.emit(progress.finished);
+		progress
+	}
+
+	/*
+	 * For drives with their own interpolator (PVT, PT modes): hand out the
+	 * segments of the plan in order, the first one starting now, instead
+	 * of sampling get_state() every dt. None when all have been handed out.
+	 * A new plan (replan(), stop(), scale_duration(), ...) starts over from
+	 * its first segment; the drive should drop what it has buffered.
+	 */
+	pub fn take_next_segment(&mut self) -> Option<Segment> {
+		let seg = self.segments.get(self.handed_off)?;
+		let seg = match self.handed_off {
+			0 => seg.part(self.time, seg.get_duration()),
+			_ => seg.clone(),
+		};
+		self.handed_off += 1;
+		Some(seg)
+	}
+
+	/*
+	 * The drive has run through count of the segments handed out by
+	 * take_next_segment(). Advances the path to the end of them, as
+	 * update() would.
+	 */
+	pub fn pop_completed_segments(&mut self, count: usize) -> Progress {
+		assert!(count <= self.handed_off, "Only {} segments handed out", self.handed_off);
+		let mut progress = self.no_progress();
+		let t_to = self.segments.iter().take(count).map(|seg| seg.get_duration()).sum();
+		self.count_distance(self.time, t_to);
+		for _ in 0..count {
+			let seg = self.segments.pop_front().unwrap();
+			self.consumed += seg.get_duration();
+			self.handed_off -= 1;
+			progress.completed_segments += 1;
+			let end_state = seg.end_state();
+			self.played.push(seg);
+			if self.segments.is_empty() {
+				self.finish_move(&end_state[..Self::STATE_LEN]);
+				progress.finished = true;
+				break;
+			}
+			self.check_residual(&end_state);
+		}
+
+		if count > 0 {
+			self.time = 0.0;
+			self.time_carry = 0.0;
+			if !self.segments.is_empty() {
+				self.state = self.segments[0].state_at(0.0);
+			}
+		}
+		self.run_queue();
+		progress.remaining = self.remaining_time();
+		progress.tag = self.current_tag();
+		progress.time = self.timestamp();
+		self.emit(progress.finished);
+		progress
+	}
+
+	/*
+	 * (pos, vel, acc) dt_fraction of an update interval after the latest
+	 * update, without advancing the path, for a consumer running faster
+	 * than the updates: e.g. with updates at 1 kHz and a current loop at
+	 * 16 kHz, call this with 1/16, 2/16, ... between updates. The interval
+	 * is that of the latest update. Past the end of the plan this gives the
+	 * end state.
+	 */
+	pub fn interpolate_since_last_update(&self, dt_fraction: f64) -> (f64, f64, f64) {
+		assert!((0.0..=1.0).contains(&dt_fraction));
+		let mut t = self.time + self.time_scale*dt_fraction*self.last_dt;
+		let pos_index = Self::STATE_LEN - 1;
+		for seg in &self.segments {
+			if t <= seg.get_duration() {
+				let state = seg.state_at(t);
+				return (state[pos_index], state[pos_index - 1], state[pos_index - 2]);
+			}
+			t -= seg.get_duration();
+		}
+		match self.segments.back() {
+			Some(_) if self.end_of_move_policy == EndOfMovePolicy::SnapToTarget => (self.target, 0.0, 0.0),
+			Some(seg) => {
+				let state = seg.end_state();
+				(state[pos_index], state[pos_index - 1], if DEGREE == 3 { state[pos_index - 2] } else { 0.0 })
+			},
+			None => self.get_state(),
+		}
+	}
+
+	// Hand the new state to the history and any async listeners
+	fn emit(&mut self, finished: bool) {
+		if let Some(step) = self.output_resolution {
+			let carry = self.get_state().0 - self.output_steps as f64*step;
+			self.output_steps += (carry/step).round() as i64;
+		}
+		let state = self.get_output_state();
+		let time = self.timestamp();
+		if let Some(history) = &mut self.history {
+			if history.len() == history.capacity() {
+				history.pop_front();
+			}
+			history.push_back([time, state.0, state.1, state.2]);
+		}
+		#[cfg(feature = "async")]
+		self.notifier.publish(state, finished);
+		#[cfg(not(feature = "async"))]
+		let _ = finished;
+	}
+
+	// Time that doesn't move forward is a fault; the time is ignored
+	fn invalid_update(&mut self) -> Progress {
+		self.raise_fault(FaultKind::InvalidInput);
+		let mut progress = self.no_progress();
+		progress.remaining = self.remaining_time();
+		progress
+	}
+
+	fn no_progress(&self) -> Progress {
+		Progress {
+			completed_segments: 0,
+			finished: false,
+			remaining: 0.0,
+			tag: None,
+			time: self.timestamp(),
+		}
+	}
+
+	fn remaining_time(&self) -> f64 {
+		if self.segments.is_empty() {
+			return 0.0;
+		}
+		self.segments.iter().map(|seg| seg.get_duration()).sum::<f64>() - self.time
+	}
+
+	fn replan_pending(&mut self) {
+		if let (Some((s_target, v_limit)), Some(last_replan)) = (self.pending_replan, self.last_replan) {
+			if self.now - last_replan >= self.min_replan_interval {
+				self.plan_move(s_target, v_limit);
+			}
+		}
+	}
+
+	/*
+	 * time += dt with Kahan summation: the low bits each addition rounds
+	 * away are kept in time_carry and added back with the next dt, so an
+	 * hour of 1 ms steps into a long cruise is still exact to rounding
+	 * instead of about a million roundings off.
+	 */
+	fn add_time(&mut self, dt: f64) {
+		let dt = dt - self.time_carry;
+		let time = self.time + dt;
+		self.time_carry = (time - self.time) - dt;
+		self.time = time;
+	}
+
+	fn advance(&mut self) -> Progress {
+		let mut progress = self.no_progress();
+		// The durations are only exact to rounding, and add_time() lands on their sum; finish at TINY_DURATION from the end
+		let end = |segments: &VecDeque<Segment>| segments[0].get_duration() - if segments.len() == 1 { TINY_DURATION } else { 0.0 };
+		while self.time > end(&self.segments) {
+			let seg = self.segments.pop_front().unwrap();
+			self.time -= seg.get_duration();
+			self.consumed += seg.get_duration();
+			progress.completed_segments += 1;
+			let end_state = seg.end_state();
+			self.played.push(seg);
+			if self.segments.is_empty() {
+				self.finish_move(&end_state[..Self::STATE_LEN]);
+				progress.finished = true;
+				break;
+			}
+			self.check_residual(&end_state);
+		}
+
+		if !self.segments.is_empty() {
+			self.state = self.segments[0].state_at(self.time);
+		}
+		progress
+	}
+
+	// Add up the jump from end_state to segments[0], and re-chain if over the bound
+	fn check_residual(&mut self, end_state: &[f64; MAX_STATE_LEN]) {
+		// state[0] is the input driving each segment, which is allowed to jump
+		let jump = end_state.iter().zip(self.segments[0].state_at(0.0).iter()).skip(1)
+			.map(|(end, start)| (end - start).abs())
+			.fold(0.0, f64::max);
+		self.residual += jump;
+
+		if self.residual_bound.is_some_and(|bound| self.residual > bound) {
+			let mut state = *end_state;
+			for seg in self.segments.iter_mut() {
+				*seg = seg.rebased(&state);
+				state = seg.end_state();
+			}
+			self.residual = 0.0;
+		}
+	}
+
+	pub(crate) fn state(&self) -> &[f64] {
+		&self.state[..Self::STATE_LEN]
+	}
+
+	fn finish_move(&mut self, end_state: &[f64]) {
+		let mut target_state = [0.0; MAX_STATE_LEN];
+		target_state[Self::STATE_LEN - 1] = self.target;
+
+		// state[0] is the input driving the last segment, not a result of it
+		let deviation = end_state.iter().zip(target_state.iter()).skip(1)
+			.map(|(val, target)| (val - target).abs())
+			.fold(0.0, f64::max);
+
+		let hold = match self.end_of_move_policy {
+			EndOfMovePolicy::SnapToTarget => false,
+			EndOfMovePolicy::HoldLastState => true,
+			EndOfMovePolicy::ErrorIfDeviationExceeds(tolerance) => {
+				if deviation > tolerance {
+					self.error = Some(Error::EndOfMoveDeviation { deviation, tolerance });
+				}
+				deviation > tolerance
+			},
+		};
+
+		if hold {
+			// Keep the integrated values, but there's no segment left driving the highest derivative
+			self.state[..Self::STATE_LEN].copy_from_slice(end_state);
+			self.state[0] = 0.0;
+		} else {
+			// Zero out any accumulated inaccuracies
+			self.state = target_state;
+		}
+	}
+
+	/*
+	 * The full current state, highest derivative first like
+	 * Segment::get_state_at(): (jerk,) acc, vel, pos. Writes into out
+	 * without allocating and returns the number of values written (DEGREE
+	 * + 1), for calling from e.g. an interrupt handler.
+	 */
+	pub fn state_into(&self, out: &mut [f64]) -> usize {
+		assert!(out.len() >= Self::STATE_LEN);
+		out[..Self::STATE_LEN].copy_from_slice(self.state());
+		Self::STATE_LEN
+	}
+
+	pub fn get_state(&self) -> (f64, f64, f64) {
+		let pos_index = Self::STATE_LEN - 1;
+		(self.state[pos_index], self.state[pos_index - 1], self.state[pos_index - 2])
+	}
+
+	/*
+	 * The commanded jerk at the current state, in the path's own time units
+	 * like get_state(); 0.0 for acc-limited paths, and at rest. It has the
+	 * sign of the change of acceleration along the position axis, whatever
+	 * the direction of motion. At a phase boundary it is the jerk of the
+	 * phase that starts there. Read from the current segment, since the
+	 * state only picks up the jerk of a new plan with the next update().
+	 */
+	pub fn current_jerk(&self) -> f64 {
+		match self.segments.front() {
+			Some(seg) if DEGREE == 3 => seg.state_at(self.time)[0],
+			_ => 0.0,
+		}
+	}
+
+	pub fn is_active(&self) -> bool {
+		!self.segments.is_empty()
+	}
+
+	/*
+	 * (vel_ff, acc_ff, jerk_ff) for a servo loop at the current state: the
+	 * planned velocity, acceleration and jerk per second of the caller's
+	 * clock (so with set_time_scale() applied), times the gains from
+	 * set_feedforward_gains(). The jerk of an acc-limited path is zero; its
+	 * acceleration jumps instead.
+	 */
+	pub fn feedforward(&self) -> (f64, f64, f64) {
+		let (_, vel, acc) = self.get_state();
+		let jerk = self.current_jerk();
+		let (k, gains) = (self.time_scale, self.feedforward_gains);
+		(gains.vel*k*vel, gains.acc*k*k*acc, gains.jerk*k*k*k*jerk)
+	}
+
+	/*
+	 * Future that resolves when the current move has been run through to
+	 * its end by update()/update_to(), or at once if the path is at rest.
+	 * A replan() before then extends the wait to the end of the new move.
+	 */
+	#[cfg(feature = "async")]
+	pub fn wait_done(&self) -> MoveDone {
+		self.notifier.done(self.is_active())
+	}
+
+	// Receiver of the state after each update()/update_to()
+	#[cfg(feature = "async")]
+	pub fn watch_state(&self) -> StateReceiver {
+		self.notifier.receiver(self.get_state())
+	}
+
+	/*
+	 * How close the remaining plan comes to each limit, or None if there is
+	 * no plan. The velocity limit is the one given to the latest replan(),
+	 * else Limits.vel; a plan with neither, such as a stop() from a set_state(),
+	 * is measured against its own peak speed.
+	 */
+	pub fn limit_margins(&self) -> Option<LimitMargins> {
+		if self.segments.is_empty() {
+			return None;
+		}
+
+		let mut peaks = [0.0f64; 3];
+		let mut t_from = self.time;
+		for seg in &self.segments {
+			let peak = |range: (f64, f64), offset: f64| (range.0 - offset).abs().max((range.1 - offset).abs());
+			peaks[0] = peaks[0].max(peak(seg.get_range(1, t_from, seg.get_duration()), 0.0));
+			peaks[1] = peaks[1].max(peak(seg.get_range(2, t_from, seg.get_duration()), self.acc_bias));
+			peaks[2] = peaks[2].max(peak(seg.get_range(3, t_from, seg.get_duration()), 0.0));
+			t_from = 0.0;
+		}
+
+		let v_ref = if self.v_limit > 0.0 { self.v_limit } else { self.limits.vel.unwrap_or(peaks[0]) };
+		Some(LimitMargins {
+			vel: if v_ref > 0.0 { peaks[0]/v_ref } else { 0.0 },
+			acc: peaks[1]/self.limits.acc,
+			jerk: self.limits.jerk.map(|jerk| peaks[2]/jerk),
+		})
+	}
+
+	/*
+	 * Rescale the rest of the plan in time, keeping the same path in space:
+	 * factor 2.0 makes it take twice as long at half the velocity and a
+	 * quarter of the acceleration. Unless at rest, this changes the current
+	 * velocity and acceleration instantly, and a factor < 1.0 can take the
+	 * plan beyond the configured limits. Meant for slaving one axis to the
+	 * progress of another.
+	 */
+	pub fn scale_duration(&mut self, factor: f64) {
+		assert!(factor > 0.0);
+		if self.segments.is_empty() {
+			return;
+		}
+
+		// Start the plan over from now
+		let first = &self.segments[0];
+		self.segments[0] = first.part(self.time, first.get_duration());
+		self.restart_clock();
+
+		for seg in self.segments.iter_mut() {
+			*seg = seg.scaled(factor);
+		}
+		self.state[..Self::STATE_LEN].copy_from_slice(&self.segments[0].get_state_at(0.0));
+	}
+
+	/*
+	 * Rescale the rest of the plan in time to take duration seconds, see
+	 * scale_duration(). Unlike scale_duration() this keeps within the limits
+	 * (the velocity limit being the one limit_margins() uses); a duration too
+	 * short for that is handled as set by set_strictness(). The shortest
+	 * duration is exact only without acceleration bias.
+	 */
+	pub fn stretch_to(&mut self, duration: f64) -> Result<(), Error> {
+		let remaining = self.plan_report().get_duration();
+		if remaining <= 0.0 {
+			return Ok(());
+		}
+
+		// Velocity scales by 1/factor, acceleration by 1/factor², jerk by 1/factor³
+		let margins = self.limit_margins().unwrap();
+		let min_factor = margins.vel.max(margins.acc.sqrt()).max(margins.jerk.map_or(0.0, math::cbrt));
+		let factor = duration/remaining;
+		if factor >= min_factor*(1.0 - 1e-9) {
+			self.scale_duration(factor);
+			return Ok(());
+		}
+
+		if self.strictness == Strictness::Strict {
+			return Err(Error::InfeasibleDuration {
+				requested: duration,
+				achieved: remaining,
+			});
+		}
+		self.scale_duration(min_factor);
+		Err(Error::InfeasibleDuration {
+			requested: duration,
+			achieved: min_factor*remaining,
+		})
+	}
+
+	/*
+	 * Plan a move from an arbitrary (pos, vel, acc) state, e.g. a predicted
+	 * future state, without touching the current plan. Uses the same limits
+	 * and settings as replan().
+	 */
+	pub fn plan_from(&self, state: (f64, f64, f64), s_target: f64, v_limit: f64) -> Vec<Segment> {
+		let mut scratch = self.scratch_at(state);
+		scratch.replan(s_target, v_limit);
+		scratch.segments.into_iter().collect()
+	}
+
+	// Like plan_from(), for a stop
+	pub(crate) fn stop_from(&self, state: (f64, f64, f64)) -> Vec<Segment> {
+		let mut scratch = self.scratch_at(state);
+		scratch.stop();
+		scratch.segments.into_iter().collect()
+	}
+
+	/*
+	 * A new path at state with the limits and the settings plan() reads,
+	 * for plan_from() and stop_from(). Nothing else is copied: no plan,
+	 * queue, history, trace, cache or replan interval.
+	 */
+	fn scratch_at(&self, state: (f64, f64, f64)) -> Self {
+		let mut scratch = Self::with_limits(self.limits);
+		scratch.set_max_segments(self.max_segments);
+		scratch.max_plan_segments = self.max_plan_segments;
+		scratch.overshoot_policy = self.overshoot_policy;
+		scratch.acc_bias = self.acc_bias;
+		scratch.approach_acc = self.approach_acc;
+		scratch.max_dec = self.max_dec;
+		scratch.tie_break = self.tie_break;
+		scratch.last_direction = self.last_direction;
+		scratch.profile = self.profile;
+		scratch.splice_snap = self.splice_snap;
+		scratch.soft_landing = self.soft_landing;
+		scratch.velocity_zones = self.velocity_zones.clone();
+		scratch.normalize = self.normalize;
+		scratch.state[Self::STATE_LEN - 1] = state.0;
+		scratch.state[Self::STATE_LEN - 2] = state.1;
+		scratch.state[Self::STATE_LEN - 3] = state.2;
+		scratch
+	}
+
+	// Replace the plan with segments made elsewhere, starting now from the current state
+	pub(crate) fn set_plan(&mut self, segments: Vec<Segment>) {
+		assert!(segments.len() <= self.max_segments);
+		self.pending_replan = None;
+		self.queue.clear();
+		self.restart_clock();
+		self.clear_trace();
+		self.segments.clear();
+		self.segments.extend(segments);
+		self.target = self.get_end_state()[Self::STATE_LEN - 1];
+	}
+
+	/*
+	 * Peak speed of a move from the current state to s_target, with the
+	 * velocity limit of the latest replan(). E.g. to see if a process step
+	 * can be done on the fly during the move.
+	 */
+	pub fn max_reachable_velocity(&self, s_target: f64) -> f64 {
+		let segments = self.plan_from(self.get_state(), s_target, self.v_limit);
+		let peak = PlanReport::new(segments).phases.iter().map(|phase| phase.peak_vel).fold(0.0, f64::max);
+		peak.max(self.get_state().1.abs())
+	}
+
+	// The remaining segments, with the first one starting now
+	fn remaining_segments(&self) -> Vec<Segment> {
+		let mut segments: Vec<Segment> = self.segments.iter().cloned().collect();
+		if let Some(first) = segments.first_mut() {
+			*first = first.part(self.time, first.get_duration());
+		}
+		segments
+	}
+
+	// Snapshot of the remaining plan, starting now
+	pub fn plan_report(&self) -> PlanReport {
+		let mut report = PlanReport::new(self.remaining_segments());
+		report.start = self.timestamp();
+		report
+	}
+
+	// None without a plan, or for one that never ends (jog() etc)
+	pub fn phase_times(&self) -> Option<PhaseTimes> {
+		if self.segments.back().is_none_or(|seg| !seg.get_duration().is_finite()) {
+			return None;
+		}
+
+		let vel_index = Self::STATE_LEN - 2;
+		let speed = |state: [f64; MAX_STATE_LEN]| state[vel_index].abs();
+		let mut t = 0.0;
+		let mut peak = (0.0, 0.0);	// (speed, time)
+		let mut cruise = None;
+		for seg in self.played.iter().chain(self.segments.iter()) {
+			let (start, end) = (speed(seg.state_at(0.0)), speed(seg.end_state()));
+			if cruise.is_none() && start > CLOSE_ENOUGH && (end - start).abs() < CLOSE_ENOUGH {
+				cruise = Some((t, t + seg.get_duration()));
+			}
+			t += seg.get_duration();
+			if end > peak.0 {
+				peak = (end, t);
+			}
+		}
+
+		let (cruise_start, cruise_end) = cruise.unwrap_or((peak.1, peak.1));
+		Some(PhaseTimes {
+			elapsed: self.consumed + self.time,
+			accel_end: cruise_start,
+			cruise_start,
+			cruise_end,
+			decel_end: t,
+		})
+	}
+
+	// The remaining plan as JSON, see PlanReport::to_json()
+	#[cfg(feature = "serde")]
+	pub fn export_plan_json(&self) -> String {
+		self.plan_report().to_json()
+	}
+
+	/*
+	 * Position over the remaining plan as a piecewise polynomial in the
+	 * time from now, for export to other tools. Derivatives follow from
+	 * PiecewisePoly::derivative().
+	 */
+	pub fn piecewise_poly(&self) -> PiecewisePoly {
+		let segments = self.remaining_segments();
+		let mut breaks = vec![0.0];
+		for seg in &segments {
+			breaks.push(breaks.last().unwrap() + seg.get_duration());
+		}
+		PiecewisePoly::new(breaks, segments.iter().map(|seg| seg.derivative(0)).collect())
+	}
+
+	/*
+	 * Time from now until the planned position first reaches pos, e.g. to
+	 * open a gripper just before arrival. None if the plan never gets
+	 * there. The end of a segment counts as reaching its end position, but
+	 * other positions only touched at a turning point may be missed to
+	 * rounding, see Polynomial::real_roots_in().
+	 */
+	pub fn time_to_reach(&self, pos: f64) -> Option<f64> {
+		if self.get_state().0 == pos {
+			return Some(0.0);
+		}
+		let mut t_start = -self.time;
+		for seg in &self.segments {
+			let t_from = (-t_start).max(0.0);
+			let mut coeffs = seg.derivative(0).coeffs().to_vec();
+			coeffs[0] -= pos;
+			if let Some(t) = Polynomial::new(coeffs).real_roots_in(t_from, seg.get_duration()).first() {
+				return Some((t_start + t)/self.time_scale);
+			}
+			// Arriving at rest only touches pos
+			let end = *seg.get_end_state().last().unwrap();
+			if seg.get_duration().is_finite() && (end - pos).abs() < CLOSE_ENOUGH {
+				return Some((t_start + seg.get_duration())/self.time_scale);
+			}
+			t_start += seg.get_duration();
+		}
+		None
+	}
+
+	/*
+	 * When the planned position is first in range within horizon seconds
+	 * from now, and when it leaves again (horizon if it is still in range
+	 * then), e.g. to check that the motion stays out of a forbidden zone.
+	 * After the plan ends the position stays at its end. The crossings are
+	 * the roots of each segment, as in time_to_reach(), and range includes
+	 * its end here.
+	 */
+	pub fn intersects(&self, range: Range<f64>, horizon: f64) -> Option<(f64, f64)> {
+		assert!(range.start <= range.end && horizon >= 0.0);
+		let t_horizon = horizon*self.time_scale;
+		let mut crossings = vec![0.0, t_horizon];
+		let mut t_start = -self.time;
+		for seg in &self.segments {
+			if t_start >= t_horizon {
+				break;
+			}
+			let (t_from, t_to) = ((-t_start).max(0.0), seg.get_duration().min(t_horizon - t_start));
+			for bound in [range.start, range.end].iter() {
+				let mut coeffs = seg.derivative(0).coeffs().to_vec();
+				coeffs[0] -= bound;
+				crossings.extend(Polynomial::new(coeffs).real_roots_in(t_from, t_to).iter().map(|t| t_start + t));
+			}
+			t_start += seg.get_duration();
+		}
+		crossings.retain(|t| *t <= t_horizon);
+		crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+		// In or out of range between each two crossings, by the position halfway
+		let in_range = |t: f64| {
+			let pos = self.planned_pos(t);
+			range.start <= pos && pos <= range.end
+		};
+		let mut window: Option<(f64, f64)> = None;
+		for pair in crossings.windows(2) {
+			let (from, to) = (pair[0], pair[1]);
+			let inside = if to > from { in_range(0.5*(from + to)) } else { in_range(from) };
+			match (inside, &mut window) {
+				(true, None) => window = Some((from, to)),
+				(true, Some(window)) => window.1 = to,
+				(false, Some(_)) => break,
+				(false, None) => (),
+			}
+		}
+		window.map(|(enter, exit)| (enter/self.time_scale, exit/self.time_scale))
+	}
+
+	// Planned position t seconds of plan time from now; the end position after the plan ends
+	fn planned_pos(&self, t: f64) -> f64 {
+		let pos_index = Self::STATE_LEN - 1;
+		let mut t = self.time + t;
+		for seg in &self.segments {
+			if t <= seg.get_duration() {
+				return seg.state_at(t)[pos_index];
+			}
+			t -= seg.get_duration();
+		}
+		self.get_end_state()[pos_index]
+	}
+
+	/*
+	 * Total distance commanded since the path was made, counting travel in
+	 * both directions, e.g. for lubrication intervals or belt wear. Counts
+	 * what update(), update_to() and pop_completed_segments() run through;
+	 * seek() jumps around in the plan without moving anything, and
+	 * set_state() and apply_transform() move no axis either.
+	 */
+	pub fn odometer(&self) -> f64 {
+		self.odometer
+	}
+
+	// Add the distance traveled from t_from to t_to into segments[0] (and on) to the odometer
+	fn count_distance(&mut self, mut t_from: f64, mut t_to: f64) {
+		for seg in self.segments.iter() {
+			let duration = seg.get_duration();
+			if t_to > t_from && t_from < duration {
+				self.odometer += seg.distance(t_from.max(0.0), t_to.min(duration));
+			}
+			t_from -= duration;
+			t_to -= duration;
+			if t_to <= 0.0 {
+				break;
+			}
+		}
+	}
+
+	// Distance left to travel in the current plan, counting any reversals
+	pub fn distance_remaining(&self) -> f64 {
+		let mut segments = self.segments.iter();
+		let first = match segments.next() {
+			Some(seg) => seg.distance(self.time, seg.get_duration()),
+			None => return 0.0,
+		};
+		first + segments.map(|seg| seg.distance(0.0, seg.get_duration())).sum::<f64>()
+	}
+
+	/*
+	 * Check the remaining plan against what the planner promises: the state
+	 * follows the plan, the segments join up, the limits hold (unless
+	 * scale_duration() sped things up, or the plan is a replan_min_jerk(),
+	 * which ignores them) and the plan either ends at rest at the target or
+	 * in a hold segment that never ends. Meant for tests and fuzzing, see
+	 * src/fuzz.rs.
+	 */
+	pub fn checked_invariants(&self) -> Result<(), Error> {
+		let check = |invariant, segment, value: f64, limit: f64| {
+			if value.is_nan() || value > limit*(1.0 + 1e-9) + 1e-9 {
+				return Err(Error::InvariantViolation { invariant, segment, value, limit });
+			}
+			Ok(())
+		};
+		// state[0] is the input driving each segment, which is allowed to jump
+		let deviation = |state: &[f64; MAX_STATE_LEN], other: &[f64; MAX_STATE_LEN]| state[1..Self::STATE_LEN].iter()
+			.zip(&other[1..Self::STATE_LEN])
+			.map(|(val, other)| (val - other).abs())
+			.fold(0.0, f64::max);
+		let peak = |range: (f64, f64), offset: f64| (range.0 - offset).abs().max((range.1 - offset).abs());
+
+		let (first, last) = match (self.segments.front(), self.segments.back()) {
+			(Some(first), Some(last)) => (first, last),
+			_ => return Ok(()),
+		};
+		check(Invariant::CurrentState, 0, deviation(&self.state, &first.state_at(self.time)), 0.0)?;
+
+		// Braking from above the velocity or acceleration limit is fine
+		let (_, vel, acc) = self.get_state();
+		let v_max = match self.limits.jerk {
+			Some(jerk) => self.v_limit.max(vel.abs() + 0.5*acc*acc/jerk),
+			None => self.v_limit.max(vel.abs()),
+		};
+		let a_max = self.dec_limit(self.limits.acc).max(self.limits.acc).max((acc - self.acc_bias).abs());
+
+		let mut t_from = self.time;
+		for (n, seg) in self.segments.iter().enumerate() {
+			let duration = seg.get_duration();
+			if n > 0 {
+				check(Invariant::Continuity, n, deviation(&self.segments[n - 1].end_state(), &seg.state_at(0.0)), 0.0)?;
+			}
+			if duration.is_infinite() {
+				// A hold segment has a constant input and must be the last one
+				check(Invariant::Termination, n, (self.segments.len() - 1 - n) as f64, 0.0)?;
+				continue;
+			}
+			check(Invariant::Termination, n, -duration, 0.0)?;
+
+			if !self.limit_free {
+				check(Invariant::Velocity, n, peak(seg.get_range(1, t_from, duration), 0.0), v_max)?;
+				check(Invariant::Acceleration, n, peak(seg.get_range(2, t_from, duration), self.acc_bias), a_max)?;
+				if let Some(jerk) = self.limits.jerk {
+					check(Invariant::Jerk, n, peak(seg.get_range(3, t_from, duration), 0.0), jerk)?;
+				}
+			}
+			t_from = 0.0;
+		}
+
+		if last.get_duration().is_finite() {
+			let mut target_state = [0.0; MAX_STATE_LEN];
+			target_state[Self::STATE_LEN - 1] = self.target;
+			check(Invariant::Termination, self.segments.len() - 1, deviation(&last.end_state(), &target_state), 0.0)?;
+		}
+		Ok(())
+	}
+
+	fn get_end_state(&self) -> [f64; MAX_STATE_LEN] {
+		if self.segments.is_empty() {
+			return self.state;
+		}
+		self.segments.back().unwrap().end_state()
+	}
+
+	fn push_segment(&mut self, seg: Segment) {
+		assert!(self.segments.len() < self.max_segments, "Plan needs more than {} segments, see Path::set_max_segments()", self.max_segments);
+		self.segments.push_back(seg);
+	}
+
+	/*
+	 * Time-optimal plan from start to end within limits, which must include
+	 * a velocity limit. Unlike replan() the end state doesn't have to be at
+	 * rest. For an acc-limited path the accelerations are ignored, since the
+	 * acceleration can jump.
+	 *
+	 * Like calc_path_3(), change the velocity to the cruise velocity,
+	 * coast, and change it to the end velocity. If there is no room for the
+	 * coast the peak velocity is lowered: in closed form for an acc-limited
+	 * path, by bisection for a jerk-limited one. A non-zero end
+	 * acceleration is reached by a final ramp at the jerk limit.
+	 */
+	fn plan_between(start: State, end: State, limits: Limits) -> Vec<Segment> {
+		let v_limit = limits.vel.expect("plan_between() needs a velocity limit");
+		assert!(end.vel.abs() <= v_limit);
+		let mut path = Self::with_limits(limits);
+		path.set_max_segments(2*DEFAULT_MAX_SEGMENTS);
+		let pos_index = Self::STATE_LEN - 1;
+		path.state[pos_index] = start.pos;
+		path.state[pos_index - 1] = start.vel;
+		if DEGREE == 3 {
+			path.state[pos_index - 2] = start.acc;
+		}
+
+		// Aim for the start of the final ramp to end.acc
+		let (mut s_end, mut v_end) = (end.pos, end.vel);
+		let mut t_ramp = 0.0;
+		if DEGREE == 3 && end.acc != 0.0 {
+			assert!(end.acc.abs() <= limits.acc);
+			t_ramp = end.acc.abs()/path.jerk_limit();
+			v_end -= 0.5*end.acc*t_ramp;
+			s_end -= v_end*t_ramp + end.acc*t_ramp*t_ramp/6.0;
+		}
+
+		let gap = s_end - path.end_pos_via(None, v_end);
+		if gap.abs() > CLOSE_ENOUGH {
+			let dir = gap.signum();
+			let t_coast = (s_end - path.end_pos_via(Some(dir*v_limit), v_end))/(dir*v_limit);
+			if t_coast >= 0.0 {
+				path.change_velocity(dir*v_limit);
+				let mut state = path.get_end_state();
+				state[pos_index - 1] = dir*v_limit;
+				path.push_segment(Segment::new(&state[pos_index - 1..Self::STATE_LEN], t_coast, Self::STATE_LEN));
+			} else if DEGREE == 2 {
+				// Peak velocity from s_end - s0 = dir*(2*vp² - v0² - v_end²)/(2*acc)
+				let v0 = start.vel;
+				let vp_sq = dir*limits.acc*(s_end - start.pos) + 0.5*(v0*v0 + v_end*v_end);
+				path.change_velocity(dir*vp_sq.max(0.0).sqrt());
+			} else {
+				let v_rest = start.vel + 0.5*start.acc*start.acc.abs()/path.jerk_limit();
+				let mut lo = 0.0f64.max(dir*v_rest).max(dir*v_end);
+				let mut hi = v_limit;
+				for _ in 0..100 {
+					let mid = 0.5*(lo + hi);
+					if dir*(path.end_pos_via(Some(dir*mid), v_end) - s_end) > 0.0 {
+						hi = mid;
+					} else {
+						lo = mid;
+					}
+				}
+				path.change_velocity(dir*lo);
+			}
+		}
+		path.change_velocity(v_end);
+
+		if t_ramp > 0.0 {
+			let mut state = path.get_end_state();
+			state[0] = end.acc.signum()*path.jerk_limit();
+			path.push_segment(Segment::new(&state[..Self::STATE_LEN], t_ramp, Self::STATE_LEN));
+		}
+		path.segments.into_iter().collect()
+	}
+
+	// Change the velocity to v_target as fast as possible, ending with zero acceleration
+	fn change_velocity(&mut self, v_target: f64) {
+		if DEGREE == 2 {
+			self.calc_path_1(v_target);
+		} else {
+			let limits = self.ordered_limits(0.0);
+			self.calc_path_2(&limits[..DEGREE], v_target, OvershootPolicy::Allow);
+		}
+	}
+
+	// End position after changing the velocity to v_cruise (if given), then to v_end
+	fn end_pos_via(&mut self, v_cruise: Option<f64>, v_end: f64) -> f64 {
+		let len = self.segments.len();
+		if let Some(v_cruise) = v_cruise {
+			self.change_velocity(v_cruise);
+		}
+		self.change_velocity(v_end);
+		let pos = self.get_end_state()[Self::STATE_LEN - 1];
+		self.segments.truncate(len);
+		pos
+	}
+
+	/*
+	 * The quintic from the end state (acceleration zero for an acc-limited
+	 * path) to rest at s_target in time T has
+	 *
+	 * c3 = (20*h - 12*v0*T - 3*a0*T²)/(2*T³)
+	 * c4 = (-30*h + 16*v0*T + 3*a0*T²)/(2*T⁴)
+	 * c5 = (12*h - 6*v0*T - a0*T²)/(2*T⁵)
+	 *
+	 * with h = s_target - s0. Find the shortest T within the limits by
+	 * doubling and bisection; the limits are raised to the start state if
+	 * it is already beyond them.
+	 */
+	fn calc_smoothstep(&mut self, v_limit: f64, s_target: f64) {
+		let state = self.get_end_state();
+		let pos_index = Self::STATE_LEN - 1;
+		let (s0, v0) = (state[pos_index], state[pos_index - 1]);
+		let a0 = if DEGREE == 3 { state[1] } else { 0.0 };
+		let h = s_target - s0;
+		if h.abs() < CLOSE_ENOUGH && v0.abs() < CLOSE_ENOUGH && a0.abs() < CLOSE_ENOUGH {
+			return;
+		}
+
+		let quintic = |t: f64| self.min_jerk_segment(s_target, t);
+		let v_max = v_limit.max(v0.abs());
+		let a_max = self.limits.acc.max((a0 - self.acc_bias).abs());
+		let within = |seg: &Segment| {
+			let peak = |order, offset: f64| {
+				let (min, max) = seg.get_range(order, 0.0, seg.get_duration());
+				(min - offset).abs().max((max - offset).abs())
+			};
+			let tolerance = 1.0 + 1e-9;
+			peak(1, 0.0) <= v_max*tolerance && peak(2, self.acc_bias) <= a_max*tolerance
+				&& self.limits.jerk.is_none_or(|jerk| peak(3, 0.0) <= jerk*tolerance)
+		};
+
+		// Rest-to-rest duration as the first guess
+		let mut hi = (1.875*h.abs()/v_max).max((5.7735*h.abs()/a_max).sqrt()).max(v0.abs()/a_max).max(TINY_DURATION);
+		let mut lo = 0.0;
+		while !within(&quintic(hi)) {
+			lo = hi;
+			hi *= 2.0;
+		}
+		for _ in 0..100 {
+			let mid = 0.5*(lo + hi);
+			if mid <= lo || mid >= hi {
+				break;
+			}
+			if within(&quintic(mid)) {
+				hi = mid;
+			} else {
+				lo = mid;
+			}
+		}
+		self.push_segment(quintic(hi));
+	}
+
+	// The quintic of calc_smoothstep() to rest at s_target in time t
+	fn min_jerk_segment(&self, s_target: f64, t: f64) -> Segment {
+		let state = self.get_end_state();
+		let pos_index = Self::STATE_LEN - 1;
+		let (s0, v0) = (state[pos_index], state[pos_index - 1]);
+		let a0 = if DEGREE == 3 { state[1] } else { 0.0 };
+		let h = s_target - s0;
+		let coeffs = [s0, v0, 0.5*a0,
+			(20.0*h - 12.0*v0*t - 3.0*a0*t*t)/(2.0*math::powi(t, 3)),
+			(-30.0*h + 16.0*v0*t + 3.0*a0*t*t)/(2.0*math::powi(t, 4)),
+			(12.0*h - 6.0*v0*t - a0*t*t)/(2.0*math::powi(t, 5))];
+		Segment::from_poly(&coeffs, t, Self::STATE_LEN)
+	}
+
+	fn calc_path_1(&mut self, v_target: f64) {
+		let mut state = self.get_end_state();
+		let v_diff = v_target - state[1];
+		let a_max = if v_diff*state[1] < 0.0 { self.dec_limit(self.limits.acc) } else { self.limits.acc };
+		let a0 = v_diff.signum()*self.acc_limit(a_max, v_diff.signum());
+		let t0 = v_diff/a0;
+
+		if t0.abs() > TINY_DURATION {
+			state[0] = a0;
+			self.push_segment(Segment::new(&state[..Self::STATE_LEN], t0, Self::STATE_LEN));
+		}
+	}
+
+	/*
+	 * True if braking as hard as possible from v0 still takes us past a
+	 * target s_diff away.
+	 */
+	fn overshoots(v0: f64, s_diff: f64, a_max: f64) -> bool {
+		if v0.abs() < CLOSE_ENOUGH {
+			return false;
+		}
+		let s_stop = 0.5*v0*v0.abs()/a_max;
+		(s_stop - s_diff)*v0.signum() > CLOSE_ENOUGH
+	}
+
+	/*
+	 * Direction to plan in for s_diff away: back against v_rest, the
+	 * velocity once the acceleration is ramped to zero, if s_diff is exactly
+	 * zero, and by the tie-break rule if both are
+	 */
+	fn direction(&self, s_diff: f64, v_rest: f64) -> f64 {
+		if s_diff != 0.0 {
+			return s_diff.signum();
+		}
+		if v_rest != 0.0 {
+			return -v_rest.signum();
+		}
+		match self.tie_break {
+			TieBreak::PreferPrevious => self.last_direction,
+			TieBreak::PreferPositive | TieBreak::Error => 1.0,
+		}
+	}
+
+	// The tie-break rule decided the direction of a position plan
+	fn report_tie(&mut self, s_target: f64) {
+		if self.tie_break == TieBreak::Error {
+			self.error = Some(Error::DirectionTie { s_target });
+		}
+	}
+
+	fn calc_path_2(&mut self, limits: &[f64], s_target: f64, policy: OvershootPolicy) {
+		let mut state = self.get_end_state();
+		let s_diff = s_target - state[2];
+		let v0 = state[1];
+
+		/* Either limits[0] (acc-limited path) or limits[1] (jerk-limited
+		 * path, planning velocity) is the acceleration limit, which depends
+		 * on direction if there is an acceleration bias, and on whether we
+		 * brake or speed up. limit_2 is used for the final braking phase. */
+		let acc_level = DEGREE == 2;
+		let brake_limits = self.brake_limits(limits);
+		let limit_0 = |sign: f64| if acc_level {
+			let a_max = if sign*v0 < 0.0 { self.dec_limit(limits[0]) } else { limits[0] };
+			self.acc_limit(a_max, sign)
+		} else {
+			limits[0]
+		};
+		let limit_1 = |sign: f64| if acc_level { limits[1] } else { self.acc_limit(limits[1], sign) };
+		let limit_2 = |sign: f64| if acc_level { self.acc_limit(brake_limits[0], sign) } else { limits[0] };
+
+		/* With a separate braking limit the first phase can't both brake and
+		 speed up in the other direction; split it at the stop. */
+		let split_at_stop = acc_level && self.max_dec.is_some();
+
+		let overshoot = Self::overshoots(v0, s_diff, limit_0(-v0.signum()));
+		if overshoot && (policy == OvershootPolicy::Forbid || split_at_stop) {
+			// Come to a full stop first, then plan the way back from rest
+			let a0 = -v0.signum()*limit_0(-v0.signum());
+			let t0 = v0/(-a0);
+			state[0] = a0;
+			self.push_segment(Segment::new(&state[..Self::STATE_LEN], t0, Self::STATE_LEN));
+			self.trace(Branch::StopFirst, &[("s_diff", s_diff), ("v0", v0), ("t0", t0), ("a0", a0)]);
+			self.calc_path_2(limits, s_target, policy);
+			return;
+		}
+
+		/* If we overshoot, the cruise phase (if any) happens on the way back,
+		 * after passing the target. */
+		let v1_sign = if overshoot { -v0.signum() } else { self.direction(s_diff, v0) };
+		let v1_target = v1_sign*limit_1(v1_sign);
+		let v1_diff = v1_target - v0;
+
+		// Already at v1_target the first phase has no length, but the triangle below still needs its sign
+		let a0_sign = if v1_diff != 0.0 { v1_diff.signum() } else { v1_sign };
+		let mut a0 = a0_sign*limit_0(a0_sign);
+		let mut t0 = v1_diff/a0;
+
+		let a2 = -v1_target.signum()*limit_2(-v1_target.signum());
+		let mut t2 = -v1_target/a2;
+
+		log!("t0 = {}; a0 = {}; t2 = {}; a2 = {}", t0, a0, t2, a2);
+		let mut t1 = s_diff/v1_target + 0.5*math::powi(v0, 2)/(a0*v1_target) - 0.5*v1_target/a0 + 0.5*v1_target/a2;
+
+		let mut branch = if overshoot { Branch::OvershootReversal } else { Branch::Trapezoid };
+		if !overshoot && Self::overshoots(v0, s_diff, a2.abs()) {
+			/* Too fast to make it in with the approach acceleration; brake
+			 harder until we're back on the approach curve. */
+			let a_brake = limit_0(-v0.signum());
+			let a_approach = a2.abs();
+			let v_switch_sq = (2.0*a_approach*s_diff.abs() - a_approach*math::powi(v0, 2)/a_brake)/(1.0 - a_approach/a_brake);
+			let v_switch = v0.signum()*v_switch_sq.max(0.0).sqrt();
+			a0 = -v0.signum()*a_brake;
+			t0 = (v_switch - v0)/a0;
+			t1 = 0.0;
+			t2 = -v_switch/a2;
+			branch = Branch::ApproachBrake;
+		} else if t1 < 0.0 && a0*a2 > 0.0 {
+			/* Braking down to v1_target from above it, the approach
+			 acceleration is never stronger than a0, so not overshooting
+			 leaves room for the coast; t1 is a rounding error below zero. */
+			t1 = 0.0;
+		} else if t1 < 0.0 {
+			/* Solve for t0 with t1 = 0 (v_target never reached). If we
+			 overshoot, both roots are positive and the larger one is the
+			 one that takes us through zero velocity and back. */
+			let k = 1.0 - a0/a2;
+			let (count, roots) = Polynomial::quadratic_roots(0.5*a0, v0, -(0.5*math::powi(v0, 2)/a2 + s_diff)/k);
+			log!("Roots: {:?}", &roots[..count]);
+			assert_eq!(count, 2);
+			t0 = roots[1].max(0.0);
+			t1 = 0.0;
+			if t0 <= TINY_DURATION {
+				/* Braking straight to rest ends within rounding of s_target,
+				 which leaves the first phase at most a rounding error
+				 long: just brake. */
+				t0 = 0.0;
+				branch = Branch::RampDown;
+			} else if !overshoot {
+				branch = Branch::Triangle;
+			}
+			t2 = -(v0 + a0*t0)/a2;
+		}
+		self.trace(branch, &[("s_diff", s_diff), ("v0", v0), ("v1_target", v1_target),
+			("t0", t0), ("a0", a0), ("t1", t1), ("t2", t2), ("a2", a2)]);
+		if acc_level && s_diff == 0.0 && v0 == 0.0 {
+			self.report_tie(s_target);
+		}
+
+		/* Phase times solved at the boundary between two branches can come
+		 * out a rounding error below zero; there is no such phase. */
+		debug_assert!([t0, t1, t2].iter().all(|t| *t > -1e-9), "Negative phase time: t0 = {}; t1 = {}; t2 = {}", t0, t1, t2);
+		if t0 > TINY_DURATION {
+			state[0] = a0;
+			self.push_segment(Segment::new(&state[..Self::STATE_LEN], t0, Self::STATE_LEN));
+			state = self.segments.back().unwrap().end_state();
+		}
+
+		if t1 > TINY_DURATION {
+			state[1] = v1_target;
+			self.push_segment(Segment::new(&state[1..Self::STATE_LEN], t1, Self::STATE_LEN));
+			state = self.segments.back().unwrap().end_state();
+		}
+
+		if t2 > TINY_DURATION {
+			state[0] = a2;
+			self.push_segment(Segment::new(&state[..Self::STATE_LEN], t2, Self::STATE_LEN));
+		}
+
+		// Check result
+		if self.segments.is_empty() {
+			let state = self.state();
+			assert!(state[1].abs() < CLOSE_ENOUGH);
+			assert!((state[2] - s_target).abs() < CLOSE_ENOUGH, "s_target = {}; self.target = {}", s_target, state[2]);
+			return;
+		}
+
+		let state = self.segments.back().unwrap().end_state();
+		assert!(state[1].abs() < CLOSE_ENOUGH);
+		assert!(branch == Branch::RampDown || (s_target - state[2]).abs() < CLOSE_ENOUGH);
+	}
+
+	/*
+	 * Change the velocity to the velocity limit towards s_target, coast,
+	 * and ramp down to rest at s_target. Without room for the coast the
+	 * peak velocity is lowered instead, found by bisection on the end
+	 * position of changing the velocity to the peak and back to rest,
+	 * which moves on with the peak speed. The "peak" may be below v_rest,
+	 * where ramping the acceleration to zero leaves the velocity: braking
+	 * to it first and then to rest. If v_rest is away from s_target the
+	 * search starts there instead of at rest. If even the lowest peak takes
+	 * the path past s_target the move reverses, and the coast or the
+	 * lowered peak is on the way back.
+	 */
+	fn calc_path_3(&mut self, limits: &[f64], s_target: f64) {
+		let start = self.get_end_state();
+		let s_diff = s_target - start[3];
+		let v_rest = start[2] + 0.5*start[1]*start[1].abs()/limits[0];
+		let mut dir = self.direction(s_diff, v_rest);
+		if s_diff == 0.0 && v_rest == 0.0 {
+			self.report_tie(s_target);
+		}
+
+		let ramp_down_limits = self.brake_limits(limits);
+		let ramp_down_limits = &ramp_down_limits[..DEGREE];
+		if self.coast_3(limits, ramp_down_limits, s_target, dir*limits[2]) {
+			return;
+		}
+
+		let mut lo = (dir*v_rest).min(0.0);
+		if dir*(self.end_pos_via_3(limits, ramp_down_limits, dir*lo, v_rest) - s_target) > 0.0 {
+			dir = -dir;
+			if self.coast_3(limits, ramp_down_limits, s_target, dir*limits[2]) {
+				return;
+			}
+			lo = (dir*v_rest).min(0.0);
+		}
+		let mut hi = limits[2];
+		for _ in 0..100 {
+			let mid = 0.5*(lo + hi);
+			if mid <= lo || mid >= hi {
+				break;
+			}
+			if dir*(self.end_pos_via_3(limits, ramp_down_limits, dir*mid, v_rest) - s_target) > 0.0 {
+				hi = mid;
+			} else {
+				lo = mid;
+			}
+		}
+
+		self.trace(Branch::PeakLowered, &[("s_diff", s_diff), ("v_rest", v_rest), ("v_peak", dir*lo)]);
+		self.change_velocity_3(limits, dir*lo, v_rest);
+		self.calc_path_2(ramp_down_limits, 0.0, OvershootPolicy::Allow);
+	}
+
+	/*
+	 * Change the velocity to v_peak. At v_rest itself that is just ramping
+	 * the acceleration to zero, which calc_path_2() would have to find on
+	 * the edge between overshooting and not.
+	 */
+	fn change_velocity_3(&mut self, limits: &[f64], v_peak: f64, v_rest: f64) {
+		if v_peak != v_rest {
+			self.calc_path_2(limits, v_peak, OvershootPolicy::Allow);
+			return;
+		}
+		let mut state = self.get_end_state();
+		if state[1] != 0.0 {
+			state[0] = -state[1].signum()*limits[0];
+			self.push_segment(Segment::new(&state[..Self::STATE_LEN], state[1].abs()/limits[0], Self::STATE_LEN));
+		}
+	}
+
+	// Plan the move to s_target via a coast at v3_target, if there is room for it
+	fn coast_3(&mut self, limits: &[f64], ramp_down_limits: &[f64], s_target: f64, v3_target: f64) -> bool {
+		let kept = self.segments.len();
+		self.calc_path_2(limits, v3_target, OvershootPolicy::Allow);
+		let coast_index = self.segments.len();
+		self.calc_path_2(ramp_down_limits, 0.0, OvershootPolicy::Allow);
+
+		let t3 = (s_target - self.get_end_state()[3])/v3_target;
+		self.trace(if t3 >= 0.0 { Branch::Coast } else { Branch::CoastRemoved }, &[("s_target", s_target), ("v3_target", v3_target), ("t3", t3)]);
+		if t3 < 0.0 {
+			self.segments.truncate(kept);
+			return false;
+		}
+
+		self.segments.truncate(coast_index);
+		let mut state = self.get_end_state();
+		state[2] = v3_target;
+		self.push_segment(Segment::new(&state[2..Self::STATE_LEN], t3, Self::STATE_LEN));
+		self.calc_path_2(ramp_down_limits, 0.0, OvershootPolicy::Allow);
+		true
+	}
+
+	// End position of changing the velocity to v_peak and then to rest, without tracing or planning it
+	fn end_pos_via_3(&mut self, limits: &[f64], ramp_down_limits: &[f64], v_peak: f64, v_rest: f64) -> f64 {
+		let kept = self.segments.len();
+		let trace = self.trace.take();
+		self.change_velocity_3(limits, v_peak, v_rest);
+		self.calc_path_2(ramp_down_limits, 0.0, OvershootPolicy::Allow);
+		let pos = self.get_end_state()[3];
+		self.segments.truncate(kept);
+		self.trace = trace;
+		pos
+	}
+
+	/* Keep a full stop if it ends past the target, so calc_path_3() plans the
+	 * way back from rest. calc_stop_3() is the earliest stop, which bounds
+	 * the excursion past the target. */
+	fn stop_first_3(&mut self, s_target: f64) {
+		let kept = self.segments.len();
+		let state = self.get_end_state();
+		self.calc_stop_3();
+		let s_stop = self.get_end_state()[3];
+		let dir = (s_stop - state[3]).signum();
+		let excursion = dir*(s_stop - s_target);
+		if excursion > CLOSE_ENOUGH {
+			self.trace(Branch::StopFirst, &[("s_diff", s_target - state[3]), ("v0", state[2]), ("a0", state[1]), ("excursion", excursion)]);
+		} else {
+			self.segments.truncate(kept);
+		}
+	}
+
+	/*
+	 * Time-optimal stop for a jerk-limited path from any (v0, a0). Ramping
+	 * the acceleration straight to zero leaves
+	 *
+	 * v_rest = v0 + a0*|a0|/(2*J)
+	 *
+	 * so we must brake in direction d = -sgn(v_rest): ramp the acceleration
+	 * to d*a_p with jerk d*J, possibly hold it, and ramp back to zero. That
+	 * changes the velocity by d*(2*a_p² - a0²)/(2*J) + d*a_p*t_hold, which
+	 * must equal -v0. Without a hold this gives a_p directly; if a_p is
+	 * above the limit, stay at the limit for t_hold instead. If a0 already
+	 * brakes harder than the limit is now, e.g. after set_max_dec(), ramp
+	 * it back to the limit first, which leaves v_rest as it is.
+	 */
+	fn calc_stop_3(&mut self) {
+		let jerk = self.jerk_limit();
+		let mut state = self.get_end_state();
+		let a0 = state[1];
+		let v0 = state[2];
+
+		let v_rest = v0 + 0.5*a0*a0.abs()/jerk;
+		if v_rest.abs() < CLOSE_ENOUGH {
+			// Just ramp the acceleration down
+			let t0 = a0.abs()/jerk;
+			if t0 > TINY_DURATION {
+				state[0] = -a0.signum()*jerk;
+				self.push_segment(Segment::new(&state[..Self::STATE_LEN], t0, Self::STATE_LEN));
+			}
+			return;
+		}
+
+		let d = -v_rest.signum();
+		let a_max = self.acc_limit(self.dec_limit(self.limits.acc), d);
+		if d*a0 > a_max {
+			state[0] = -d*jerk;
+			self.push_segment(Segment::new(&state[..Self::STATE_LEN], (d*a0 - a_max)/jerk, Self::STATE_LEN));
+			self.calc_stop_3();
+			return;
+		}
+		let mut a_p = (0.5*math::powi(a0, 2) - jerk*d*v0).max(0.0).sqrt();
+		let mut t1 = 0.0;
+		if a_p > a_max {
+			a_p = a_max;
+			t1 = (-v0 - d*(2.0*math::powi(a_p, 2) - math::powi(a0, 2))/(2.0*jerk))/(d*a_p);
+		}
+		let t0 = (d*a_p - a0)/(d*jerk);
+		let t2 = a_p/jerk;
+
+		if t0 > TINY_DURATION {
+			state[0] = d*jerk;
+			self.push_segment(Segment::new(&state[..Self::STATE_LEN], t0, Self::STATE_LEN));
+			state = self.get_end_state();
+		}
+
+		if t1 > TINY_DURATION {
+			state[0] = 0.0;
+			state[1] = d*a_p;
+			self.push_segment(Segment::new(&state[..Self::STATE_LEN], t1, Self::STATE_LEN));
+			state = self.get_end_state();
+		}
+
+		if t2 > TINY_DURATION {
+			state[0] = -d*jerk;
+			state[1] = d*a_p;
+			self.push_segment(Segment::new(&state[..Self::STATE_LEN], t2, Self::STATE_LEN));
+		}
+
+		let state = self.get_end_state();
+		assert!(state[1].abs() < CLOSE_ENOUGH && state[2].abs() < CLOSE_ENOUGH, "stop ended in {:?}", state);
+	}
+
+	pub fn print(&self) {
+		eprintln!("{}", self);
+	}
+}
+
+impl<const DEGREE: usize> fmt::Display for Path<DEGREE> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Path: state {:?}, target {}, limits {:?}", self.state(), self.target, self.limits)?;
+		for seg in &self.segments {
+			write!(f, "\n{}", seg)?;
+		}
+		Ok(())
+	}
+}
+
+
+/*
+ * Plan from an arbitrary start state to an arbitrary end state, acc- or
+ * jerk-limited depending on limits.jerk, e.g. for blending waypoints. The
+ * segments are in the same form as from Path::plan_from().
+ */
+pub fn plan_between(start: State, end: State, limits: Limits) -> Vec<Segment> {
+	match limits.jerk {
+		None => Path::<2>::plan_between(start, end, limits),
+		Some(_) => Path::<3>::plan_between(start, end, limits),
+	}
+}
+
+
+/*
+ * Duration of a time-optimal move over s_diff, starting at v_start and
+ * ending at rest, in closed form without building the plan, e.g. for
+ * schedulers comparing many candidate moves. Exact for acc-limited limits
+ * (overshoot allowed, no bias or approach limits) and for jerk-limited ones
+ * from rest; a jerk-limited move with v_start != 0 gets the acc-limited
+ * time, which is a lower bound for it.
+ */
+pub fn estimate_duration(s_diff: f64, v_start: f64, limits: Limits) -> f64 {
+	let v_limit = limits.vel.unwrap_or(f64::INFINITY);
+	match limits.jerk {
+		Some(jerk) if v_start == 0.0 => {
+			// Time and distance to speed up from rest to vp
+			let speed_up = |vp: f64| {
+				let t = if vp >= limits.acc*limits.acc/jerk { vp/limits.acc + limits.acc/jerk } else { 2.0*(vp/jerk).sqrt() };
+				(t, 0.5*vp*t)
+			};
+			let dist = s_diff.abs();
+			let (t, s) = speed_up(v_limit);
+			if 2.0*s <= dist {
+				return 2.0*t + (dist - 2.0*s)/v_limit;
+			}
+			// Triangle in velocity, peak vp from vp*(vp/acc + acc/jerk) = dist
+			let a_j = limits.acc*limits.acc/jerk;
+			let mut vp = 0.5*(-a_j + (a_j*a_j + 4.0*limits.acc*dist).sqrt());
+			if vp < a_j {
+				// No constant-acceleration phase, vp*2*sqrt(vp/jerk) = dist
+				let x = 0.5*dist*jerk.sqrt();
+				vp = math::cbrt(x*x);
+			}
+			2.0*speed_up(vp).0
+		},
+		_ => {
+			let a = limits.acc;
+			if s_diff == 0.0 && v_start == 0.0 {
+				return 0.0;
+			}
+			// Same phases as TrapezoidPlanner::replan()
+			let s_stop = 0.5*v_start*v_start.abs()/a;
+			let dir = if (s_stop - s_diff)*v_start.signum() > 1e-12 { -v_start.signum() } else { s_diff.signum() };
+			let v1 = dir*v_limit;
+			let t0 = (v1 - v_start).abs()/a;
+			let t1 = (s_diff - 0.5*(v_start + v1)*t0 - 0.5*v1*v_limit/a)/v1;
+			if v_limit.is_finite() && t1 >= 0.0 {
+				return t0 + t1 + v_limit/a;
+			}
+			let vp = (a*dir*s_diff + 0.5*v_start*v_start).max(0.0).sqrt();
+			(dir*vp - v_start).abs()/a + vp/a
+		},
+	}
+}
+
+/*
+ * A Path with its degree chosen at runtime from the number of limits, for
+ * bindings to languages that can't use the const generic parameter.
+ */
+#[derive(Clone, Debug)]
+pub enum DynPath {
+	Acc(Path<2>),
+	Jerk(Path<3>),
+}
+
+macro_rules! dispatch {
+	($self:expr, $path:ident => $call:expr) => {
+		match $self {
+			DynPath::Acc($path) => $call,
+			DynPath::Jerk($path) => $call,
+		}
+	};
+}
+
+impl DynPath {
+	pub fn with_limits(limits: Limits) -> Self {
+		match limits.jerk {
+			None => DynPath::Acc(Path::with_limits(limits)),
+			Some(_) => DynPath::Jerk(Path::with_limits(limits)),
+		}
+	}
+
+	pub fn new_at(limits: Limits, initial_pos: f64) -> Self {
+		match limits.jerk {
+			None => DynPath::Acc(Path::new_at(limits, initial_pos)),
+			Some(_) => DynPath::Jerk(Path::new_at(limits, initial_pos)),
+		}
+	}
+
+	pub fn try_with_limits(limits: Limits) -> Result<Self, Error> {
+		limits.validate()?;
+		Ok(Self::with_limits(limits))
+	}
+
+	// Limits as for the old Path::new(): (MAX_)ACC, (JERK)
+	#[deprecated(note = "use DynPath::with_limits()")]
+	pub fn new(limits: Vec<f64>) -> Self {
+		Self::with_limits(Limits::from_slice(&limits))
+	}
+
+	pub fn replan(&mut self, s_target: f64, v_limit: f64) {
+		dispatch!(self, path => path.replan(s_target, v_limit))
+	}
+
+	pub fn stop(&mut self) {
+		dispatch!(self, path => path.stop())
+	}
+
+	pub fn update(&mut self, dt: f64) -> Progress {
+		dispatch!(self, path => path.update(dt))
+	}
+
+	pub fn update_to(&mut self, t: f64) -> Progress {
+		dispatch!(self, path => path.update_to(t))
+	}
+
+	pub fn get_state(&self) -> (f64, f64, f64) {
+		dispatch!(self, path => path.get_state())
+	}
+
+	pub fn current_jerk(&self) -> f64 {
+		dispatch!(self, path => path.current_jerk())
+	}
+
+	pub fn odometer(&self) -> f64 {
+		dispatch!(self, path => path.odometer())
+	}
+
+	pub fn is_active(&self) -> bool {
+		dispatch!(self, path => path.is_active())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Path;
+	use super::Limits;
+	use super::OvershootPolicy;
+	use super::Progress;
+	use super::CLOSE_ENOUGH;
+	use crate::segment::Segment;
+	use crate::scenario::{Action, ActionType, Scenario, check_eq, check_states_eq};
+
+	fn run_test(limits: Vec<f64>, actions: &[Action], test_name: &str) -> Result<(), String> {
+		let mut file = std::fs::File::create(test_name.to_owned() + ".data").expect("Cannot create data file");
+		let scenario = Scenario::from_actions(actions);
+		let limits = Limits::from_slice(&limits);
+		if limits.jerk.is_none() {
+			scenario.run_plotted(&mut Path::<2>::with_limits(limits), &mut file)
+		} else {
+			scenario.run_plotted(&mut Path::<3>::with_limits(limits), &mut file)
+		}
+	}
+
+	// ***
+	// *** Test acceleration-limited motion ***
+	// ***
+
+	// Zero-distance move
+	#[test]
+	fn alim_null_move() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.00, MAX_VEL)) },
+			Action { t: 0.01, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_null_move")
+	}
+
+	// Long move that reach all the limits
+	#[test]
+	fn alim_move_all_limits_pos() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.10, action: ActionType::CheckAcc(MAX_ACC) },
+			Action { t: 0.30, action: ActionType::CheckState((0.02, MAX_VEL, 0.0)) },
+			Action { t: 0.60, action: ActionType::CheckState((0.04, 0.0, 0.0)) },
+			Action { t: 0.61, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_move_all_limits_pos")
+	}
+	#[test]
+	fn alim_move_all_limits_neg() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((-0.04, MAX_VEL)) },
+			Action { t: 0.10, action: ActionType::CheckAcc(-MAX_ACC) },
+			Action { t: 0.30, action: ActionType::CheckState((-0.02, -MAX_VEL, 0.0)) },
+			Action { t: 0.60, action: ActionType::CheckState((-0.04, 0.0, 0.0)) },
+			Action { t: 0.61, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_move_all_limits_neg")
+	}
+
+	// Short, separate moves that reach a_max but not v_max
+	#[test]
+	fn alim_move_no_vmax_pos() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.2;
+		const MAX_ACC: f64 = 1.0;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.02, MAX_VEL)) },
+			Action { t: 0.30, action: ActionType::CheckState((0.02, 0.0, 0.0)) },
+			Action { t: 0.31, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_move_no_vmax_pos")
+	}
+	#[test]
+	fn alim_move_no_vmax_neg() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.2;
+		const MAX_ACC: f64 = 1.0;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((-0.02, MAX_VEL)) },
+			Action { t: 0.30, action: ActionType::CheckState((-0.02, 0.0, 0.0)) },
+			Action { t: 0.31, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_move_no_vmax_neg")
+	}
+
+	// Plan a move, then plan another one that continues in exactly the same way
+	#[test]
+	fn alim_continued_move_pos() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.30, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.60, action: ActionType::CheckState((0.04, 0.0, 0.0)) },
+			Action { t: 0.61, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_continued_move_pos")
+	}
+	#[test]
+	fn alim_continued_move_neg() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((-0.04, MAX_VEL)) },
+			Action { t: 0.30, action: ActionType::MoveTo((-0.04, MAX_VEL)) },
+			Action { t: 0.60, action: ActionType::CheckState((-0.04, 0.0, 0.0)) },
+			Action { t: 0.61, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_continued_move_neg")
+	}
+
+	// Interrupted move, higher velocity
+	#[test]
+	fn alim_inc_vel_pos() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.06, MAX_VEL)) },
+			Action { t: 0.30, action: ActionType::MoveTo((0.06, MAX_VEL*1.5)) },
+			Action { t: 0.41, action: ActionType::CheckVel(MAX_VEL*1.5) },
+			Action { t: 0.80, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_inc_vel_pos")
+	}
+
+	// Interrupted move, higher velocity, doesn't reach vmax
+	#[test]
+	fn alim_inc_vel_novmax_pos() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.05, MAX_VEL)) },
+			Action { t: 0.30, action: ActionType::MoveTo((0.05, MAX_VEL*1.5)) },
+			Action { t: 0.70, action: ActionType::CheckPos(0.05) },
+			Action { t: 0.71, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_inc_vel_novmax_pos")
+	}
+
+	// Interrupted move, lower velocity
+	#[test]
+	fn alim_dec_vel_pos() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.05, MAX_VEL)) },
+			Action { t: 0.30, action: ActionType::MoveTo((0.05, MAX_VEL*0.5)) },
+			Action { t: 1.00, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_dec_vel_pos")
+	}
+
+	// Shortened move - new command at s = 0.020 overshoots to 0.030 before going back to 0.025
+	#[test]
+	fn alim_shortened_move_pos() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.05, MAX_VEL)) },
+			Action { t: 0.30, action: ActionType::MoveTo((0.025, MAX_VEL*0.5)) },
+			Action { t: 0.80, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_shortened_move_pos")
+	}
+	#[test]
+	fn alim_shortened_move_neg() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((-0.05, MAX_VEL)) },
+			Action { t: 0.30, action: ActionType::MoveTo((-0.025, MAX_VEL*0.5)) },
+			Action { t: 0.80, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_shortened_move_neg")
+	}
+
+	// Shortened move with overshoot forbidden - brake to a stop at 0.030, then move back to 0.025
+	#[test]
+	fn alim_shortened_move_forbid_pos() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.05, MAX_VEL)) },
+			Action { t: 0.20, action: ActionType::SetOvershootPolicy(OvershootPolicy::Forbid) },
+			Action { t: 0.30, action: ActionType::MoveTo((0.025, MAX_VEL*0.5)) },
+			Action { t: 0.50, action: ActionType::CheckPos(0.03) },
+			Action { t: 0.60, action: ActionType::CheckVel(-MAX_VEL*0.5) },
+			Action { t: 0.71, action: ActionType::CheckState((0.025, 0.0, 0.0)) },
+			Action { t: 0.72, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_shortened_move_forbid_pos")
+	}
+	#[test]
+	fn alim_shortened_move_forbid_neg() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((-0.05, MAX_VEL)) },
+			Action { t: 0.20, action: ActionType::SetOvershootPolicy(OvershootPolicy::Forbid) },
+			Action { t: 0.30, action: ActionType::MoveTo((-0.025, MAX_VEL*0.5)) },
+			Action { t: 0.50, action: ActionType::CheckPos(-0.03) },
+			Action { t: 0.60, action: ActionType::CheckVel(MAX_VEL*0.5) },
+			Action { t: 0.71, action: ActionType::CheckState((-0.025, 0.0, 0.0)) },
+			Action { t: 0.72, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_shortened_move_forbid_neg")
+	}
+
+	// Shortened move while still accelerating - target is within stopping distance at s = 0.0025, v = 0.05
+	#[test]
+	fn alim_shortened_accelerating_move() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.05, MAX_VEL)) },
+			Action { t: 0.10, action: ActionType::MoveTo((0.004, MAX_VEL*2.0)) },
+			Action { t: 0.30, action: ActionType::CheckState((0.004, 0.0, 0.0)) },
+			Action { t: 0.31, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_shortened_accelerating_move")
+	}
+
+	// Vertical axis: gravity leaves less acceleration going up but more deceleration
+	#[test]
+	fn alim_acc_bias_up() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		const BIAS: f64 = -0.25;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::SetAccBias(BIAS) },
+			Action { t: 0.01, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.21, action: ActionType::CheckAcc(MAX_ACC + BIAS) },
+			Action { t: 0.46, action: ActionType::CheckState((0.025, MAX_VEL, 0.0)) },
+			Action { t: 0.60, action: ActionType::CheckAcc(-MAX_ACC + BIAS) },
+			Action { t: 0.68, action: ActionType::CheckState((0.04, 0.0, 0.0)) },
+			Action { t: 0.69, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_acc_bias_up")
+	}
+	#[test]
+	fn alim_acc_bias_down() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		const BIAS: f64 = -0.25;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::SetAccBias(BIAS) },
+			Action { t: 0.01, action: ActionType::MoveTo((-0.04, MAX_VEL)) },
+			Action { t: 0.06, action: ActionType::CheckAcc(-MAX_ACC + BIAS) },
+			Action { t: 0.61, action: ActionType::CheckAcc(MAX_ACC + BIAS) },
+			Action { t: 0.68, action: ActionType::CheckState((-0.04, 0.0, 0.0)) },
+			Action { t: 0.69, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_acc_bias_down")
+	}
+	#[test]
+	fn jlim_acc_bias_up() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		const JERK: f64 = 5.0;
+		const BIAS: f64 = -0.25;
+		let limits = vec![MAX_ACC, JERK];
+		let actions = [
+			Action { t: 0.00, action: ActionType::SetAccBias(BIAS) },
+			Action { t: 0.01, action: ActionType::MoveTo((0.06, MAX_VEL)) },
+			Action { t: 0.21, action: ActionType::CheckAcc(MAX_ACC + BIAS) },
+			Action { t: 1.00, action: ActionType::CheckState((0.06, 0.0, 0.0)) },
+			Action { t: 1.01, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "jlim_acc_bias_up")
+	}
+
+	// Safe approach: brake in with half the acceleration
+	#[test]
+	fn alim_approach_move() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::SetApproachAcc(Some(MAX_ACC*0.5)) },
+			Action { t: 0.01, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.10, action: ActionType::CheckAcc(MAX_ACC) },
+			Action { t: 0.50, action: ActionType::CheckAcc(-MAX_ACC*0.5) },
+			Action { t: 0.72, action: ActionType::CheckState((0.04, 0.0, 0.0)) },
+			Action { t: 0.73, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_approach_move")
+	}
+
+	// Safe approach with the target moving closer: brake at MAX_ACC until back on the approach curve
+	#[test]
+	fn alim_approach_shrinking_target() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::SetApproachAcc(Some(MAX_ACC*0.5)) },
+			Action { t: 0.01, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.26, action: ActionType::MoveTo((0.027, MAX_VEL)) },
+			Action { t: 0.30, action: ActionType::CheckAcc(-MAX_ACC) },
+			Action { t: 0.50, action: ActionType::CheckAcc(-MAX_ACC*0.5) },
+			Action { t: 0.55, action: ActionType::CheckState((0.027, 0.0, 0.0)) },
+			Action { t: 0.56, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_approach_shrinking_target")
+	}
+	#[test]
+	fn jlim_approach_move() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		const JERK: f64 = 5.0;
+		let limits = vec![MAX_ACC, JERK];
+		let actions = [
+			Action { t: 0.00, action: ActionType::SetApproachAcc(Some(MAX_ACC*0.5)) },
+			Action { t: 0.01, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.16, action: ActionType::CheckAcc(MAX_ACC) },
+			Action { t: 0.65, action: ActionType::CheckAcc(-MAX_ACC*0.5) },
+			Action { t: 0.82, action: ActionType::CheckState((0.04, 0.0, 0.0)) },
+			Action { t: 0.83, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "jlim_approach_move")
+	}
+
+	// Distance remaining counts the overshoot of a reversed move in both directions
+	#[test]
+	fn alim_distance_remaining() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let mut path = Path::acc_limited(MAX_ACC);
+		path.replan(0.05, MAX_VEL);
+		check_eq(0.0, path.distance_remaining(), 0.05)?;
+		path.update(0.30);
+		check_eq(0.30, path.distance_remaining(), 0.03)?;
+		path.replan(0.010, MAX_VEL*0.5);
+		check_eq(0.30, path.distance_remaining(), 0.03)?;
+		path.update(0.20);
+		check_eq(0.50, path.distance_remaining(), 0.02)?;
+		path.update(1.0);
+		check_eq(1.50, path.distance_remaining(), 0.0)
+	}
+
+	#[test]
+	fn alim_limit_margins() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let mut path = Path::acc_limited(MAX_ACC);
+		if path.limit_margins().is_some() {
+			return Err("Margins reported without a plan".to_string());
+		}
+		path.replan(0.04, MAX_VEL);
+		let margins = path.limit_margins().unwrap();
+		check_eq(0.0, margins.vel, 1.0)?;
+		check_eq(0.0, margins.acc, 1.0)?;
+		path.replan(0.002, MAX_VEL);
+		let margins = path.limit_margins().unwrap();
+		check_eq(0.0, margins.vel, (MAX_ACC*0.002).sqrt()/MAX_VEL)?;
+		if margins.jerk.is_some() {
+			return Err("Jerk margin reported for acc-limited path".to_string());
+		}
+
+		// Stopping without any velocity limit given
+		let mut path = Path::acc_limited(MAX_ACC);
+		path.set_state((0.0, MAX_VEL, 0.0));
+		path.stop();
+		check_eq(0.0, path.limit_margins().unwrap().vel, 1.0)?;
+		path.stretch_to(0.4).map_err(|err| err.to_string())?;
+		check_eq(0.0, path.plan_report().get_duration(), 0.4)?;
+		let mut path = Path::<2>::with_limits(Limits { vel: Some(2.0*MAX_VEL), ..Limits::acc_limited(MAX_ACC) });
+		path.set_state((0.0, MAX_VEL, 0.0));
+		path.stop();
+		check_eq(0.0, path.limit_margins().unwrap().vel, 0.5)
+	}
+	#[test]
+	fn jlim_limit_margins() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 10.0;
+		const JERK: f64 = 62.5;
+		let mut path = Path::jerk_limited(MAX_ACC, JERK);
+		path.replan(0.02, MAX_VEL);
+		let margins = path.limit_margins().unwrap();
+		check_eq(0.0, margins.vel, 1.0)?;
+		check_eq(0.0, margins.acc, 0.25)?;
+		check_eq(0.0, margins.jerk.unwrap(), 1.0)
+	}
+
+	// Same move with a lower acceleration limit: longer ramps, same cruise speed
+	#[test]
+	fn alim_plan_report_diff() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		let mut path_a = Path::acc_limited(0.5);
+		let mut path_b = Path::acc_limited(0.25);
+		path_a.replan(0.06, MAX_VEL);
+		path_b.replan(0.06, MAX_VEL);
+		let report_a = path_a.plan_report();
+		let report_b = path_b.plan_report();
+		let diff = report_a.diff(&report_b);
+		if diff.phases.len() != 3 {
+			return Err(format!("Expected 3 phases, got {}", diff.phases.len()));
+		}
+		check_eq(0.0, diff.duration, 0.2)?;
+		check_eq(0.0, diff.phases[0].duration, 0.2)?;
+		check_eq(0.0, diff.phases[1].duration, -0.2)?;
+		check_eq(0.0, diff.phases[2].duration, 0.2)?;
+		check_eq(0.0, diff.phases[0].peak_acc, -0.25)?;
+		check_eq(0.0, diff.phases[1].peak_vel, 0.0)?;
+		// Deviation is largest while both cruise, between t = 0.4 and 0.6
+		check_eq(0.0, diff.max_deviation, 0.01)?;
+		if diff.max_deviation_time < 0.4 - CLOSE_ENOUGH || diff.max_deviation_time > 0.6 + CLOSE_ENOUGH {
+			return Err(format!("Max deviation at unexpected time {}", diff.max_deviation_time));
+		}
+		check_eq(0.0, report_a.diff(&report_a).max_deviation, 0.0)
+	}
+
+	// Shortened move: braking from 0.1 m/s at 0.02 stops at 0.03, past the new target
+	#[test]
+	fn alim_plan_report_overshoot() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.05, MAX_VEL);
+		if let Some(overshoot) = path.plan_report().overshoot {
+			return Err(format!("Unexpected overshoot {:?}", overshoot));
+		}
+		path.update(0.3);
+		path.replan(0.025, MAX_VEL);
+		let overshoot = path.plan_report().overshoot.ok_or("Expected an overshoot")?;
+		check_eq(0.3, overshoot.pos, 0.03)?;
+		check_eq(0.3, overshoot.time, 0.2)?;
+		check_eq(0.3, overshoot.distance, 0.005)
+	}
+
+	#[test]
+	fn plan_report_shape() -> Result<(), String> {
+		use crate::report::MoveShape;
+		let check = |path: &Path<2>, shape: Option<MoveShape>| {
+			let report = path.plan_report();
+			if report.shape != shape {
+				return Err(format!("Expected {:?}, got {:?} for {:?}", shape, report.shape, report.phases));
+			}
+			Ok(())
+		};
+
+		let mut path = Path::acc_limited(0.5);
+		check(&path, None)?;
+		path.replan(0.001, 0.1);
+		check(&path, Some(MoveShape::Triangle))?;
+		path.replan(0.05, 0.1);
+		check(&path, Some(MoveShape::Trapezoid))?;
+		path.update(0.3);
+		path.replan(0.025, 0.1);
+		check(&path, Some(MoveShape::OvershootReversal))?;
+		path.stop();
+		check(&path, Some(MoveShape::StopOnly))?;
+
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		path.replan(1.0, 0.1);
+		if path.plan_report().shape != Some(MoveShape::SCurveFull) {
+			return Err(format!("Jerk-limited move: {:?}", path.plan_report().shape));
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn alim_replan_directional() -> Result<(), String> {
+		use super::Direction;
+		use crate::error::Error;
+
+		let mut path = Path::acc_limited(0.5);
+		path.replan_directional(0.04, 0.1, Direction::OnlyPositive).map_err(|err| err.to_string())?;
+		path.update(0.3);
+
+		// Stopping distance 0.01 from 0.02
+		match path.replan_directional(0.025, 0.1, Direction::OnlyPositive) {
+			Err(Error::ForbiddenDirection { reachable, .. }) => check_eq(0.3, reachable, 0.03)?,
+			result => return Err(format!("Expected a ForbiddenDirection error, got {:?}", result)),
+		}
+		let mut t = 0.3;
+		while path.is_active() {
+			path.update(0.01);
+			t += 0.01;
+			if path.get_state().1 < 0.0 {
+				return Err(format!("Moving backwards at {}: {:?}", t, path.get_state()));
+			}
+		}
+		check_states_eq(t, path.get_state(), (0.03, 0.0, 0.0))?;
+
+		path.clear_fault();
+		path.replan_directional(0.0, 0.1, Direction::OnlyNegative).map_err(|err| err.to_string())?;
+		if path.replan_directional(0.05, 0.1, Direction::OnlyNegative).is_ok() {
+			return Err("Reversal allowed".to_string());
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn alim_status_and_faults() -> Result<(), String> {
+		use super::{PathStatus, FaultKind};
+		let check = |path: &Path<2>, status: PathStatus| {
+			if path.status() != status {
+				return Err(format!("Expected {:?}, got {:?}", status, path.status()));
+			}
+			Ok(())
+		};
+
+		let mut path = Path::acc_limited(0.5);
+		check(&path, PathStatus::Idle)?;
+		path.replan(0.04, 0.1);
+		check(&path, PathStatus::Moving)?;
+		path.update(0.3);
+		path.stop();
+		check(&path, PathStatus::Stopping)?;
+		path.update(0.3);
+		check(&path, PathStatus::Idle)?;
+
+		// A bad target stops the path and latches until cleared
+		path.replan(0.0, 0.1);
+		path.update(0.1);
+		path.replan(f64::NAN, 0.1);
+		check(&path, PathStatus::Faulted(FaultKind::InvalidInput))?;
+		path.replan(0.0, 0.1);
+		path.update(1.0);
+		check_states_eq(1.0, path.get_state(), (0.025, 0.0, 0.0))?;
+		check(&path, PathStatus::Faulted(FaultKind::InvalidInput))?;
+
+		path.clear_fault();
+		check(&path, PathStatus::Idle)?;
+		path.update(-0.1);
+		check(&path, PathStatus::Faulted(FaultKind::InvalidInput))?;
+		path.clear_fault();
+		path.replan(0.0, 0.1);
+		check(&path, PathStatus::Moving)
+	}
+
+	#[test]
+	fn alim_time_to_reach() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		for (pos, time) in [(0.01, Some(0.2)), (0.02, Some(0.3)), (0.04, Some(0.6)), (0.05, None), (-0.01, None)] {
+			match (path.time_to_reach(pos), time) {
+				(Some(t), Some(time)) => check_eq(0.0, t, time)?,
+				(None, None) => {},
+				(t, _) => return Err(format!("Position {}: expected {:?}, got {:?}", pos, time, t)),
+			}
+		}
+
+		path.update(0.1);
+		check_eq(0.1, path.time_to_reach(0.01).ok_or("Never reached")?, 0.1)?;
+		path.set_time_scale(2.0);
+		check_eq(0.1, path.time_to_reach(0.01).ok_or("Never reached")?, 0.05)?;
+		if path.time_to_reach(0.001).is_some() {
+			return Err("Reached a position already passed".to_string());
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn alim_output_resolution() -> Result<(), String> {
+		const STEP: f64 = 0.001;
+		let mut path = Path::acc_limited(0.5);
+		path.set_output_resolution(Some(STEP));
+		path.replan(0.0404, 0.1);
+		let mut t = 0.0;
+		let mut steps = 0;
+		while path.is_active() {
+			path.update(0.001);
+			t += 0.001;
+			let (pos, vel, _) = path.get_output_state();
+			let exact = path.get_state();
+			if (pos - exact.0).abs() > 0.5*STEP + 1e-12 || vel != exact.1 {
+				return Err(format!("Time {:.3}: output {} too far from {:?}", t, pos, exact));
+			}
+			if (path.output_steps() - steps).abs() > 1 {
+				return Err(format!("Time {:.3}: jumped from {} to {} steps", t, steps, path.output_steps()));
+			}
+			steps = path.output_steps();
+		}
+		if steps != 40 || path.get_state().0 != 0.0404 {
+			return Err(format!("Ended at {} steps, state {:?}", steps, path.get_state()));
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn alim_move_tags() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan_tagged(0.04, 0.1, 10);
+		path.queue_move_tagged(0.0, 0.1, 11);
+		path.queue_move(0.02, 0.1);
+		if path.current_tag() != Some(10) {
+			return Err(format!("First move tagged {:?}", path.current_tag()));
+		}
+
+		let mut tags = Vec::new();
+		while path.is_active() {
+			let tag = path.update(0.01).tag;
+			if tags.last() != Some(&tag) {
+				tags.push(tag);
+			}
+		}
+		if tags != [Some(10), Some(11), None] {
+			return Err(format!("Tags {:?}", tags));
+		}
+
+		// A short command latency keeps the tagged start of the old plan
+		path.set_command_latency(0.05);
+		path.replan_tagged(0.04, 0.1, 12);
+		path.update(0.02);
+		path.replan(0.0, 0.1);
+		if path.current_tag() != Some(12) {
+			return Err(format!("Tag {:?} within the latency", path.current_tag()));
+		}
+		path.update(0.06);
+		if path.current_tag().is_some() {
+			return Err(format!("Tag {:?} after the latency", path.current_tag()));
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn plan_report_verify() -> Result<(), String> {
+		use crate::error::Invariant;
+
+		let limits = Limits { vel: Some(0.1), ..Limits::jerk_limited(0.5, 5.0) };
+		let mut path = Path::<3>::with_limits(limits);
+		path.replan(1.0, 0.1);
+		path.plan_report().verify(&limits).map_err(|err| err.to_string())?;
+
+		let slower = Limits { vel: Some(0.09), ..limits };
+		match path.plan_report().verify(&slower) {
+			Err(violation) if violation.invariant == Invariant::Velocity => check_eq(0.0, violation.value, 0.1)?,
+			result => return Err(format!("Expected a velocity violation, got {:?}", result)),
+		}
+
+		// Twice as fast is four times the acceleration
+		path.scale_duration(0.5);
+		match path.plan_report().verify(&Limits { vel: None, ..limits }) {
+			Err(violation) if violation.invariant == Invariant::Acceleration => {
+				check_eq(0.0, violation.value, 2.0)?;
+				check_eq(0.0, violation.phase as f64, 0.0)
+			},
+			result => Err(format!("Expected an acceleration violation, got {:?}", result)),
+		}
+	}
+
+	#[test]
+	fn soft_landing() -> Result<(), String> {
+		fn land<const DEGREE: usize>(mut path: Path<DEGREE>) -> Result<(), String> {
+			const WINDOW: f64 = 0.005;
+			let plain: f64 = path.plan_from((0.0, 0.0, 0.0), 0.04, 0.1).iter().map(|seg| seg.get_duration()).sum();
+			path.set_soft_landing(Some((WINDOW, 0.25)));
+			path.replan(0.04, 0.1);
+			path.checked_invariants().map_err(|err| err.to_string())?;
+			let mut t = 0.0;
+			while path.is_active() {
+				path.update(0.001);
+				t += 0.001;
+				let (pos, _, acc) = path.get_state();
+				if pos > 0.04 - WINDOW + 1e-6 && acc.abs() > 0.25*0.5 + 1e-9 {
+					return Err(format!("Acceleration {} at {} inside the window", acc, pos));
+				}
+			}
+			if t < plain + 0.1 {
+				return Err(format!("Took {} s, {} s without the soft landing", t, plain));
+			}
+			check_states_eq(t, path.get_state(), (0.04, 0.0, 0.0))
+		}
+		land(Path::acc_limited(0.5))?;
+		land(Path::jerk_limited(0.5, 5.0))
+	}
+
+	// Mirror a move halfway, then change to millimeters: same timing, mapped end
+	#[test]
+	fn alim_apply_transform() -> Result<(), String> {
+		use super::Transform;
+
+		let mut path = Path::acc_limited(0.5);
+		path.set_travel_limits(Some((-0.1, 0.09)));
+		path.replan(0.04, 0.1);
+		path.update(0.3);
+		path.apply_transform(Transform::Mirror(0.01));
+		check_states_eq(0.3, path.get_state(), (0.0, -0.1, 0.0))?;
+		path.apply_transform(Transform::Offset(-0.02));
+		path.apply_transform(Transform::Scale(1000.0));
+		check_states_eq(0.3, path.get_state(), (-20.0, -100.0, 0.0))?;
+		check_eq(0.3, path.update(0.0).remaining, 0.3)?;
+		if path.travel_limits != Some((-90.0, 100.0)) || path.limits.acc != 500.0 {
+			return Err(format!("Settings not transformed: {:?}, {:?}", path.travel_limits, path.limits));
+		}
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_states_eq(0.6, path.get_state(), (-40.0, 0.0, 0.0))
+	}
+
+	// Zero distance left: moving, turn back; at rest, the direction follows the tie-break, not the sign bit
+	#[test]
+	fn alim_tie_break() -> Result<(), String> {
+		use super::TieBreak;
+		use crate::error::Error;
+
+		let mut path = Path::acc_limited(0.5);
+		path.set_trace(true);
+		let v1_target = |path: &mut Path<2>, s_target: f64, vel: f64, tie_break: TieBreak| {
+			path.set_tie_break(tie_break);
+			path.set_state((s_target + 0.0, vel, 0.0));
+			path.replan(s_target, 0.1);
+			path.plan_trace().unwrap().steps[0].get("v1_target").unwrap()
+		};
+
+		// -0.0 - 0.0 is -0.0, which signum() takes as negative
+		if v1_target(&mut path, -0.0, 0.0, TieBreak::PreferPositive) != 0.1 {
+			return Err(format!("Expected positive: {:?}", path.plan_trace()));
+		}
+		// Too slow to overshoot by more than CLOSE_ENOUGH, but still moving
+		if v1_target(&mut path, 0.0, 2e-12, TieBreak::Error) != -0.1 || path.take_error().is_some() {
+			return Err(format!("Expected to turn back: {:?}", path.plan_trace()));
+		}
+		path.set_state((0.0, 0.0, 0.0));
+		path.replan(-0.04, 0.1);
+		if v1_target(&mut path, -0.04, 0.0, TieBreak::PreferPrevious) != -0.1 {
+			return Err(format!("Expected the previous, negative direction: {:?}", path.plan_trace()));
+		}
+		if v1_target(&mut path, -0.04, 0.0, TieBreak::Error) != 0.1 || path.take_error() != Some(Error::DirectionTie { s_target: -0.04 }) {
+			return Err(format!("Expected positive and an error: {:?}", path.plan_trace()));
+		}
+		Ok(())
+	}
+
+	// Zero and NaN limits are refused with an error, leaving a running path as it was
+	#[test]
+	fn alim_invalid_limits() -> Result<(), String> {
+		use super::DynPath;
+		use crate::error::Error;
+
+		if Path::<2>::try_with_limits(Limits::acc_limited(0.0)).err() != Some(Error::InvalidLimit { name: "acc", value: 0.0 }) {
+			return Err("Expected a zero acc limit to be refused".to_string());
+		}
+		match DynPath::try_with_limits(Limits::jerk_limited(0.5, f64::NAN)) {
+			Err(Error::InvalidLimit { name: "jerk", value }) if value.is_nan() => (),
+			result => return Err(format!("Expected a NaN jerk limit to be refused, got {:?}", result.err())),
+		}
+		match DynPath::try_with_limits(Limits { vel: Some(f64::INFINITY), ..Limits::acc_limited(0.5) }) {
+			Ok(DynPath::Acc(_)) => (),
+			result => return Err(format!("Expected an unlimited acc-limited path, got {:?}", result)),
+		}
+
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		path.update(0.3);
+		if path.set_limits(Limits { vel: Some(0.0), ..Limits::acc_limited(0.5) }) != Err(Error::InvalidLimit { name: "vel", value: 0.0 }) {
+			return Err("Expected a zero vel limit to be refused".to_string());
+		}
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_states_eq(0.6, path.get_state(), (0.04, 0.0, 0.0))
+	}
+
+	// Back into the speed-up, on from there, then forward into the braking and to the end
+	#[test]
+	fn alim_seek() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		path.update(0.45);
+		path.seek(0.1);
+		check_states_eq(0.1, path.get_state(), (0.0025, 0.05, 0.5))?;
+		check_eq(0.1, path.update(0.2).remaining, 0.3)?;
+		check_states_eq(0.3, path.get_state(), (0.02, 0.1, 0.0))?;
+
+		path.seek(0.55);
+		check_states_eq(0.55, path.get_state(), (0.039375, 0.025, -0.5))?;
+		let now = path.timestamp();
+		if !path.update_to(now + 0.05).finished || path.get_state() != (0.04, 0.0, 0.0) {
+			return Err(format!("Expected the move to finish: {}", path));
+		}
+		Ok(())
+	}
+
+	// An hour of 10 ms updates into a cruise still gives the exact position
+	#[test]
+	fn alim_hour_long_cruise() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(360.02, 0.1);
+		path.update(0.2);
+		let (dt, steps) = (0.01, 360_000);
+		for _ in 0..steps {
+			path.update(dt);
+		}
+		let t = steps as f64*dt;
+		let pos = path.get_state().0;
+		if (pos - (0.01 + 0.1*t)).abs() > 1e-10 {
+			return Err(format!("At {} s into the cruise: {}, expected {}", t, pos, 0.01 + 0.1*t));
+		}
+		Ok(())
+	}
+
+	// A zone passed through during the cruise, one reached at the end, and one never reached
+	#[test]
+	fn alim_intersects() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		path.update(0.1);
+		match path.intersects(0.015..0.025, 1.0) {
+			Some((enter, exit)) if (enter - 0.15).abs() < 1e-9 && (exit - 0.25).abs() < 1e-9 => (),
+			window => return Err(format!("Expected (0.15, 0.25), got {:?}", window)),
+		}
+		match path.intersects(0.035..0.05, 1.0) {
+			// 0.005 before the end is sqrt(2*0.005/a) of braking left
+			Some((enter, exit)) if (enter - (0.5 - 0.02f64.sqrt())).abs() < 1e-9 && exit == 1.0 => (),
+			window => return Err(format!("Expected to enter 0.141 s before the end, got {:?}", window)),
+		}
+		if let Some(window) = path.intersects(0.015..0.025, 0.04).or(path.intersects(-0.1..0.0, 1.0)) {
+			return Err(format!("Expected no intersection, got {:?}", window));
+		}
+		Ok(())
+	}
+
+	// Brake from 0.1 to 0.05 during the cruise, and cruise on to the same target
+	#[test]
+	fn alim_slow_to() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan_tagged(0.04, 0.1, 7);
+		path.update(0.25);
+		path.slow_to(0.05);
+		path.slow_to(0.2);
+		// 0.0075 to brake to 0.05 and 0.0025 to stop, leaving 0.015 of the 0.025 left to cruise
+		check_eq(0.25, path.update(0.0).remaining, 0.1 + 0.3 + 0.1)?;
+		if path.current_tag() != Some(7) {
+			return Err(format!("Tag {:?} after slowing down", path.current_tag()));
+		}
+		path.update(0.2);
+		check_states_eq(0.45, path.get_state(), (0.0275, 0.05, 0.0))?;
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_states_eq(0.8, path.get_state(), (0.04, 0.0, 0.0))
+	}
+
+	// Exactly the given duration, beyond the limits, and the next move time-optimal again
+	#[test]
+	fn alim_min_jerk() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan_min_jerk(1.0, 2.0);
+		path.checked_invariants().map_err(|err| err.to_string())?;
+		check_eq(0.0, path.update(1.0).remaining, 1.0)?;
+		check_states_eq(1.0, path.get_state(), (0.5, 0.9375, 0.0))?;
+		if !path.update(1.01).finished || path.get_state() != (1.0, 0.0, 0.0) {
+			return Err(format!("Expected the move done: {}", path));
+		}
+
+		path.replan(1.04, 0.1);
+		check_eq(2.01, path.update(0.0).remaining, 0.6)
+	}
+
+	// Gains and time scale applied to the planned derivatives
+	#[test]
+	fn jlim_feedforward() -> Result<(), String> {
+		use super::FeedforwardGains;
+
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		path.replan(1.0, 0.1);
+		path.update(0.05);
+		let (_, vel, acc) = path.get_state();
+		check_states_eq(0.05, path.feedforward(), (vel, acc, 5.0))?;
+
+		path.set_feedforward_gains(FeedforwardGains { vel: 2.0, acc: 0.5, jerk: 0.0 });
+		path.set_time_scale(2.0);
+		check_states_eq(0.05, path.feedforward(), (4.0*vel, 2.0*acc, 0.0))
+	}
+
+	// The commanded jerk has the sign of the change of acceleration, also on the way to a negative target
+	#[test]
+	fn jlim_current_jerk() -> Result<(), String> {
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		path.replan(-1.0, 0.1);
+		let dt = 0.001;
+		let mut t = 0.0;
+		let mut jerks = Vec::new();
+		while path.is_active() {
+			let jerk = path.current_jerk();
+			let acc = path.get_state().2;
+			path.update(dt);
+			t += dt;
+			// Steps across a phase boundary see the mean jerk
+			let slope = (path.get_state().2 - acc)/dt;
+			if (slope - jerk).abs() > 1e-6 && path.current_jerk() == jerk && path.is_active() {
+				return Err(format!("At {}: jerk {} but the acceleration changes by {}/s", t, jerk, slope));
+			}
+			if jerks.last() != Some(&jerk) {
+				jerks.push(jerk);
+			}
+		}
+		if jerks != [-5.0, 0.0, 5.0, 0.0, 5.0, 0.0, -5.0] || path.current_jerk() != 0.0 {
+			return Err(format!("Jerk phases {:?}", jerks));
+		}
+
+		let mut path = Path::acc_limited(0.5);
+		path.replan(1.0, 0.1);
+		path.update(0.1);
+		check_eq(0.1, path.current_jerk(), 0.0)
+	}
+
+	// The odometer counts both ways of a move reversed mid-move
+	#[test]
+	fn alim_odometer() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.05, 0.1);
+		path.update(0.3);
+		path.replan(0.01, 0.05);
+		let mut s_max = 0.0f64;
+		while path.is_active() {
+			path.update(0.001);
+			s_max = s_max.max(path.get_state().0);
+		}
+		check_eq(1.0, path.odometer(), 2.0*s_max - 0.01)?;
 
-		eprintln!("Path::replan(), state {:?}, s_target {}, limits {:?}", self.state, s_target, limits);
-		assert!(v_limit > 0.0);
+		// Seeking back and forth isn't motion, pop_completed_segments() is
+		let mut path = Path::acc_limited(0.5);
+		path.replan(-0.04, 0.1);
+		path.update(0.1);
+		path.seek(0.5);
+		path.seek(0.0);
+		check_eq(0.1, path.odometer(), 0.0025)?;
+		path.take_next_segment();
+		path.pop_completed_segments(1);
+		check_eq(0.2, path.odometer(), 0.0025 + 0.01)
+	}
 
-		self.time = 0.0;
-		self.segments.clear();
+	// Phases of a trapezoid part of the way in, and of a triangle
+	#[test]
+	fn alim_phase_times() -> Result<(), String> {
+		let check = |path: &Path<2>, expected: [f64; 5]| {
+			let times = path.phase_times().ok_or("No phase times")?;
+			let got = [times.elapsed, times.accel_end, times.cruise_start, times.cruise_end, times.decel_end];
+			if got.iter().zip(expected.iter()).any(|(got, expected)| (got - expected).abs() > 1e-9) {
+				return Err(format!("Expected {:?}, got {:?}", expected, times));
+			}
+			Ok(())
+		};
 
-		if self.limits.len() == 1 {
-			// Acc-limited path
-			self.calc_path_2(&limits, s_target);
-		} else /* self.limits.len() == 2 */ {
-			// Jerk-limited path
-			self.calc_path_3(&limits, s_target);
+		let mut path = Path::acc_limited(0.5);
+		if path.phase_times().is_some() {
+			return Err("Phase times without a plan".to_string());
 		}
+		path.replan(0.04, 0.1);
+		path.update(0.25);
+		check(&path, [0.25, 0.2, 0.2, 0.4, 0.6])?;
 
-		self.target = s_target;
+		// Peak speed sqrt(a*s) = sqrt(0.005) after sqrt(s/a) = sqrt(0.02)
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.01, 0.1);
+		let t_peak = 0.02f64.sqrt();
+		check(&path, [0.0, t_peak, t_peak, t_peak, 2.0*t_peak])
 	}
 
-	pub fn stop(&mut self) {
-		eprintln!("Path::stop(), state {:?}", self.state);
+	// Starting away from zero: idle there, and moves end exactly at their targets
+	#[test]
+	fn alim_new_at() -> Result<(), String> {
+		let mut path = Path::<2>::new_at(Limits::acc_limited(0.5), -0.5);
+		path.enable_hold_correction(0.0);
+		path.update(0.1);
+		if path.is_active() || path.take_error().is_some() || path.get_state() != (-0.5, 0.0, 0.0) {
+			return Err(format!("Expected at rest at -0.5: {}", path));
+		}
+		path.replan(-0.46, 0.1);
+		check_eq(0.0, path.update(0.0).remaining, 0.6)?;
+		path.update(0.3);
+		check_states_eq(0.3, path.get_state(), (-0.48, 0.1, 0.0))?;
+		while path.is_active() {
+			path.update(0.01);
+		}
+		if path.get_state() != (-0.46, 0.0, 0.0) {
+			return Err(format!("Ended in {:?}", path.get_state()));
+		}
+		Ok(())
+	}
 
-		self.time = 0.0;
-		self.segments.clear();
+	// A full queue refuses moves, and a zoned plan over the limit falls back to the plain one
+	#[test]
+	fn alim_guardrails() -> Result<(), String> {
+		use crate::error::Error;
 
-		if self.limits.len() == 1 {
-			// Acc-limited path
-			self.calc_path_1(0.0);
-		} else /* self.limits.len() == 2 */ {
-			// Jerk-limited path
-			self.calc_path_2(&self.limits.clone(), 0.0);
+		let mut path = Path::acc_limited(0.5);
+		path.set_max_queue_depth(2);
+		for s_target in [0.04, 0.08, 0.12, 0.16] {
+			path.queue_move(s_target, 0.1);
+		}
+		if path.queued_moves() != 2 || path.take_error() != Some(Error::QueueFull { depth: 2 }) {
+			return Err(format!("Expected 2 queued moves and a full queue, got {}", path.queued_moves()));
 		}
 
-		if !self.segments.is_empty() {
-			let end_state = self.segments.back().unwrap().get_end_state();
-
-			// Accept whatever position we end up at as target
-			self.target = *end_state.last().unwrap();
-		} else {
-			self.target = *self.state.last().unwrap();
+		let mut path = Path::acc_limited(0.5);
+		path.set_max_plan_segments(4);
+		path.set_velocity_zones(&[(0.3..0.5, 0.05)]);
+		path.replan(1.0, 0.2);
+		match path.take_error() {
+			Some(Error::PlanTooLong { segments, limit: 4 }) if segments > 4 => (),
+			error => return Err(format!("Expected PlanTooLong, got {:?}", error)),
+		}
+		if path.update(0.0).remaining != path.plan_from((0.0, 0.0, 0.0), 1.0, 0.2).iter().map(|seg| seg.get_duration()).sum::<f64>() {
+			return Err(format!("Expected the plan without zones: {}", path));
 		}
+		Ok(())
 	}
 
-	pub fn update(&mut self, dt: f64) {
-		assert!(dt >= 0.0);
-		if self.segments.is_empty() {
-			// No movement planned - nothing to do
-			return;
+	// Lagging feedback during a move, then a steady offset corrected at the end
+	#[test]
+	fn alim_report_measured() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.set_settle_correction(Some(0.0002));
+		path.replan(0.04, 0.1);
+		for _ in 0..61 {
+			path.update(0.01);
+			let (pos, vel, _) = path.get_state();
+			path.report_measured(pos - 0.001, vel);
+		}
+		let stats = path.tracking_stats();
+		check_eq(0.0, stats.max_pos_error, 0.001)?;
+		check_eq(0.0, stats.rms_pos_error(), 0.001)?;
+		if stats.settle_error.is_none() || stats.corrections != 1 || !path.is_active() {
+			return Err(format!("Expected a correction: {:?}", stats));
 		}
 
-		self.time += dt;
-		while self.time > self.segments[0].get_duration() {
-			let seg = self.segments.pop_front().unwrap();
-			self.time -= seg.get_duration();
-			if self.segments.is_empty() {
-				// Zero out any accumulated inaccuracies
-				self.state = vec![0.0; self.limits.len() + 1];
-				self.state.push(self.target);
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_eq(0.0, path.get_state().0, 0.041)?;
+		path.report_measured(0.04, 0.0);
+		let stats = path.tracking_stats();
+		if stats.settle_error != Some(0.0) || stats.corrections != 1 || path.is_active() {
+			return Err(format!("Expected settled after one correction: {:?}", stats));
+		}
+		Ok(())
+	}
 
-				break;
+	// The same moves in meters and in nanometers, normalized
+	#[test]
+	fn alim_normalization() -> Result<(), String> {
+		const NM: f64 = 1e9;
+		let mut path = Path::acc_limited(0.5);
+		let mut nm_path = Path::acc_limited(0.5*NM);
+		nm_path.set_normalization(true);
+		let moves = [(0.0, 0.04), (0.3, 0.021), (0.8, -0.003)];
+		let mut t = 0.0;
+		for step in 0..2000 {
+			for (t_move, s_target) in moves {
+				if step == (t_move/0.001f64).round() as usize {
+					path.replan(s_target, 0.1);
+					nm_path.replan(s_target*NM, 0.1*NM);
+					if nm_path.segments.len() != path.segments.len() {
+						return Err(format!("Segments differ at {}:\n{}\n{}", t, path, nm_path));
+					}
+				}
 			}
+			path.update(0.001);
+			nm_path.update(0.001);
+			t += 0.001;
+			let (pos, vel, acc) = nm_path.get_state();
+			check_states_eq(t, (pos/NM, vel/NM, acc/NM), path.get_state())?;
+		}
+		if nm_path.get_state() != (-0.003*NM, 0.0, 0.0) {
+			return Err(format!("Ended in {:?}", nm_path.get_state()));
 		}
+		Ok(())
+	}
 
-		if !self.segments.is_empty() {
-			self.state = self.segments[0].get_state_at(self.time);
+	// Slow zone across the middle of the move, and one around the target
+	#[test]
+	fn velocity_zones() -> Result<(), String> {
+		fn run<const DEGREE: usize>(mut path: Path<DEGREE>) -> Result<(), String> {
+			let zones = [(0.3..0.5, 0.05), (0.9..1.2, 0.1)];
+			let plain: f64 = path.plan_from((0.0, 0.0, 0.0), 1.0, 0.2).iter().map(|seg| seg.get_duration()).sum();
+			path.set_velocity_zones(&zones);
+			path.replan(1.0, 0.2);
+			path.checked_invariants().map_err(|err| err.to_string())?;
+			let mut t = 0.0;
+			let mut v_peak: f64 = 0.0;
+			while path.is_active() {
+				path.update(0.001);
+				t += 0.001;
+				let (pos, vel, _) = path.get_state();
+				for (range, v_max) in &zones {
+					if range.contains(&pos) && vel.abs() > v_max + 1e-9 {
+						return Err(format!("Velocity {} at {}, limit {}", vel, pos, v_max));
+					}
+				}
+				v_peak = v_peak.max(vel);
+			}
+			if t < plain || v_peak < 0.2 - 1e-9 {
+				return Err(format!("Took {} s ({} s without zones), peak velocity {}", t, plain, v_peak));
+			}
+			check_states_eq(t, path.get_state(), (1.0, 0.0, 0.0))
 		}
+		run(Path::acc_limited(0.5))?;
+		run(Path::jerk_limited(0.5, 5.0))
 	}
 
-	pub fn get_state(&self) -> (f64, f64, f64) {
-		let pos_index = self.state.len() - 1;
-		(self.state[pos_index], self.state[pos_index - 1], self.state[pos_index - 2])
+	// Streamed targets while cruising reuse the plan, with the same states as without the cache
+	#[test]
+	fn plan_cache() -> Result<(), String> {
+		fn stream<const DEGREE: usize>(mut path: Path<DEGREE>) -> Result<(), String> {
+			let mut plain = path.clone();
+			path.set_cache(true);
+			let targets = [1.0, 0.8, 0.7, 0.65, 0.62, 0.61];
+			let mut t = 0.0;
+			for s_target in targets {
+				path.replan(s_target, 0.1);
+				plain.replan(s_target, 0.1);
+				for _ in 0..500 {
+					path.update(0.001);
+					plain.update(0.001);
+					t += 0.001;
+					check_states_eq(t, path.get_state(), plain.get_state())?;
+				}
+			}
+			let metrics = path.metrics();
+			// The first replan is from rest, the second the first from the cruise
+			if metrics.cache_hits != 4 || metrics.cache_misses != 2 || metrics.cache_hit_rate() != Some(4.0/6.0) {
+				return Err(format!("Expected 4 hits and 2 misses: {:?}", metrics));
+			}
+			if plain.metrics().cache_hit_rate().is_some() {
+				return Err("Cache counted while off".to_string());
+			}
+			while path.is_active() {
+				path.update(0.01);
+			}
+			check_states_eq(t, path.get_state(), (0.61, 0.0, 0.0))
+		}
+		stream(Path::acc_limited(0.5))?;
+		stream(Path::jerk_limited(0.5, 5.0))
 	}
 
-	pub fn is_active(&self) -> bool {
-		!self.segments.is_empty()
+	#[test]
+	fn alim_epoch() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.set_epoch(100.0);
+		path.enable_history(10);
+		path.replan(0.04, 0.1);
+		check_eq(0.0, path.plan_report().start, 100.0)?;
+		check_eq(0.1, path.update(0.1).time, 100.1)?;
+		check_eq(0.1, path.plan_report().start, 100.1)?;
+		let (t, _) = path.history().next().ok_or("Nothing recorded")?;
+		check_eq(0.1, t, 100.1)?;
+
+		// update_to() takes the path's own clock
+		check_eq(0.2, path.update_to(0.2).time, 100.2)
 	}
 
-	fn get_end_state(&self) -> Vec<f64> {
-		if self.segments.is_empty() {
-			return self.state.clone();
+	// Ramp the acceleration up at the jerk limit, hold it, then ramp it back down
+	#[test]
+	fn jlim_ramp_acceleration() -> Result<(), String> {
+		let mut path = Path::jerk_limited(1.0, 10.0);
+		path.ramp_acceleration_to(0.5);
+		path.update(0.05);
+		check_states_eq(0.05, path.get_state(), (10.0/6.0*0.05f64.powi(3), 0.0125, 0.5))?;
+		path.update(0.1);
+		check_states_eq(0.15, path.get_state(), (0.0039583333333333, 0.0625, 0.5))?;
+		path.ramp_acceleration_to(0.0);
+		path.update(0.05);
+		check_states_eq(0.20, path.get_state(), (0.0075, 0.075, 0.0))?;
+		path.update(1.0);
+		if !path.is_active() {
+			return Err("Path stopped holding its velocity".to_string());
 		}
-		self.segments.back().unwrap().get_end_state()
+		check_states_eq(1.20, path.get_state(), (0.0825, 0.075, 0.0))
 	}
 
-	fn calc_path_1(&mut self, v_target: f64) {
-		let mut state = self.get_end_state();
-		let v_diff = v_target - state[1];
-		let a0 = v_diff.signum()*self.limits[0];
-		let t0 = v_diff/a0;
+	// Braking at twice the acceleration: 0.2 s speeding up, 0.25 s cruising, 0.1 s braking
+	#[test]
+	fn alim_max_dec() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::SetMaxDec(Some(2.0*MAX_ACC)) },
+			Action { t: 0.01, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.11, action: ActionType::CheckAcc(MAX_ACC) },
+			Action { t: 0.31, action: ActionType::CheckState((0.02, MAX_VEL, 0.0)) },
+			Action { t: 0.51, action: ActionType::CheckState((0.03875, 0.5*MAX_VEL, -2.0*MAX_ACC)) },
+			Action { t: 0.57, action: ActionType::CheckState((0.04, 0.0, 0.0)) },
+			Action { t: 0.58, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_max_dec")
+	}
 
-		let degree = self.limits.len() + 1;
-		if t0.abs() > TINY_DURATION {
-			state[0] = a0;
-			self.segments.push_back(Segment::new(&state[..], t0, degree + 1));
+	// Reversing with a weaker brake: stop at the braking limit, then speed up at MAX_ACC
+	#[test]
+	fn alim_max_dec_reverse() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::SetMaxDec(Some(0.5*MAX_ACC)) },
+			Action { t: 0.01, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.31, action: ActionType::MoveTo((0.0, MAX_VEL)) },
+			Action { t: 0.41, action: ActionType::CheckAcc(-0.5*MAX_ACC) },
+			Action { t: 0.71, action: ActionType::CheckState((0.04, 0.0, -0.5*MAX_ACC)) },
+			Action { t: 0.81, action: ActionType::CheckVel(-MAX_ACC*0.1) },
+			Action { t: 1.42, action: ActionType::CheckPos(0.0) },
+			Action { t: 1.43, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_max_dec_reverse")
+	}
+
+	// Weaker brake on a jerk-limited path: 0.3 s speeding up, 0.025 s cruising, 0.45 s braking
+	#[test]
+	fn jlim_max_dec() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		const JERK: f64 = 5.0;
+		let limits = vec![MAX_ACC, JERK];
+		let actions = [
+			Action { t: 0.00, action: ActionType::SetMaxDec(Some(0.5*MAX_ACC)) },
+			Action { t: 0.01, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.16, action: ActionType::CheckAcc(MAX_ACC) },
+			Action { t: 0.41, action: ActionType::CheckAcc(-0.5*MAX_ACC) },
+			Action { t: 0.79, action: ActionType::CheckState((0.04, 0.0, 0.0)) },
+			Action { t: 0.80, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "jlim_max_dec")
+	}
+
+	// The exported spline follows the plan, breaks at the phase boundaries
+	#[test]
+	fn alim_piecewise_poly() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, MAX_VEL);
+		path.update(0.1);
+		let pp = path.piecewise_poly();
+		if pp.breaks.len() != 4 {
+			return Err(format!("Expected 4 breaks, got {:?}", pp.breaks));
 		}
+		check_eq(0.0, pp.breaks[1], 0.1)?;
+		check_eq(0.0, pp.breaks[3], 0.5)?;
+		let vel = pp.derivative();
+		check_eq(0.0, pp.eval(0.0), 0.0025)?;
+		check_eq(0.2, pp.eval(0.2), 0.02)?;
+		check_eq(0.2, vel.eval(0.2), MAX_VEL)?;
+		check_eq(0.4, vel.eval(0.4), 0.5*MAX_VEL)?;
+		check_eq(0.5, pp.eval(0.5), 0.04)
 	}
 
-	fn calc_path_2(&mut self, limits: &Vec<f64>, s_target: f64) {
-		let mut state = self.get_end_state();
-		let s_diff = s_target - state[2];
-		let v0 = state[1];
+	// Replans within 0.1 s of the previous one are held back, and only the latest is used
+	#[test]
+	fn alim_min_replan_interval() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		let mut path = Path::acc_limited(0.5);
+		path.set_min_replan_interval(0.1);
+		path.replan(0.04, MAX_VEL);
+		path.update(0.02);
+		path.replan(0.02, MAX_VEL);
+		path.update(0.03);
+		path.replan(0.03, MAX_VEL);
+		path.update(0.049);
+		check_eq(0.099, path.target, 0.04)?;
+		path.update(0.001);
+		check_eq(0.1, path.target, 0.03)?;
+		path.replan(0.01, MAX_VEL);
+		path.stop();
+		path.update(0.1);
+		if path.pending_replan.is_some() || path.target > 0.03 {
+			return Err(format!("Replan after stop(), target {}", path.target));
+		}
+		Ok(())
+	}
 
-		let v1_target = s_diff.signum()*limits[1];
-		let v1_diff = v1_target - v0;
+	// A position jump between segments shows up in the residual, and is removed when over the bound
+	#[test]
+	fn alim_numerical_residual() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		for bound in [None, Some(1e-6)] {
+			let mut path = Path::acc_limited(0.5);
+			path.set_residual_bound(bound);
+			path.replan(0.04, MAX_VEL);
+			let cruise = &path.segments[1];
+			path.segments[1] = Segment::new(&[0.0, MAX_VEL, 0.0101], cruise.get_duration(), 3);
+			path.update(0.25);
+			match bound {
+				None => {
+					check_eq(0.25, path.numerical_residual(), 1e-4)?;
+					check_eq(0.25, path.get_state().0, 0.0151)?;
+				},
+				Some(_) => {
+					check_eq(0.25, path.numerical_residual(), 0.0)?;
+					check_eq(0.25, path.get_state().0, 0.015)?;
+				},
+			}
+		}
 
-//		println!("calc_path_2(): s_diff = {}; v1_target = {}", s_diff, v1_target);
+		// Re-chaining keeps the tag and the quintic terms of a Smoothstep move
+		let mut path = Path::<2>::with_profile(Limits::acc_limited(0.5), super::Profile::Smoothstep);
+		path.set_residual_bound(Some(1e-6));
+		path.replan_tagged(0.04, MAX_VEL, 7);
+		let half = 0.5*path.segments[0].get_duration();
+		path.segments.push_front(Segment::new(&[0.0, 0.0, -0.001], 0.1, 3).with_tag(Some(7)));
+		path.update(0.1 + half);
+		if path.current_tag() != Some(7) {
+			return Err(format!("Tag {:?} after re-chaining", path.current_tag()));
+		}
+		check_eq(0.1 + half, path.get_state().0, 0.019)
+	}
 
-		let a0 = v1_diff.signum()*limits[0];
-		let mut t0 = v1_diff/a0;
+	// Progress reports each segment change and the end of the move once
+	#[test]
+	fn alim_update_progress() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		let progress = path.update(0.1);
+		if progress.completed_segments != 0 || progress.finished {
+			return Err(format!("Unexpected progress {:?}", progress));
+		}
+		check_eq(0.1, progress.remaining, 0.5)?;
+		let progress = path.update(0.35);
+		if progress.completed_segments != 2 || progress.finished {
+			return Err(format!("Unexpected progress {:?}", progress));
+		}
+		let progress = path.update(0.2);
+		if progress.completed_segments != 1 || !progress.finished || progress.remaining != 0.0 {
+			return Err(format!("Unexpected progress {:?}", progress));
+		}
+		let progress = path.update(0.1);
+		if progress != (Progress { completed_segments: 0, finished: false, remaining: 0.0, tag: None, ..progress }) {
+			return Err(format!("Unexpected progress when idle {:?}", progress));
+		}
+		Ok(())
+	}
 
-		let mut a2 = -v1_target.signum()*limits[0];
-		let mut t2 = -v1_target/a2;
+	// Short moves don't reach the velocity limit: v = sqrt(MAX_ACC*s) for a triangle profile
+	#[test]
+	fn alim_max_reachable_velocity() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let mut path = Path::acc_limited(MAX_ACC);
+		path.replan(0.0, MAX_VEL);
+		check_eq(0.0, path.max_reachable_velocity(0.04), MAX_VEL)?;
+		check_eq(0.0, path.max_reachable_velocity(-0.01), (MAX_ACC*0.01f64).sqrt())?;
+		path.replan(0.04, MAX_VEL);
+		path.update(0.3);
+		check_eq(0.3, path.max_reachable_velocity(0.03), MAX_VEL)?;
+		check_eq(0.3, path.get_state().0, 0.02)
+	}
 
-		println!("t0 = {}; a0 = {}; t2 = {}; a2 = {}", t0, a0, t2, a2);
-		let mut t1 = s_diff/v1_target + 0.5*v0.powi(2)/(a0*v1_target) - 0.5*v1_target/a0 + 0.5*v1_target/a2;
+	// Catch up with a target moving away, then follow it
+	fn check_tracking<const DEGREE: usize>(mut path: Path<DEGREE>, s_target: f64) -> Result<(), String> {
+		const FRAME_VEL: f64 = 0.05;
+		path.replan_tracking(s_target, FRAME_VEL, 0.1);
+		if path.segments.back().map(|seg| seg.get_duration()) != Some(f64::INFINITY) {
+			return Err("No follow segment at end of plan".to_string());
+		}
+		let t_meet: f64 = path.segments.iter().take(path.segments.len() - 1).map(|seg| seg.get_duration()).sum();
 
-		if t1 < 0.0 {
-			// Solve for t0 with t1 = 0 (v_target never reached)
-			let x = v0/a0;
-			let roots = Poly::solve_quadratic(1.0, 2.0*x, 0.5*v0*x/a0 - s_diff/a0);
-			eprintln!("Roots: {:?}", roots);
-			t0 = roots[1];
-			t1 = 0.0;
-			t2 = t0 + x;
-			if roots[0] > 0.0 {
-				/* If both roots are positive, that means this
-				 is a shortened move that overshoots the new
-				 target, i.e. it crosses the target during t0.
-				 Thus s_diff will have effectively changed sign
-				 during t0, so change sign of a2. */
-				a2 = -a2;
+		let mut t = 0.0;
+		while t < t_meet + 1.0 {
+			path.update(0.001);
+			t += 0.001;
+			if path.get_state().1 > 0.1 + CLOSE_ENOUGH {
+				return Err(format!("Time {:.3}: velocity {}", t, path.get_state().1));
 			}
 		}
+		check_states_eq(t, path.get_state(), (s_target + FRAME_VEL*t, FRAME_VEL, 0.0))?;
+		if !path.is_active() {
+			return Err("Stopped following".to_string());
+		}
+		Ok(())
+	}
 
-		let degree = self.limits.len() + 1;
-		if t0.abs() > TINY_DURATION {
-			state[0] = a0;
-			self.segments.push_back(Segment::new(&state[..], t0, degree + 1));
-			state = self.segments.back().unwrap().get_end_state();
+	#[test]
+	fn replan_tracking() -> Result<(), String> {
+		use super::{FaultKind, PathStatus, Profile};
+
+		check_tracking(Path::acc_limited(0.5), 0.04)?;
+		check_tracking(Path::jerk_limited(0.5, 5.0), 0.1)?;
+
+		// The whole quintic is carried along, not just its state at the start
+		let mut path = Path::<2>::with_profile(Limits::acc_limited(0.5), Profile::Smoothstep);
+		path.replan_tracking(0.04, 0.0, 0.1);
+		path.update(2.0);
+		check_states_eq(2.0, path.get_state(), (0.04, 0.0, 0.0))?;
+
+		// Too slow to catch up
+		let mut path = Path::acc_limited(0.5);
+		path.replan_tracking(0.04, 0.1, 0.1);
+		if path.status() != PathStatus::Faulted(FaultKind::InvalidInput) {
+			return Err(format!("Status {:?} for v_limit == frame_vel", path.status()));
 		}
+		Ok(())
+	}
 
-		if t1.abs() > TINY_DURATION {
-			state[1] = v1_target;
-			self.segments.push_back(Segment::new(&state[1..], t1, degree + 1));
-			state = self.segments.back().unwrap().get_end_state();
+	#[test]
+	fn jlim_state_into() -> Result<(), String> {
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		path.replan(1.0, 0.1);
+		path.update(0.05);
+		let mut out = [0.0; 4];
+		if path.state_into(&mut out) != 4 || out != [5.0, 0.25, 0.00625, 5.0/6.0*0.05*0.05*0.05] {
+			return Err(format!("Path state {:?}", out));
 		}
 
-		if t2.abs() > TINY_DURATION {
-			state[0] = a2;
-			self.segments.push_back(Segment::new(&state[..], t2, degree + 1));
+		let seg = Segment::new(&[0.5, 0.1, 0.0], 1.0, 3);
+		let mut out = [0.0; 3];
+		if seg.state_into(0.2, &mut out) != 3 || out[..] != seg.get_state_at(0.2)[..] {
+			return Err(format!("Segment state {:?}", out));
 		}
+		Ok(())
+	}
 
-		// Check result
-		if self.segments.is_empty() {
-			assert!(self.state[1].abs() < CLOSE_ENOUGH);
-			assert!((self.state[2] - s_target).abs() < CLOSE_ENOUGH, "s_target = {}; self.target = {}", s_target, self.state[2]);
-			return;
+	#[test]
+	fn estimate_duration() -> Result<(), String> {
+		use super::estimate_duration;
+		let limits = Limits { vel: Some(0.1), ..Limits::acc_limited(0.5) };
+		// Trapezoid, triangle, reverse, overshoot and come back
+		for s_target in [0.04, 0.001, -0.02, 0.021] {
+			let mut path = Path::acc_limited(0.5);
+			path.replan(0.04, 0.1);
+			path.update(0.3);
+			let (pos, vel, _) = path.get_state();
+			path.replan(s_target, 0.1);
+			check_eq(0.0, estimate_duration(s_target - pos, vel, limits), path.update(0.0).remaining)?;
 		}
 
-		let state = self.segments.back().unwrap().get_end_state();
-		assert!(state[1].abs() < CLOSE_ENOUGH);
-		assert!((s_target - state[2]).abs() < CLOSE_ENOUGH);
+		// Coasting, and too short for a constant-acceleration phase
+		let limits = Limits { vel: Some(0.1), ..Limits::jerk_limited(0.5, 5.0) };
+		for s_target in [1.0, 0.002] {
+			let mut path = Path::<3>::with_limits(limits);
+			path.replan(s_target, 0.1);
+			check_eq(0.0, estimate_duration(s_target, 0.0, limits), path.update(0.0).remaining)?;
+		}
+		Ok(())
 	}
 
-	fn calc_path_3(&mut self, limits: &Vec<f64>, s_target: f64) {
-		let s_diff = s_target - self.state[3];
-		let v3_target = s_diff.signum()*limits[2];
+	// Plans that don't end at rest
+	#[test]
+	fn plan_between() -> Result<(), String> {
+		use super::{plan_between, State};
+		let end_state = |segments: &[Segment]| {
+			let state = segments.last().unwrap().get_end_state();
+			let len = state.len();
+			(state[len - 1], state[len - 2], state[len - 3])
+		};
+		let duration = |segments: &[Segment]| segments.iter().map(|seg| seg.get_duration()).sum::<f64>();
+		let limits = Limits { vel: Some(0.1), ..Limits::acc_limited(0.5) };
 
-		self.calc_path_2(limits, v3_target);
-		let coast_index = self.segments.len();
-		self.calc_path_2(limits, 0.0);
+		// Speed up, coast, slow down
+		let segments = plan_between((0.0, 0.05, 0.0).into(), (0.1, 0.05, 0.0).into(), limits);
+		check_states_eq(1.05, end_state(&segments), (0.1, 0.05, -0.5))?;
+		check_eq(1.05, duration(&segments), 1.05)?;
 
-		let mut state = self.get_end_state();
-		let t3 = (s_target - state[3])/v3_target;
+		// Just reverse
+		let segments = plan_between(State { vel: 0.1, ..State::default() }, State { vel: -0.1, ..State::default() }, limits);
+		if segments.len() != 1 {
+			return Err(format!("Expected one segment, got {:?}", segments));
+		}
+		check_states_eq(0.4, end_state(&segments), (0.0, -0.1, -0.5))?;
 
-		let degree = self.limits.len() + 1;
-		if t3 >= 0.0 {
-			while self.segments.len() > coast_index {
-				self.segments.pop_back();
-			}
-			state = self.get_end_state();
-			state[2] = v3_target;
-			self.segments.push_back(Segment::new(&state[2..], t3, degree + 1));
-			self.calc_path_2(limits, 0.0);
+		// Too short to reach the velocity limit
+		let segments = plan_between((0.0, 0.0, 0.0).into(), (0.01, 0.05, 0.0).into(), limits);
+		check_states_eq(0.0, end_state(&segments), (0.01, 0.05, -0.5))?;
 
-			return;
+		// Jerk-limited, ending accelerating, with and without coast
+		for s_end in [1.0, 0.01] {
+			let limits = Limits { vel: Some(0.2), ..Limits::jerk_limited(0.5, 5.0) };
+			let segments = plan_between(State::default(), (s_end, 0.1, 0.2).into(), limits);
+			check_states_eq(0.0, end_state(&segments), (s_end, 0.1, 0.2))?;
+			for seg in &segments {
+				let peak = |order| { let range = seg.get_range(order, 0.0, seg.get_duration()); range.0.abs().max(range.1.abs()) };
+				if peak(1) > 0.2 + CLOSE_ENOUGH || peak(2) > 0.5 + CLOSE_ENOUGH || peak(3) > 5.0 + CLOSE_ENOUGH {
+					return Err(format!("Segment over limits: {}", seg));
+				}
+			}
 		}
+		Ok(())
+	}
 
-		todo!();
+	// Slow motion gives the same states, later
+	#[test]
+	fn alim_time_scale() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.set_time_scale(0.5);
+		path.replan(0.04, 0.1);
+		path.update(0.2);
+		check_states_eq(0.2, path.get_state(), (0.0025, 0.05, 0.5))?;
 
-		// let v = <something>;
-		// self.calc_path_2(limits, v);
-		// self.calc_path_2(limits, 0.0);
+		// Switch to fast forward with the caller's clock
+		path.update_to(0.5);
+		check_states_eq(0.5, path.get_state(), (0.015, 0.1, 0.0))?;
+		path.set_time_scale(2.0);
+		path.update_to(0.55);
+		check_states_eq(0.55, path.get_state(), (0.025, 0.1, 0.0))
 	}
 
-	pub fn print(&self) {
-		for seg in &self.segments {
-			seg.print();
+	// Run two queued moves, stopping in between or not
+	#[test]
+	fn alim_queue_move() -> Result<(), String> {
+		use super::QueuePolicy;
+		for (policy, duration) in [(QueuePolicy::StopBetween, 1.2), (QueuePolicy::Blend, 1.0)] {
+			let mut path = Path::acc_limited(0.5);
+			path.set_queue_policy(policy);
+			path.queue_move(0.04, 0.1);
+			path.queue_move(0.08, 0.1);
+			if path.queued_moves() != 1 || !path.is_active() {
+				return Err(format!("{:?}: first move not started", policy));
+			}
+
+			// Velocity when the first move alone would have ended
+			let mut t = 0.0f64;
+			let mut vel = 0.0;
+			while path.is_active() {
+				path.update(0.001);
+				t += 0.001;
+				if (t - 0.6).abs() < 0.0005 {
+					vel = path.get_state().1;
+				}
+			}
+			check_states_eq(t, path.get_state(), (0.08, 0.0, 0.0))?;
+			if (t - duration).abs() > 0.005 || (policy == QueuePolicy::Blend) != (vel > 0.05) {
+				return Err(format!("{:?}: took {} s, velocity {} at 0.6 s", policy, t, vel));
+			}
 		}
-	}
-}
 
+		let mut path = Path::acc_limited(0.5);
+		path.queue_move(0.04, 0.1);
+		path.queue_move(0.08, 0.1);
+		path.stop();
+		if path.queued_moves() != 0 {
+			return Err("stop() kept the queue".to_string());
+		}
+		Ok(())
+	}
 
-#[cfg(test)]
-mod tests {
-	use super::Path;
-	use super::CLOSE_ENOUGH;
-	use std::io::Write;
+	// The move back starts where the drive is when it gets the command
+	#[test]
+	fn alim_command_latency() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		path.update(0.05);
+		path.set_command_latency(0.02);
+		path.replan(0.0, 0.1);
+		path.checked_invariants().map_err(|err| err.to_string())?;
+		path.update(0.02);
+		check_states_eq(0.07, path.get_state(), (0.5*0.5*0.07*0.07, 0.035, 0.5))?;
+		path.update(0.001);
+		if path.get_state().2 >= 0.0 {
+			return Err(format!("Not braking after the latency: {:?}", path.get_state()));
+		}
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_states_eq(1.0, path.get_state(), (0.0, 0.0, 0.0))
+	}
 
-	enum ActionType {
-		MoveTo((f64, f64)),
-		CheckAcc(f64),
-		CheckVel(f64),
-		CheckPos(f64),
-		CheckState((f64, f64, f64)),
-		Stop,
-		Done,
+	// Speeding up a plan breaks its limits
+	#[test]
+	fn alim_checked_invariants() -> Result<(), String> {
+		use crate::error::{Error, Invariant};
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		path.update(0.05);
+		path.checked_invariants().map_err(|err| err.to_string())?;
+		path.scale_duration(0.5);
+		match path.checked_invariants() {
+			Err(Error::InvariantViolation { invariant: Invariant::Velocity, segment: 0, .. }) => Ok(()),
+			other => Err(format!("Expected a velocity violation, got {:?}", other)),
+		}
 	}
 
-	struct Action {
-		t: f64,
-		action: ActionType,
+	// Limits.vel caps the velocity of each replan
+	#[test]
+	fn alim_limits_vel_cap() -> Result<(), String> {
+		let mut path = Path::<2>::with_limits(Limits { vel: Some(0.05), ..Limits::acc_limited(0.5) });
+		path.replan(0.04, 0.1);
+		path.update(0.3);
+		check_eq(0.3, path.get_state().1, 0.05)?;
+		check_eq(0.3, path.limit_margins().unwrap().vel, 1.0)?;
+
+		// Called the way it was before the degree became a type parameter
+		#[allow(deprecated)]
+		let mut old = Path::new(vec![0.5, 5.0]);
+		match &old {
+			super::DynPath::Jerk(path) if path.limits() == Limits::jerk_limited(0.5, 5.0) => {},
+			_ => return Err(format!("Path::new() gave {:?}", old)),
+		}
+		old.replan(0.04, 0.1);
+		old.update(0.1);
+		let mut new = Path::jerk_limited(0.5, 5.0);
+		new.replan(0.04, 0.1);
+		new.update(0.1);
+		check_states_eq(0.1, old.get_state(), new.get_state())?;
+		#[allow(deprecated)]
+		let old = Path::new(vec![0.5]);
+		if !matches!(old, super::DynPath::Acc(_)) {
+			return Err(format!("Path::new() gave {:?}", old));
+		}
+		Ok(())
 	}
 
-	fn val_eq(val1: f64, val2: f64) -> bool {
-		(val1 - val2).abs() < CLOSE_ENOUGH
+	/*
+	 * Counts the allocations made on each thread, so a test can check that
+	 * replanning and updating after construction don't allocate
+	 */
+	struct CountingAlloc;
+
+	thread_local! {
+		static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
 	}
 
-	fn check_eq(time: f64, val: f64, goal: f64) -> Result<(), String> {
-		if val_eq(val, goal) {
-			return Ok(());
+	unsafe impl std::alloc::GlobalAlloc for CountingAlloc {
+		unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+			let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+			unsafe { std::alloc::System.alloc(layout) }
 		}
 
-		Err(format!("Time {:.3}: value {} differs from {}", time, val, goal))
+		unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+			unsafe { std::alloc::System.dealloc(ptr, layout) }
+		}
 	}
 
-	fn check_states_eq(time: f64, state: (f64, f64, f64), goal: (f64, f64, f64)) -> Result<(), String> {
-		if val_eq(state.0, goal.0) && val_eq(state.1, goal.1) && val_eq(state.2, goal.2) {
-			return Ok(());
+	#[global_allocator]
+	static COUNTING_ALLOC: CountingAlloc = CountingAlloc;
+
+	fn allocations() -> usize {
+		ALLOCATIONS.with(|count| count.get())
+	}
+
+	// replan(), stop() and update() from the segment pool, without allocating
+	#[test]
+	fn replan_no_alloc() -> Result<(), String> {
+		let mut acc_limited = Path::acc_limited(0.5);
+		let mut jerk_limited = Path::jerk_limited(0.5, 5.0);
+		let before = allocations();
+		for (n, target) in [0.5, -0.5, 1.0, 0.2, -1.0].iter().enumerate() {
+			acc_limited.replan(*target, 0.1);
+			jerk_limited.replan(*target, 0.1);
+			acc_limited.update(0.1*n as f64);
+			jerk_limited.update(0.1*n as f64);
+			acc_limited.stop();
+			jerk_limited.stop();
+			acc_limited.update(0.05);
+			jerk_limited.update(0.05);
+		}
+		let count = allocations() - before;
+		if count != 0 {
+			return Err(format!("{} allocations", count));
 		}
+		Ok(())
+	}
 
-		Err(format!("Time {:.3}: state {:?} differs from {:?}", time, state, goal))
+	// update() and update_to() through a plan, without allocating
+	#[test]
+	fn update_no_alloc() -> Result<(), String> {
+		let mut acc_limited = Path::acc_limited(0.5);
+		let mut jerk_limited = Path::jerk_limited(0.5, 5.0);
+		acc_limited.replan(0.5, 0.1);
+		jerk_limited.replan(-0.5, 0.1);
+		let before = allocations();
+		for n in 0..100 {
+			acc_limited.update(0.01);
+			jerk_limited.update_to(0.01*n as f64);
+		}
+		let count = allocations() - before;
+		if count != 0 {
+			return Err(format!("{} allocations", count));
+		}
+		Ok(())
 	}
 
-	fn write_state(orig_state: &Vec<f64>, file: &mut std::fs::File) {
-		for val in orig_state.iter().rev() {
-			write!(file, "{:.6} ", *val).expect("Could not write to file");
+	// Replanning reuses the preallocated segment pool
+	#[test]
+	fn jlim_segment_pool() -> Result<(), String> {
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		let capacity = path.segments.capacity();
+		for (n, target) in [0.5, -0.5, 1.0, 0.2, -1.0].iter().enumerate() {
+			path.replan(*target, 0.1);
+			path.update(0.1*n as f64);
+			path.stop();
+			path.update(0.05);
+		}
+		if path.segments.capacity() != capacity {
+			return Err(format!("Segment capacity changed from {} to {}", capacity, path.segments.capacity()));
 		}
+		path.set_max_segments(16);
+		if path.segments.capacity() < 16 {
+			return Err(format!("Capacity {} after set_max_segments(16)", path.segments.capacity()));
+		}
+		Ok(())
+	}
 
-		// Print dummy jerk to keep gnuplot happy
-		if orig_state.len() < 4 {
-			write!(file, "{:.6} ", 0.0).expect("Could not write to file");
+	// Plan from a hypothetical cruising state, leaving the path itself alone
+	#[test]
+	fn alim_plan_from() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let path = Path::acc_limited(MAX_ACC);
+		let segments = path.plan_from((0.02, MAX_VEL, 0.0), 0.04, MAX_VEL);
+		if segments.len() != 2 {
+			return Err(format!("Expected cruise and decel segments, got {}", segments.len()));
+		}
+		check_eq(0.0, segments[0].get_duration(), 0.1)?;
+		check_eq(0.0, segments[1].get_duration(), 0.2)?;
+		let end_state = segments[1].get_end_state();
+		check_eq(0.0, end_state[2], 0.04)?;
+		if path.is_active() {
+			return Err("plan_from() changed the path".to_string());
 		}
+
+		// With the braking limit, and not held back by the replan interval
+		let mut path = Path::acc_limited(MAX_ACC);
+		path.set_max_dec(Some(0.5*MAX_ACC));
+		path.set_min_replan_interval(1.0);
+		path.replan(1.0, MAX_VEL);
+		let segments = path.plan_from((0.02, MAX_VEL, 0.0), 0.04, MAX_VEL);
+		check_eq(0.0, segments.iter().map(|seg| seg.get_duration()).sum(), 0.4)
 	}
 
-	fn run_test(limits: Vec<f64>, actions: &[Action], test_name: &str) -> Result<(), String> {
-		let mut file = std::fs::File::create(test_name.to_owned() + ".data").expect("Cannot create data file");
+	// End-of-move handling when the plan misses its target (faked here by moving the target)
+	#[test]
+	fn alim_end_of_move_policy() -> Result<(), String> {
+		use super::EndOfMovePolicy;
+		use crate::error::Error;
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		const MISS: f64 = 1e-6;
+		let run = |policy: EndOfMovePolicy| {
+			let mut path = Path::acc_limited(MAX_ACC);
+			path.set_end_of_move_policy(policy);
+			path.replan(0.04, MAX_VEL);
+			path.target += MISS;
+			path.update(1.0);
+			(path.get_state(), path.take_error())
+		};
 
-		let mut replans = Vec::new();
+		let (state, error) = run(EndOfMovePolicy::SnapToTarget);
+		check_states_eq(1.0, state, (0.04 + MISS, 0.0, 0.0))?;
+		assert_eq!(error, None);
 
-		let mut result = Ok(());
+		let (state, error) = run(EndOfMovePolicy::HoldLastState);
+		check_states_eq(1.0, state, (0.04, 0.0, 0.0))?;
+		assert_eq!(error, None);
 
-		let dt = 0.001;
-		let tolerance_fact = 1.01;
+		let (state, error) = run(EndOfMovePolicy::ErrorIfDeviationExceeds(MISS*10.0));
+		check_states_eq(1.0, state, (0.04 + MISS, 0.0, 0.0))?;
+		assert_eq!(error, None);
 
-		let mut path = Path::new(limits.clone());
-		let mut t = 0.0;
-		let mut s_prev = 0.0;
-		let mut v_prev = 0.0;
-		let mut a_prev = 0.0;
-		let mut action_index = 0;
-		loop {
-			// Check limits
-			let state = path.get_state();
-			let v = (state.0 - s_prev)/dt;
-			let a = (v - v_prev)/dt;
-			let j = (a - a_prev)/dt;
-
-			// Velocity is variable so we don't have a reference to check against
-
-			if result.is_ok() {
-				if a.abs() > limits[0]*tolerance_fact {
-					result = Err(format!("Time {}: acceleration ({}) over limit ({})", t, a, limits[0]));
-				}
-			}
+		let (state, error) = run(EndOfMovePolicy::ErrorIfDeviationExceeds(MISS*0.1));
+		check_states_eq(1.0, state, (0.04, 0.0, 0.0))?;
+		match error {
+			Some(Error::EndOfMoveDeviation { deviation, .. }) => check_eq(1.0, deviation, MISS),
+			_ => Err(format!("Expected a deviation error, got {:?}", error)),
+		}
+	}
 
-			if limits.len() > 1 {
-				if result.is_ok() {
-					if j.abs() > limits[1]*tolerance_fact {
-						result = Err(format!("Time {}: jerk ({}) over limit ({})", t, j, limits[1]));
-					}
-				}
-			}
+	// Slowing a plan down keeps the positions but halves the velocity
+	#[test]
+	fn alim_scale_duration() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let mut path = Path::acc_limited(MAX_ACC);
+		path.replan(0.04, MAX_VEL);
+		path.update(0.3);
+		path.scale_duration(2.0);
+		check_states_eq(0.3, path.get_state(), (0.02, MAX_VEL*0.5, 0.0))?;
+		path.update(0.1);
+		check_states_eq(0.4, path.get_state(), (0.025, MAX_VEL*0.5, 0.0))?;
+		path.update(0.3);
+		check_states_eq(0.7, path.get_state(), (0.0375, 0.25*MAX_VEL, -0.25*MAX_ACC))?;
+		path.stretch_to(0.1).map_err(|err| err.to_string())?;
+		check_states_eq(0.7, path.get_state(), (0.0375, 0.5*MAX_VEL, -MAX_ACC))?;
+		path.update(0.11);
+		check_states_eq(0.81, path.get_state(), (0.04, 0.0, 0.0))
+	}
 
-			s_prev = state.0;
-			v_prev = v;
-			a_prev = a;
+	// Too short a duration leaves the plan, or gives the fastest one within the limits
+	#[test]
+	fn alim_stretch_to_best_effort() -> Result<(), String> {
+		use super::Strictness;
+		use crate::error::Error;
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let mut path = Path::acc_limited(MAX_ACC);
+		path.replan(0.04, MAX_VEL);
+		path.update(0.3);
+		path.scale_duration(2.0);
+		match path.stretch_to(0.1) {
+			Err(Error::InfeasibleDuration { requested, achieved }) => {
+				check_eq(0.3, requested, 0.1)?;
+				check_eq(0.3, achieved, 0.6)?;
+			},
+			result => return Err(format!("Expected an infeasible duration, got {:?}", result)),
+		}
+		check_states_eq(0.3, path.get_state(), (0.02, 0.5*MAX_VEL, 0.0))?;
+		check_eq(0.3, path.plan_report().get_duration(), 0.6)?;
+		path.set_strictness(Strictness::BestEffort);
+		match path.stretch_to(0.1) {
+			Err(Error::InfeasibleDuration { requested, achieved }) => {
+				check_eq(0.3, requested, 0.1)?;
+				check_eq(0.3, achieved, 0.3)?;
+			},
+			result => return Err(format!("Expected an infeasible duration, got {:?}", result)),
+		}
+		check_states_eq(0.3, path.get_state(), (0.02, MAX_VEL, 0.0))?;
+		path.checked_invariants().map_err(|err| err.to_string())?;
+		path.update(0.3);
+		check_states_eq(0.6, path.get_state(), (0.04, 0.0, 0.0))
+	}
 
-			// Check specific values at specific times
-			let action = &actions[action_index];
-			if t + dt >= action.t {
-				let t_frac = action.t - t;
-				path.update(t_frac);
-				match action.action {
-					ActionType::MoveTo((x, v)) => {
-						path.replan(x, v);
-						replans.push(action.t);
-					},
-					ActionType::Stop => {
-						path.stop();
-						replans.push(action.t);
-					},
-					ActionType::CheckAcc(acc) => {
-						if result.is_ok() {
-							let state = path.get_state();
-							result = check_eq(action.t, state.2, acc);
-						}
-					},
-					ActionType::CheckVel(vel) => {
-						if result.is_ok() {
-							let state = path.get_state();
-							result = check_eq(action.t, state.1, vel);
-						}
-					},
-					ActionType::CheckPos(pos) => {
-						if result.is_ok() {
-							let state = path.get_state();
-							result = check_eq(action.t, state.0, pos);
-						}
-					},
-					ActionType::CheckState(goal) => {
-						if result.is_ok() {
-							let state = path.get_state();
-							result = check_states_eq(action.t, state, goal);
-						}
-					},
-					ActionType::Done => {
-						if result.is_ok() {
-							if path.is_active() {
-								result = Err(format!("Time {:.3}: Planner still active", action.t));
-							}
-						}
-						break;
-					},
-				}
-				let t_frac = t + dt - action.t;
-				path.update(t_frac);
-				action_index += 1;
-			} else {
-				path.update(dt);
-			}
+	// Segments handed out to a drive, in order, with the path following what it has run
+	#[test]
+	fn alim_segment_handoff() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		path.update(0.1);
+		let segments: Vec<Segment> = std::iter::from_fn(|| path.take_next_segment()).collect();
+		let durations: Vec<f64> = segments.iter().map(|seg| seg.get_duration()).collect();
+		if durations.len() != 3 || durations.iter().zip([0.1, 0.2, 0.2]).any(|(dur, goal)| (dur - goal).abs() > CLOSE_ENOUGH) {
+			return Err(format!("Handed out durations {:?}", durations));
+		}
+
+		// The handed out segments are what the path plays
+		let state = segments[0].get_state_at(0.0);
+		check_states_eq(0.1, (state[2], state[1], state[0]), path.get_state())?;
+		let progress = path.pop_completed_segments(2);
+		check_states_eq(0.4, path.get_state(), (0.03, 0.1, -0.5))?;
+		if progress.completed_segments != 2 || progress.finished || path.take_next_segment().is_some() {
+			return Err(format!("Expected one segment left to run, {:?}", progress));
+		}
+		if !path.pop_completed_segments(1).finished {
+			return Err("Expected the move to finish".to_string());
+		}
+		check_states_eq(0.6, path.get_state(), (0.04, 0.0, 0.0))?;
 
-			// Plot state to file
-			write!(file, "{:.6} ", t).expect("Could not write to file");
-			write_state(&path.state, &mut file);
-			writeln!(file, "").expect("Could not write to file");
+		// A new plan starts over
+		path.replan(0.0, 0.1);
+		match path.take_next_segment() {
+			Some(seg) if seg.get_state_at(0.0)[2] == 0.04 => Ok(()),
+			seg => Err(format!("Expected the new plan from 0.04, got {:?}", seg)),
+		}
+	}
+
+	// The history keeps the latest states, with their times
+	#[test]
+	fn alim_history() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.enable_history(3);
+		path.replan(0.04, 0.1);
+		for _ in 0..5 {
+			path.update(0.1);
+		}
+		let history: Vec<_> = path.history().collect();
+		let expected = [(0.3, 0.02), (0.4, 0.03), (0.5, 0.0375)];
+		if history.len() != 3 || history.iter().zip(expected).any(|((t, state), (t_goal, pos))| (t - t_goal).abs() > 1e-9 || (state.0 - pos).abs() > CLOSE_ENOUGH) {
+			return Err(format!("History {:?}", history));
+		}
+		if history[2].1 != path.get_state() {
+			return Err(format!("Latest entry {:?}, state {:?}", history[2], path.get_state()));
+		}
+		Ok(())
+	}
 
-			t += dt;
+	// Counters over a move, an overshooting replan and a stop
+	#[test]
+	fn alim_metrics() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		let metrics = path.metrics();
+		if metrics.replans != 1 || metrics.segments != 3 || metrics.overshoot_segments != 0 || metrics.max_segments_queued != 3 {
+			return Err(format!("After one move: {:?}", metrics));
 		}
+		check_eq(0.0, metrics.planned_time, 0.6)?;
 
-		writeln!(file, "").expect("Could not write to file");
-		writeln!(file, "").expect("Could not write to file");
-		for t in replans {
-			write!(file, "{:.6} ", t).expect("Could not write to file");
+		path.update(0.3);
+		path.replan(0.021, 0.1);
+		let metrics = path.metrics();
+		if metrics.replans != 2 || metrics.overshoot_segments == 0 || metrics.overshoot_segments >= metrics.segments {
+			return Err(format!("After an overshooting replan: {:?}", metrics));
 		}
-		writeln!(file, "").expect("Could not write to file");
 
-		result
+		path.stop();
+		let stopped = path.metrics();
+		if stopped.replans != 2 || stopped.segments != metrics.segments + 1 || stopped.overshoot_segments != metrics.overshoot_segments {
+			return Err(format!("After a stop: {:?}", stopped));
+		}
+		check_eq(0.3, stopped.planned_time, metrics.planned_time + 0.2)
 	}
 
-	// ***
-	// *** Test acceleration-limited motion ***
-	// ***
-
-	// Zero-distance move
+	// Replanning at the velocity limit, or at a segment boundary, adds no tiny segments
 	#[test]
-	fn alim_null_move() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.1;
-		const MAX_ACC: f64 = 0.5;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((0.00, MAX_VEL)) },
-			Action { t: 0.01, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_null_move")
-	}
+	fn jlim_replan_at_limit() -> Result<(), String> {
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		path.replan(1.0, 0.1);
+		path.update(1.0);
+		// As if the plan had accumulated some rounding
+		path.state[2] -= 1e-13;
+		path.state[1] = 1e-15;
+		path.replan(1.0, 0.1);
+		if path.state[2] != 0.1 || path.state[1] != 0.0 {
+			return Err(format!("State not snapped: {:?}", path.state()));
+		}
+		let durations: Vec<f64> = path.segments.iter().map(|seg| seg.get_duration()).collect();
+		if durations.len() != 4 || durations.iter().any(|dur| *dur < 1e-3) {
+			return Err(format!("Expected coast and braking, got durations {:?}", durations));
+		}
 
-	// Long move that reach all the limits
-	#[test]
-	fn alim_move_all_limits_pos() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.1;
-		const MAX_ACC: f64 = 0.5;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((0.04, MAX_VEL)) },
-			Action { t: 0.10, action: ActionType::CheckAcc(MAX_ACC) },
-			Action { t: 0.30, action: ActionType::CheckState((0.02, MAX_VEL, 0.0)) },
-			Action { t: 0.60, action: ActionType::CheckState((0.04, 0.0, 0.0)) },
-			Action { t: 0.61, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_move_all_limits_pos")
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		path.update(0.2);
+		path.replan(0.04, 0.1);
+		let durations: Vec<f64> = path.segments.iter().map(|seg| seg.get_duration()).collect();
+		if durations.len() != 2 || (durations[0] - 0.2).abs() > 1e-9 || (durations[1] - 0.2).abs() > 1e-9 {
+			return Err(format!("Expected coast and braking, got durations {:?}", durations));
+		}
+		Ok(())
 	}
+
+	// The 3-4-5 polynomial from rest, and a smooth replan while moving
 	#[test]
-	fn alim_move_all_limits_neg() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.1;
-		const MAX_ACC: f64 = 0.5;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((-0.04, MAX_VEL)) },
-			Action { t: 0.10, action: ActionType::CheckAcc(-MAX_ACC) },
-			Action { t: 0.30, action: ActionType::CheckState((-0.02, -MAX_VEL, 0.0)) },
-			Action { t: 0.60, action: ActionType::CheckState((-0.04, 0.0, 0.0)) },
-			Action { t: 0.61, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_move_all_limits_neg")
+	fn smoothstep_profile() -> Result<(), String> {
+		use super::Profile;
+		let mut path = Path::<2>::with_profile(Limits::acc_limited(0.5), Profile::Smoothstep);
+		path.replan(0.04, 0.1);
+		// Within the tolerance on the limits
+		let duration = path.plan_report().get_duration();
+		if (duration - 0.75).abs() > 1e-6 {
+			return Err(format!("Duration {}, expected 0.75", duration));
+		}
+		path.update(0.5*duration);
+		let state = path.get_state();
+		check_eq(0.5*duration, state.0, 0.02)?;
+		if (state.1 - 0.1).abs() > 1e-6 || state.2.abs() > 1e-6 {
+			return Err(format!("Midway state {:?}", state));
+		}
+
+		// The rest of the quintic, not just the state it starts from
+		let report = path.plan_report();
+		let phase = report.phases[0];
+		if phase.start != state || (phase.end.0 - 0.04).abs() > 1e-9 || (phase.peak_vel - 0.1).abs() > 1e-6 {
+			return Err(format!("Remaining plan {:?}", phase));
+		}
+		check_eq(0.75*duration, report.pos_at(0.25*duration), 0.04*(10.0*0.421875 - 15.0*0.31640625 + 6.0*0.2373046875))?;
+		let end = path.take_next_segment().ok_or("No segment handed out")?.get_end_state();
+		check_states_eq(duration, (end[2], end[1], end[0]), (0.04, 0.0, 0.0))?;
+
+		let mut path = Path::<3>::with_profile(Limits::jerk_limited(0.5, 5.0), Profile::Smoothstep);
+		path.replan(1.0, 0.1);
+		path.update(2.0);
+		let before = path.get_state();
+		path.replan(0.5, 0.1);
+		check_states_eq(2.0, path.get_state(), before)?;
+		path.checked_invariants().map_err(|err| err.to_string())?;
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_states_eq(2.0, path.get_state(), (0.5, 0.0, 0.0))
 	}
 
-	// Short, separate moves that reach a_max but not v_max
+	// Jog until released, and up to the travel limit without a release
 	#[test]
-	fn alim_move_no_vmax_pos() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.2;
-		const MAX_ACC: f64 = 1.0;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((0.02, MAX_VEL)) },
-			Action { t: 0.30, action: ActionType::CheckState((0.02, 0.0, 0.0)) },
-			Action { t: 0.31, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_move_no_vmax_pos")
+	fn alim_jog() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.jog(1.0, 0.1);
+		path.update(0.5);
+		check_states_eq(0.5, path.get_state(), (0.04, 0.1, 0.0))?;
+		if !path.is_active() {
+			return Err("Jog ended on its own".to_string());
+		}
+		path.jog_release();
+		path.update(0.3);
+		check_states_eq(0.8, path.get_state(), (0.05, 0.0, 0.0))?;
+		if path.is_active() {
+			return Err("Still moving after the release".to_string());
+		}
+
+		path.set_travel_limits(Some((0.0, 0.09)));
+		path.jog(1.0, 0.1);
+		path.update(1.0);
+		check_states_eq(1.8, path.get_state(), (0.09, 0.0, 0.0))?;
+		path.jog(1.0, 0.1);
+		if path.is_active() {
+			return Err("Jogged past the travel limit".to_string());
+		}
+		Ok(())
 	}
+
+	// Noise within the band doesn't replan
 	#[test]
-	fn alim_move_no_vmax_neg() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.2;
-		const MAX_ACC: f64 = 1.0;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((-0.02, MAX_VEL)) },
-			Action { t: 0.30, action: ActionType::CheckState((-0.02, 0.0, 0.0)) },
-			Action { t: 0.31, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_move_no_vmax_neg")
+	fn alim_follow_target() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.set_follow_hysteresis(0.001);
+		let targets = [(0.04, true), (0.0405, false), (0.0395, false), (0.042, true), (0.0415, false)];
+		for (n, (pos, replans)) in targets.iter().enumerate() {
+			if path.follow_target(*pos, 0.1) != *replans {
+				return Err(format!("Target {} ({}): expected replan {}", n, pos, replans));
+			}
+			path.update(0.05);
+		}
+		if !path.follow_target(0.042, 0.05) {
+			return Err("A new velocity limit should replan".to_string());
+		}
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_states_eq(0.0, path.get_state(), (0.042, 0.0, 0.0))
 	}
 
-	// Plan a move, then plan another one that continues in exactly the same way
 	#[test]
-	fn alim_continued_move_pos() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.1;
-		const MAX_ACC: f64 = 0.5;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((0.04, MAX_VEL)) },
-			Action { t: 0.30, action: ActionType::MoveTo((0.04, MAX_VEL)) },
-			Action { t: 0.60, action: ActionType::CheckState((0.04, 0.0, 0.0)) },
-			Action { t: 0.61, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_continued_move_pos")
+	fn alim_hold_correction() -> Result<(), String> {
+		use crate::error::Error;
+
+		let mut path = Path::acc_limited(0.5);
+		path.enable_hold_correction(0.001);
+		path.replan(0.04, 0.1);
+		while path.is_active() {
+			path.update(0.01);
+		}
+
+		// Drift within the tolerance is left alone
+		path.set_state((0.0405, 0.0, 0.0));
+		path.update(0.01);
+		if path.is_active() || path.take_error().is_some() {
+			return Err("Corrected drift within tolerance".to_string());
+		}
+
+		path.set_state((0.045, 0.0, 0.0));
+		path.update(0.01);
+		match path.take_error() {
+			Some(Error::HoldDrift { deviation, .. }) => check_eq(0.0, deviation, 0.005)?,
+			error => return Err(format!("Expected a HoldDrift error, got {:?}", error)),
+		}
+		if !path.is_active() {
+			return Err("No correction move".to_string());
+		}
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_states_eq(0.0, path.get_state(), (0.04, 0.0, 0.0))
 	}
+
+	// With a splice snap, a replan mid jerk phase ramps the jerk instead of jumping it
 	#[test]
-	fn alim_continued_move_neg() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.1;
-		const MAX_ACC: f64 = 0.5;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((-0.04, MAX_VEL)) },
-			Action { t: 0.30, action: ActionType::MoveTo((-0.04, MAX_VEL)) },
-			Action { t: 0.60, action: ActionType::CheckState((-0.04, 0.0, 0.0)) },
-			Action { t: 0.61, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_continued_move_neg")
+	fn jlim_splice_snap() -> Result<(), String> {
+		const SNAP: f64 = 1000.0;
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		path.set_splice_snap(Some(SNAP));
+		path.replan(1.0, 0.1);
+		path.update(0.05);
+		path.replan(-1.0, 0.1);
+
+		let dt = 0.0005;
+		let mut jerk = path.state[0];
+		for step in 0..40 {
+			path.update(dt);
+			if (path.state[0] - jerk).abs() > SNAP*dt*(1.0 + 1e-9) {
+				return Err(format!("Step {}: jerk from {} to {}", step, jerk, path.state[0]));
+			}
+			jerk = path.state[0];
+			path.checked_invariants().map_err(|err| err.to_string())?;
+		}
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_states_eq(0.0, path.get_state(), (-1.0, 0.0, 0.0))
 	}
 
-	// Interrupted move, higher velocity
+	#[cfg(feature = "serde")]
 	#[test]
-	fn alim_inc_vel_pos() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.1;
-		const MAX_ACC: f64 = 0.5;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((0.06, MAX_VEL)) },
-			Action { t: 0.30, action: ActionType::MoveTo((0.06, MAX_VEL*1.5)) },
-			Action { t: 0.41, action: ActionType::CheckVel(MAX_VEL*1.5) },
-			Action { t: 0.80, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_inc_vel_pos")
+	fn alim_export_plan_json() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		let json = path.export_plan_json();
+		let labels: Vec<&str> = json.match_indices(r#""label": ""#).map(|(n, key)| {
+			let rest = &json[n + key.len()..];
+			&rest[..rest.find('"').unwrap()]
+		}).collect();
+		if labels != ["accelerate", "cruise", "decelerate"] || !json.contains(r#""overshoot": null"#)
+				|| !json.contains(r#""duration": 0.2, "start": [0, 0, 0.5]"#) || !json.contains(r#""coeffs": [0, 0, 0.25]"#) {
+			return Err(format!("Unexpected JSON {}", json));
+		}
+		Ok(())
 	}
 
-	// Interrupted move, higher velocity, doesn't reach vmax
+	// Sub-tick states between updates, without moving the path
 	#[test]
-	fn alim_inc_vel_novmax_pos() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.1;
-		const MAX_ACC: f64 = 0.5;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((0.05, MAX_VEL)) },
-			Action { t: 0.30, action: ActionType::MoveTo((0.05, MAX_VEL*1.5)) },
-			Action { t: 0.70, action: ActionType::CheckPos(0.05) },
-			Action { t: 0.71, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_inc_vel_novmax_pos")
+	fn alim_interpolate_since_last_update() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		path.update(0.1);
+		for n in 0..=4 {
+			let fraction = n as f64/4.0;
+			let t = 0.1 + 0.1*fraction;
+			check_states_eq(t, path.interpolate_since_last_update(fraction), (0.25*t*t, 0.5*t, 0.5))?;
+		}
+		check_states_eq(0.1, path.get_state(), (0.0025, 0.05, 0.5))?;
+
+		// Across a segment boundary, and past the end
+		path.update(0.1);
+		check_states_eq(0.25, path.interpolate_since_last_update(0.5), (0.015, 0.1, 0.0))?;
+		path.update(0.5);
+		check_states_eq(0.8, path.interpolate_since_last_update(1.0), (0.04, 0.0, 0.0))
 	}
 
-	// Interrupted move, lower velocity
+	// Relative moves stack up on the commanded target, not the current position
 	#[test]
-	fn alim_dec_vel_pos() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.1;
-		const MAX_ACC: f64 = 0.5;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((0.05, MAX_VEL)) },
-			Action { t: 0.30, action: ActionType::MoveTo((0.05, MAX_VEL*0.5)) },
-			Action { t: 1.00, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_dec_vel_pos")
+	fn alim_move_relative() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		path.update(0.1);
+		path.move_relative(0.02, 0.1);
+		path.move_relative(-0.01, 0.1);
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_states_eq(0.0, path.get_state(), (0.05, 0.0, 0.0))?;
+
+		// Also on top of a held back replan
+		path.set_min_replan_interval(1.0);
+		path.move_relative(0.01, 0.1);
+		path.update(0.1);
+		path.move_relative(0.01, 0.1);
+		path.move_relative(0.01, 0.1);
+		while path.is_active() || path.pending_replan.is_some() {
+			path.update(0.01);
+		}
+		check_states_eq(0.0, path.get_state(), (0.08, 0.0, 0.0))
 	}
 
-	// Shortened move - new command at s = 0.020 overshoots to 0.030 before going back to 0.025
+	// New limits mid move replan it, unless the state is beyond them
 	#[test]
-	fn alim_shortened_move_pos() -> Result<(), String> {
-		const MAX_VEL: f64 = 0.1;
-		const MAX_ACC: f64 = 0.5;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((0.05, MAX_VEL)) },
-			Action { t: 0.30, action: ActionType::MoveTo((0.025, MAX_VEL*0.5)) },
-			Action { t: 0.80, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_shortened_move_pos")
+	fn set_limits() -> Result<(), String> {
+		use crate::error::Error;
+		let mut path = Path::acc_limited(0.5);
+		path.replan_tagged(1.0, 0.1, 3);
+		path.update(0.5);
+		path.set_limits(Limits { vel: Some(0.05), ..Limits::acc_limited(0.25) }).map_err(|err| err.to_string())?;
+		path.update(0.1);
+		check_states_eq(0.6, path.get_state(), (0.04875, 0.075, -0.25))?;
+		if path.current_tag() != Some(3) {
+			return Err(format!("Tag {:?} after the new limits", path.current_tag()));
+		}
+		path.checked_invariants().map_err(|err| err.to_string())?;
+		while path.is_active() {
+			path.update(0.01);
+		}
+		check_states_eq(0.6, path.get_state(), (1.0, 0.0, 0.0))?;
+
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		path.replan(1.0, 0.1);
+		path.update(0.15);
+		match path.set_limits(Limits::jerk_limited(0.3, 5.0)) {
+			Err(Error::StateBeyondLimits { value, limit }) if value == 0.5 && limit == 0.3 => (),
+			result => return Err(format!("Expected the acceleration beyond the new limit, got {:?}", result)),
+		}
+		if path.limits() != Limits::jerk_limited(0.5, 5.0) {
+			return Err(format!("Limits changed to {:?}", path.limits()));
+		}
+
+		let mut path = Path::acc_limited(0.5);
+		path.set_acc_bias(-0.3);
+		match path.set_limits(Limits::acc_limited(0.3)) {
+			Err(Error::StateBeyondLimits { value, limit }) if value == -0.3 && limit == 0.3 => (),
+			result => return Err(format!("Expected the bias beyond the new limit, got {:?}", result)),
+		}
+		if path.limits() != Limits::acc_limited(0.5) {
+			return Err(format!("Limits changed to {:?}", path.limits()));
+		}
+		Ok(())
 	}
+
+	// Timestamp-based updates with a jittery scheduler
 	#[test]
-	fn alim_shortened_move_neg() -> Result<(), String> {
+	fn alim_update_to_jitter() -> Result<(), String> {
 		const MAX_VEL: f64 = 0.1;
 		const MAX_ACC: f64 = 0.5;
-		let limits = vec![MAX_ACC];
-		let actions = [
-			Action { t: 0.00, action: ActionType::MoveTo((-0.05, MAX_VEL)) },
-			Action { t: 0.30, action: ActionType::MoveTo((-0.025, MAX_VEL*0.5)) },
-			Action { t: 0.80, action: ActionType::Done },
-		];
-		run_test(limits, &actions, "alim_shortened_move_neg")
+		const T_START: f64 = 1000.0;
+		let mut path = Path::acc_limited(MAX_ACC);
+		path.update_to(T_START);
+		path.replan(0.04, MAX_VEL);
+		for tick in 1..300 {
+			let jitter = if tick % 2 == 0 { 0.0004 } else { -0.0003 };
+			path.update_to(T_START + tick as f64*0.001 + jitter);
+		}
+		path.update_to(T_START + 0.30);
+		check_states_eq(0.30, path.get_state(), (0.02, MAX_VEL, 0.0))?;
+		path.update_to(T_START + 0.61);
+		if path.is_active() {
+			return Err("Planner still active".to_string());
+		}
+		check_states_eq(0.61, path.get_state(), (0.04, 0.0, 0.0))
 	}
 
 	// Reversed move - new command at s = 0.020, v > 0, with target that needs v < 0.
@@ -646,6 +5846,21 @@ mod tests {
 		];
 		run_test(limits, &actions, "alim_stop_neg")
 	}
+	// Zero velocity limit during motion: stop as for stop()
+	#[test]
+	fn alim_zero_vel_limit() -> Result<(), String> {
+		const MAX_VEL: f64 = 0.1;
+		const MAX_ACC: f64 = 0.5;
+		let limits = vec![MAX_ACC];
+		let actions = [
+			Action { t: 0.00, action: ActionType::MoveTo((0.04, MAX_VEL)) },
+			Action { t: 0.15, action: ActionType::MoveTo((0.04, 0.0)) },
+			Action { t: 0.31, action: ActionType::CheckState((0.01125, 0.0, 0.0)) },
+			Action { t: 0.32, action: ActionType::MoveTo((0.04, 0.0)) },
+			Action { t: 0.35, action: ActionType::Done },
+		];
+		run_test(limits, &actions, "alim_zero_vel_limit")
+	}
 	#[test]
 	fn alim_stop_interrupted() -> Result<(), String> {
 		const MAX_VEL: f64 = 0.2;
@@ -873,6 +6088,194 @@ mod tests {
 	}
 
 
+	/*
+	 * stop() from every sign combination of (v, a), including a opposing v
+	 * strongly enough to pass v = 0 on the way: must end at rest within the
+	 * limits, and no later than ramping a to the limit and back would take.
+	 */
+	#[test]
+	fn jlim_stop_all_signs() -> Result<(), String> {
+		const MAX_ACC: f64 = 0.5;
+		const JERK: f64 = 5.0;
+		let cases = [
+			(0.05, 0.3), (0.05, 0.0), (0.05, -0.3),
+			(0.0, 0.3), (0.0, -0.3),
+			(-0.05, 0.3), (-0.05, 0.0), (-0.05, -0.3),
+			(0.005, -0.5), (-0.005, 0.5), (0.1, 0.5), (-0.1, -0.5),
+		];
+		for (v0, a0) in cases {
+			let mut path = Path::jerk_limited(MAX_ACC, JERK);
+			path.state[1] = a0;
+			path.state[2] = v0;
+			path.stop();
+			let report = path.plan_report();
+			for phase in &report.phases {
+				if phase.jerk.abs() > JERK*(1.0 + 1e-12) || phase.peak_acc > MAX_ACC*(1.0 + 1e-12) {
+					return Err(format!("Stop from ({}, {}) over limits in {:?}", v0, a0, phase));
+				}
+			}
+			let end = report.phases.last().ok_or(format!("No stop planned from ({}, {})", v0, a0))?.end;
+			if end.1.abs() > CLOSE_ENOUGH || end.2.abs() > CLOSE_ENOUGH {
+				return Err(format!("Stop from ({}, {}) ended in {:?}", v0, a0, end));
+			}
+			let t_bound = (a0.abs() + 2.0*MAX_ACC)/JERK + (v0.abs() + 0.5*a0.powi(2)/JERK)/MAX_ACC;
+			if report.get_duration() > t_bound {
+				return Err(format!("Stop from ({}, {}) took {}", v0, a0, report.get_duration()));
+			}
+			path.update(report.get_duration() + 0.001);
+			if path.is_active() || path.target != end.0 {
+				return Err(format!("Stop from ({}, {}) did not end at {}", v0, a0, end.0));
+			}
+		}
+		Ok(())
+	}
+
+	/*
+	 * stop() while braking harder than a braking limit set since: the
+	 * acceleration ramps back to the limit first, and the stop stays
+	 * within it from there.
+	 */
+	#[test]
+	fn jlim_stop_above_max_dec() -> Result<(), String> {
+		let mut path = Path::jerk_limited(1.0, 10.0);
+		path.replan(0.05, 0.5);
+		path.update(0.3);
+		let a0 = path.state[1];
+		if a0 > -0.2 {
+			return Err(format!("Expected to brake harder than 0.2, at {}", a0));
+		}
+		path.set_max_dec(Some(0.2));
+		path.stop();
+		path.checked_invariants().map_err(|err| err.to_string())?;
+
+		let report = path.plan_report();
+		for phase in report.phases.iter().skip(1) {
+			if phase.peak_acc > 0.2*(1.0 + 1e-12) {
+				return Err(format!("Stop above the braking limit in {:?}", phase));
+			}
+		}
+		let end = report.phases.last().ok_or("No stop planned")?.end;
+		if end.1.abs() > CLOSE_ENOUGH || end.2.abs() > CLOSE_ENOUGH {
+			return Err(format!("Stop ended in {:?}", end));
+		}
+		Ok(())
+	}
+
+	/*
+	 * Target behind the stopping point with overshoot forbidden: full stop
+	 * first, then the move back from rest. The excursion is the stopping
+	 * distance, v*(v/a + a/j)/2 from v_max.
+	 */
+	#[test]
+	fn jlim_forbid_overshoot() -> Result<(), String> {
+		use crate::trace::Branch;
+
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		path.set_overshoot_policy(OvershootPolicy::Forbid);
+		path.set_trace(true);
+		path.replan(1.0, 0.1);
+		path.update(0.5);
+		let s_target = path.get_state().0;
+		path.replan(s_target, 0.02);
+
+		let trace = path.plan_trace().unwrap();
+		let excursion = trace.steps[0].get("excursion").unwrap_or(0.0);
+		if trace.steps[0].branch != Branch::StopFirst || (excursion - 0.015).abs() > 1e-9 {
+			return Err(format!("Expected a stop first with excursion 0.015: {:?}", trace));
+		}
+		let overshoot = path.plan_report().overshoot.ok_or("No excursion in the report")?;
+		if (overshoot.distance - excursion).abs() > 1e-9 {
+			return Err(format!("Reported excursion {} vs {}", overshoot.distance, excursion));
+		}
+
+		let mut s_max = s_target;
+		while path.is_active() {
+			path.update(0.001);
+			path.checked_invariants().map_err(|err| err.to_string())?;
+			s_max = s_max.max(path.get_state().0);
+		}
+		if s_max > s_target + excursion + 1e-9 || path.get_state() != (s_target, 0.0, 0.0) {
+			return Err(format!("Passed {} past the target, ended in {:?}", s_max - s_target, path.get_state()));
+		}
+		Ok(())
+	}
+
+	// Interrupted moves at v_max: target behind, so far that the way back coasts, or so close that it can't
+	#[test]
+	fn jlim_reversed_move() -> Result<(), String> {
+		use crate::trace::Branch;
+
+		for (s_back, coasts) in [(-0.1, true), (-0.002, false)].iter() {
+			let mut path = Path::jerk_limited(0.5, 5.0);
+			path.set_trace(true);
+			path.replan(1.0, 0.1);
+			path.update(0.5);
+			let (s0, v0, _) = path.get_state();
+			let s_target = s0 + 0.5*v0*v0/0.5 + s_back;
+			path.replan(s_target, 0.1);
+
+			let branches = path.plan_trace().unwrap().branches();
+			if branches.contains(&Branch::Coast) != *coasts || branches.contains(&Branch::PeakLowered) == *coasts {
+				return Err(format!("Back by {}: {:?}", s_back, branches));
+			}
+			let mut v_min = v0;
+			while path.is_active() {
+				path.update(0.001);
+				path.checked_invariants().map_err(|err| err.to_string())?;
+				v_min = v_min.min(path.get_state().1);
+			}
+			if v_min >= 0.0 || (path.get_state().0 - s_target).abs() > 1e-9 || path.get_state().1 != 0.0 {
+				return Err(format!("Back by {}: lowest velocity {}, ended in {:?}", s_back, v_min, path.get_state()));
+			}
+		}
+		Ok(())
+	}
+
+	// Replans found by fuzzing: the peak velocity at v_rest, and braking below v_rest from a non-zero acceleration
+	#[test]
+	fn jlim_lowered_peak_edge_cases() -> Result<(), String> {
+		let mut path = Path::jerk_limited(0.225, 107.0);
+		path.replan(-0.8745098039215686, 0.3158823529411765);
+		path.update(0.04301730103806229);
+		path.replan(-0.8509803921568627, 0.798235294117647);
+		path.checked_invariants().map_err(|err| err.to_string())?;
+
+		let mut braking = Path::jerk_limited(10.1625, 159.0);
+		braking.set_state((0.040900055339332046, 0.309253534352638, -6.690103806228372));
+		braking.replan(0.050980392156862786, 0.9472549019607843);
+		braking.checked_invariants().map_err(|err| err.to_string())?;
+
+		// Still ramping the acceleration down, with the target within rounding of where that ends
+		let mut ramping = Path::jerk_limited(2.6625, 132.0);
+		ramping.set_state((0.0009418823005843314, 1.3676237557467413e-5, -0.06008765859284915));
+		ramping.replan(0.0009418843757689103, 0.4178);
+		ramping.checked_invariants().map_err(|err| err.to_string())?;
+
+		// Ramping down ends moving away from the target, and a peak between that and rest reaches it
+		let mut away = Path::jerk_limited(12.225, 60.0);
+		away.set_state((-0.6705882352941177, 0.1352941176470588, -4.075));
+		away.replan(-0.6696479752924595, 0.9864705882352941);
+		away.checked_invariants().map_err(|err| err.to_string())?;
+
+		for path in [path, braking, ramping, away].iter_mut() {
+			while path.is_active() {
+				path.update(0.001);
+				path.checked_invariants().map_err(|err| err.to_string())?;
+			}
+		}
+
+		// Above v_limit and only just not overshooting with the approach acceleration
+		let mut path = Path::acc_limited(15.1625);
+		path.set_state((-0.03082745917806843, -0.36803725093002926, 15.1625));
+		path.replan(-0.03529411764705881, 0.2727450980392157);
+		path.checked_invariants().map_err(|err| err.to_string())?;
+		while path.is_active() {
+			path.update(0.001);
+			path.checked_invariants().map_err(|err| err.to_string())?;
+		}
+		Ok(())
+	}
+
 	// Interrupted moves at v_max: same v_max, same direction
 	// Interrupted moves below v_max: same v_max, same direction
 	// Interrupted moves at v_max: same v_max, other direction
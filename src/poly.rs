@@ -21,9 +21,164 @@
 
 #![allow(non_snake_case)]
 
-pub struct Poly;
+use crate::math;
+
+// Highest degree real_roots_into() solves without allocating: that of a quintic
+pub(crate) const MAX_FIXED_DEGREE: usize = 5;
+
+/*
+ * Polynomial in one variable, with coefficients stored constant term
+ * first: coeffs[n] belongs to x^n.
+ */
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct Polynomial {
+	coeffs: Vec<f64>,
+}
+
+impl Polynomial {
+	pub fn new(coeffs: Vec<f64>) -> Self {
+		Self {
+			coeffs,
+		}
+	}
+
+	pub fn coeffs(&self) -> &[f64] {
+		&self.coeffs
+	}
+
+	// Coefficient of x^n, zero beyond the stored ones
+	pub fn coeff(&self, n: usize) -> f64 {
+		self.coeffs.get(n).copied().unwrap_or(0.0)
+	}
+
+	// Degree ignoring zero leading coefficients; the zero polynomial has degree 0
+	pub fn degree(&self) -> usize {
+		self.coeffs.iter().rposition(|coeff| *coeff != 0.0).unwrap_or(0)
+	}
+
+	pub fn eval(&self, x: f64) -> f64 {
+		Self::eval_coeffs(&self.coeffs, x)
+	}
+
+	// Same as eval(), for coefficients stored the same way outside a Polynomial
+	pub(crate) fn eval_coeffs(coeffs: &[f64], x: f64) -> f64 {
+		coeffs.iter().rev().fold(0.0, |acc, coeff| acc*x + coeff)
+	}
+
+	pub fn derivative(&self) -> Self {
+		Self::new(self.coeffs.iter().enumerate().skip(1).map(|(n, coeff)| n as f64*coeff).collect())
+	}
+
+	// Antiderivative with constant term c
+	pub fn integral(&self, c: f64) -> Self {
+		let mut coeffs = vec![c];
+		coeffs.extend(self.coeffs.iter().enumerate().map(|(n, coeff)| coeff/(n + 1) as f64));
+		Self::new(coeffs)
+	}
+
+	/*
+	 * Real roots in [from, to], in increasing order. Up to degree 2 they are
+	 * solved for directly. Above that the polynomial is monotonic between
+	 * the roots of its derivative and each such interval holds at most one
+	 * root, found by bisection. Roots where the polynomial only touches zero
+	 * may be missed to rounding. A constant has no roots, even if zero.
+	 */
+	pub fn real_roots_in(&self, from: f64, to: f64) -> Vec<f64> {
+		assert!(from <= to);
+
+		let degree = self.degree();
+		let mut roots = if degree == 0 {
+			Vec::new()
+		} else if degree <= 2 {
+			Self::solve_quadratic(self.coeff(2), self.coeff(1), self.coeff(0))
+		} else {
+			let mut bounds = vec![from];
+			bounds.append(&mut self.derivative().real_roots_in(from, to));
+			bounds.push(to);
+			bounds.windows(2).filter_map(|w| Self::bisect(&self.coeffs, w[0], w[1])).collect()
+		};
+		roots.retain(|root| *root >= from && *root <= to);
+		roots.dedup();
+		roots
+	}
+
+	/*
+	 * Same as real_roots_in(), for coefficients stored the same way up to
+	 * degree MAX_FIXED_DEGREE, without allocating: (number of roots, roots)
+	 */
+	pub(crate) fn real_roots_into(coeffs: &[f64], from: f64, to: f64) -> (usize, [f64; MAX_FIXED_DEGREE]) {
+		assert!(from <= to);
+
+		let degree = coeffs.iter().rposition(|coeff| *coeff != 0.0).unwrap_or(0);
+		assert!(degree <= MAX_FIXED_DEGREE);
+		let mut found = [0.0; MAX_FIXED_DEGREE];
+		let found_count = if degree == 0 {
+			0
+		} else if degree <= 2 {
+			let coeff = |n: usize| coeffs.get(n).copied().unwrap_or(0.0);
+			let (count, roots) = Self::quadratic_roots(coeff(2), coeff(1), coeff(0));
+			found[..count].copy_from_slice(&roots[..count]);
+			count
+		} else {
+			let mut derivative = [0.0; MAX_FIXED_DEGREE];
+			for n in 1..=degree {
+				derivative[n - 1] = n as f64*coeffs[n];
+			}
+			let (count, bounds) = Self::real_roots_into(&derivative[..degree], from, to);
+			let mut found_count = 0;
+			let mut lo = from;
+			for hi in bounds[..count].iter().copied().chain(std::iter::once(to)) {
+				if let Some(root) = Self::bisect(&coeffs[..=degree], lo, hi) {
+					found[found_count] = root;
+					found_count += 1;
+				}
+				lo = hi;
+			}
+			found_count
+		};
+
+		let mut roots = [0.0; MAX_FIXED_DEGREE];
+		let mut count = 0;
+		for root in found[..found_count].iter().copied() {
+			if root >= from && root <= to && (count == 0 || roots[count - 1] != root) {
+				roots[count] = root;
+				count += 1;
+			}
+		}
+		(count, roots)
+	}
+
+	fn bisect(coeffs: &[f64], mut lo: f64, mut hi: f64) -> Option<f64> {
+		let eval = |x: f64| Self::eval_coeffs(coeffs, x);
+		let (val_lo, val_hi) = (eval(lo), eval(hi));
+		if val_lo == 0.0 {
+			return Some(lo);
+		}
+		if val_hi == 0.0 {
+			return Some(hi);
+		}
+		if val_lo.signum() == val_hi.signum() {
+			return None;
+		}
+
+		loop {
+			let mid = 0.5*(lo + hi);
+			if mid <= lo || mid >= hi {
+				return Some(mid);
+			}
+			let val = eval(mid);
+			if val == 0.0 {
+				return Some(mid);
+			}
+			if val.signum() == val_lo.signum() {
+				lo = mid;
+			} else {
+				hi = mid;
+			}
+		}
+	}
 
-impl Poly {
 	/* GSL code written to return +1.0 if num == -0.0. Rust's num.signum()
 	 * returns -1.0 if num == -0.0, so we can't use that. */
 	fn sgn(num: f64) -> f64 {
@@ -34,26 +189,27 @@ impl Poly {
 	}
 
 	pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
-		let mut res = Vec::new();
+		let (count, roots) = Self::quadratic_roots(a, b, c);
+		roots[..count].to_vec()
+	}
 
+	// Same as solve_quadratic(), without allocating: (number of roots, roots)
+	pub(crate) fn quadratic_roots(a: f64, b: f64, c: f64) -> (usize, [f64; 2]) {
 		// Handle linear case
 		if a == 0.0 {
 			if b == 0.0 {
-				return res;
+				return (0, [0.0; 2]);
 			} else {
-				res.push(-c/b);
-				return res;
+				return (1, [-c/b, 0.0]);
 			}
 		}
 
-		let disc = b.powi(2) - 4.0*a*c;
+		let disc = math::powi(b, 2) - 4.0*a*c;
 
 		if disc > 0.0 {
 			if b == 0.0 {
 				let r = (-c/a).sqrt();
-				res.push(-r);
-				res.push(r);
-				return res;
+				return (2, [-r, r]);
 			}
 
 			let temp = -0.5*(b + Self::sgn(b)*disc.sqrt());
@@ -61,38 +217,32 @@ impl Poly {
 			let r2 = c/temp;
 
 			if r1 < r2 {
-				res.push(r1);
-				res.push(r2);
+				return (2, [r1, r2]);
 			} else {
-				res.push(r2);
-				res.push(r1);
+				return (2, [r2, r1]);
 			}
-
-			return res;
 		}
 
 		if disc == 0.0 {
-			res.push(-0.5*b/a);
-			res.push(-0.5*b/a);
-			return res;
+			return (2, [-0.5*b/a, -0.5*b/a]);
 		}
 
 		// Discriminant < 0.0; no roots
-		return res;
+		(0, [0.0; 2])
 	}
 
 	fn gsl_poly_solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
-		let q = a.powi(2) - 3.0*b;
-		let r = 2.0*a.powi(3) - 9.0*a*b + 27.0*c;
+		let q = math::powi(a, 2) - 3.0*b;
+		let r = 2.0*math::powi(a, 3) - 9.0*a*b + 27.0*c;
 
 		let Q = q/9.0;
 		let R = r/54.0;
 
-		let Q3 = Q.powi(3);
-		let R2 = R.powi(2);
+		let Q3 = math::powi(Q, 3);
+		let R2 = math::powi(R, 2);
 
-		let CR2 = 729.0*r.powi(2);
-		let CQ3 = 2916.0*q.powi(3);
+		let CR2 = 729.0*math::powi(r, 2);
+		let CQ3 = 2916.0*math::powi(q, 3);
 
 		let mut res = Vec::new();
 
@@ -127,11 +277,11 @@ impl Poly {
 
 		if R2 < Q3 {
 			let ratio = Self::sgn(R)*(R2/Q3).sqrt();
-			let theta = ratio.acos();
+			let theta = math::acos(ratio);
 			let norm = -2.0*Q.sqrt();
-			res.push(norm*(theta/3.0).cos() - a/3.0);
-			res.push(norm*((theta + 2.0*std::f64::consts::PI)/3.0).cos() - a/3.0);
-			res.push(norm*((theta - 2.0*std::f64::consts::PI)/3.0).cos() - a/3.0);
+			res.push(norm*math::cos(theta/3.0) - a/3.0);
+			res.push(norm*math::cos((theta + 2.0*std::f64::consts::PI)/3.0) - a/3.0);
+			res.push(norm*math::cos((theta - 2.0*std::f64::consts::PI)/3.0) - a/3.0);
 
 			// Sort roots into increasing order
 			res.sort_by(|a, b| a.partial_cmp(b).unwrap());
@@ -139,13 +289,82 @@ impl Poly {
 			return res;
 		}
 
-		let A = -Self::sgn(R)*(R.abs() + (R2 - Q3).sqrt()).powf(1.0/3.0);
+		let A = -Self::sgn(R)*math::cbrt(R.abs() + (R2 - Q3).sqrt());
 		let B = Q/A;
 		res.push(A + B - a/3.0);
-		return res;
+		res
 	}
 
 	pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
 		Self::gsl_poly_solve_cubic(b/a, c/a, d/a)
 	}
 }
+
+/*
+ * Piecewise polynomial in the form used by e.g. Matlab's mkpp() and
+ * SciPy's PPoly: pieces[n] applies between breaks[n] and breaks[n + 1],
+ * as a function of the time since breaks[n].
+ */
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PiecewisePoly {
+	pub breaks: Vec<f64>,
+	pub pieces: Vec<Polynomial>,
+}
+
+impl PiecewisePoly {
+	pub fn new(breaks: Vec<f64>, pieces: Vec<Polynomial>) -> Self {
+		assert_eq!(breaks.len(), pieces.len() + 1);
+		Self {
+			breaks,
+			pieces,
+		}
+	}
+
+	/*
+	 * Value at x, from the piece that starts at x on a break point. Outside
+	 * the breaks the first or last piece is extrapolated.
+	 */
+	pub fn eval(&self, x: f64) -> f64 {
+		if self.pieces.is_empty() {
+			return 0.0;
+		}
+		let n = self.breaks[1..self.pieces.len()].iter().take_while(|brk| x >= **brk).count();
+		self.pieces[n].eval(x - self.breaks[n])
+	}
+
+	pub fn derivative(&self) -> Self {
+		Self::new(self.breaks.clone(), self.pieces.iter().map(Polynomial::derivative).collect())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::Polynomial;
+
+	#[test]
+	fn cubic_roots_derivative_integral() -> Result<(), String> {
+		// (x + 1)(x - 0.5)(x - 2) = x³ - 1.5x² - 1.5x + 1
+		let poly = Polynomial::new(vec![1.0, -1.5, -1.5, 1.0]);
+		let roots = poly.real_roots_in(-3.0, 3.0);
+		let expected = [-1.0, 0.5, 2.0];
+		if roots.len() != expected.len() || roots.iter().zip(expected.iter()).any(|(a, b)| (a - b).abs() > 1e-12) {
+			return Err(format!("Roots {:?}, expected {:?}", roots, expected));
+		}
+		if poly.real_roots_in(0.0, 1.0).len() != 1 {
+			return Err("Expected a single root in [0, 1]".to_string());
+		}
+		let (count, fixed) = Polynomial::real_roots_into(poly.coeffs(), -3.0, 3.0);
+		if fixed[..count] != roots[..] {
+			return Err(format!("Roots without allocating {:?}, expected {:?}", &fixed[..count], roots));
+		}
+		if poly.derivative() != Polynomial::new(vec![-1.5, -3.0, 3.0]) {
+			return Err(format!("Derivative {:?}", poly.derivative()));
+		}
+		if poly.derivative().integral(1.0) != poly {
+			return Err(format!("Integral of derivative {:?}", poly.derivative().integral(1.0)));
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,110 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+/*
+ * Starting points for common classes of hardware, in metres and seconds.
+ * They are typical values, not the limits of any one machine: start from
+ * the closest one and tune from there, e.g. with tuning::sweep().
+ *
+ *   let mut path = presets::printer_xy().path();
+ *   path.replan(0.1, 0.2);
+ */
+
+use crate::path::{EndOfMovePolicy, Limits, Path};
+
+/*
+ * Limits for the path, and how far off the target (in any state value)
+ * the end of a move may be before it counts as a deviation rather than
+ * rounding, see EndOfMovePolicy::ErrorIfDeviationExceeds.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Preset {
+	pub limits: Limits,
+	pub end_tolerance: f64,
+}
+
+impl Preset {
+	// A jerk-limited path with the limits and end of move tolerance
+	pub fn path(&self) -> Path<3> {
+		let mut path = Path::with_limits(self.limits);
+		path.set_end_of_move_policy(EndOfMovePolicy::ErrorIfDeviationExceeds(self.end_tolerance));
+		path
+	}
+}
+
+fn preset(vel: f64, acc: f64, jerk: f64, end_tolerance: f64) -> Preset {
+	Preset {
+		limits: Limits {
+			vel: Some(vel),
+			..Limits::jerk_limited(acc, jerk)
+		},
+		end_tolerance,
+	}
+}
+
+// Belt-driven XY axes of a 3D printer or laser cutter: light, fast, some belt stretch
+pub fn printer_xy() -> Preset {
+	preset(0.3, 3.0, 300.0, 1e-6)
+}
+
+// Leadscrew Z axis of the same kind of machine
+pub fn printer_z() -> Preset {
+	preset(0.01, 0.5, 50.0, 1e-6)
+}
+
+// Rack-and-pinion gantry of a few hundred kg: a low jerk keeps the frame from ringing
+pub fn heavy_gantry() -> Preset {
+	preset(1.0, 1.0, 5.0, 1e-5)
+}
+
+// Ballscrew axis of a milling machine
+pub fn cnc_mill() -> Preset {
+	preset(0.1, 2.0, 50.0, 1e-6)
+}
+
+// Piezo nanopositioning stage: tens of µm of travel, nm resolution
+pub fn piezo_stage() -> Preset {
+	preset(0.001, 1.0, 1e4, 1e-10)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::{printer_xy, printer_z, heavy_gantry, cnc_mill, piezo_stage};
+
+	#[test]
+	fn presets_are_valid_and_finish_moves() -> Result<(), String> {
+		let presets = [("printer_xy", printer_xy(), 0.1), ("printer_z", printer_z(), 0.005), ("heavy_gantry", heavy_gantry(), 1.0),
+			("cnc_mill", cnc_mill(), 0.05), ("piezo_stage", piezo_stage(), 20e-6)];
+		for (name, preset, distance) in presets.iter() {
+			preset.limits.validate().map_err(|err| format!("{}: {}", name, err))?;
+
+			let mut path = preset.path();
+			path.replan(*distance, f64::INFINITY);
+			while path.is_active() {
+				path.update(0.001);
+			}
+			if let Some(err) = path.take_error() {
+				return Err(format!("{}: {}", name, err));
+			}
+			if path.get_state() != (*distance, 0.0, 0.0) {
+				return Err(format!("{}: ended in {:?}", name, path.get_state()));
+			}
+		}
+		Ok(())
+	}
+}
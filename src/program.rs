@@ -0,0 +1,310 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::sync::Arc;
+use crate::path::{Path, Limits, State};
+use crate::segment::Segment;
+
+/*
+ * Programs planned offline, for CNC-style users who want the whole
+ * trajectory fixed up front instead of replanning online:
+ *
+ * let program = Program::compile(&[Move::new(0.04, 0.1), Move::new(0.1, 0.1)], limits);
+ * let mut player = program.player();
+ * let (pos, vel, acc) = player.update(0.001);
+ *
+ * The moves blend like QueuePolicy::Blend, except that each next move
+ * starts exactly when the one before it starts its final braking rather
+ * than at the first update() after that, so the same moves and limits
+ * always give the same program.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Move {
+	pub target: f64,
+	pub v_limit: f64,
+}
+
+impl Move {
+	pub fn new(target: f64, v_limit: f64) -> Self {
+		Self { target, v_limit }
+	}
+}
+
+pub struct Program;
+
+impl Program {
+	// Acc- or jerk-limited depending on limits.jerk, see Path::with_limits()
+	pub fn compile(moves: &[Move], limits: Limits) -> CompiledProgram {
+		let (segments, move_starts, end) = match limits.jerk {
+			None => Self::plan(Path::<2>::with_limits(limits), moves),
+			Some(_) => Self::plan(Path::<3>::with_limits(limits), moves),
+		};
+		let mut breaks = vec![0.0];
+		for seg in &segments {
+			breaks.push(breaks.last().unwrap() + seg.get_duration());
+		}
+		CompiledProgram {
+			inner: Arc::new(Compiled {
+				segments,
+				breaks,
+				move_starts,
+				end,
+				state_len: if limits.jerk.is_some() { 4 } else { 3 },
+			}),
+		}
+	}
+
+	// The segments, the start time of each move and the final position
+	fn plan<const DEGREE: usize>(mut path: Path<DEGREE>, moves: &[Move]) -> (Vec<Segment>, Vec<f64>, f64) {
+		let mut segments = Vec::new();
+		let mut move_starts = Vec::new();
+		let mut t = 0.0;
+		for (n, next) in moves.iter().enumerate() {
+			path.replan(next.target, next.v_limit);
+			let mut plan = Vec::new();
+			while let Some(seg) = path.take_next_segment() {
+				plan.push(seg);
+			}
+
+			// Run up to the final braking, where the next move takes over
+			let run = if n + 1 == moves.len() { plan.len() } else { Self::braking_start(&plan, DEGREE + 1) };
+			let duration: f64 = plan[..run].iter().map(|seg| seg.get_duration()).sum();
+			move_starts.push(t);
+			segments.extend(plan.drain(..run));
+			path.update(duration);
+			t += duration;
+		}
+		(segments, move_starts, moves.last().map_or(0.0, |last| last.target))
+	}
+
+	// Index of the first segment from which the speed only goes down, like Path::braking()
+	fn braking_start(plan: &[Segment], state_len: usize) -> usize {
+		let vel_index = state_len - 2;
+		let braking = |seg: &Segment| seg.end_state()[vel_index].abs() < seg.state_at(0.0)[vel_index].abs();
+		plan.len() - plan.iter().rev().take_while(|seg| braking(seg)).count()
+	}
+}
+
+#[derive(Debug)]
+struct Compiled {
+	segments: Vec<Segment>,
+	breaks: Vec<f64>,	// Start time of each segment, then the end of the program
+	move_starts: Vec<f64>,
+	end: f64,		// Position at the end, exactly at the last target
+	state_len: usize,
+}
+
+/*
+ * Immutable result of Program::compile(). Clones share the plan, so one
+ * program can feed any number of players, e.g. one per thread.
+ */
+#[derive(Clone, Debug)]
+pub struct CompiledProgram {
+	inner: Arc<Compiled>,
+}
+
+impl CompiledProgram {
+	pub fn segments(&self) -> &[Segment] {
+		&self.inner.segments
+	}
+
+	pub fn duration(&self) -> f64 {
+		*self.inner.breaks.last().unwrap()
+	}
+
+	// Time into the program at which each move starts
+	pub fn move_starts(&self) -> &[f64] {
+		&self.inner.move_starts
+	}
+
+	// (pos, vel, acc) at time t into the program, at rest at the end after it
+	pub fn state_at(&self, t: f64) -> (f64, f64, f64) {
+		let (index, t) = self.locate(t);
+		self.segment_state(index, t)
+	}
+
+	pub fn player(&self) -> Player {
+		Player {
+			program: self.clone(),
+			index: 0,
+			time: 0.0,
+			playing: true,
+			feed: 1.0,
+		}
+	}
+
+	// Segment at time t into the program, and the time into it
+	fn locate(&self, t: f64) -> (usize, f64) {
+		let index = self.inner.breaks.partition_point(|start| *start <= t).saturating_sub(1);
+		(index, t - self.inner.breaks[index])
+	}
+
+	fn segment_state(&self, index: usize, t: f64) -> (f64, f64, f64) {
+		let len = self.inner.state_len;
+		match self.inner.segments.get(index) {
+			Some(seg) => {
+				let state = seg.state_at(t);
+				(state[len - 1], state[len - 2], state[len - 3])
+			},
+			None => (self.inner.end, 0.0, 0.0),
+		}
+	}
+}
+
+/*
+ * Runs a CompiledProgram like a job on a machine: playing or paused, with
+ * seek() and single steps, and a feed override that runs the program k
+ * times faster. The state is that of the axis, so the velocity is scaled
+ * by the override and the acceleration by its square; paused, the axis
+ * holds its position at rest. A new player is playing at 100% feed.
+ */
+#[derive(Clone, Debug)]
+pub struct Player {
+	program: CompiledProgram,
+	index: usize,		// Current segment
+	time: f64,		// Time into it
+	playing: bool,
+	feed: f64,		// Feed override, program seconds per second
+}
+
+impl Player {
+	// Advance dt seconds of the caller's clock, and return the new state
+	pub fn update(&mut self, dt: f64) -> (f64, f64, f64) {
+		assert!(dt >= 0.0);
+		self.advance(dt*self.rate());
+		self.get_state()
+	}
+
+	pub fn get_state(&self) -> (f64, f64, f64) {
+		let (pos, vel, acc) = self.program.segment_state(self.index, self.time);
+		let k = self.rate();
+		(pos, k*vel, k*k*acc)
+	}
+
+	// get_state() as a State, like Path::plan_between() takes
+	pub fn state(&self) -> State {
+		self.get_state().into()
+	}
+
+	pub fn play(&mut self) {
+		self.playing = true;
+	}
+
+	pub fn pause(&mut self) {
+		self.playing = false;
+	}
+
+	pub fn is_playing(&self) -> bool {
+		self.playing
+	}
+
+	// Jump to t seconds into the program, without running through the way there
+	pub fn seek(&mut self, t: f64) {
+		assert!(t >= 0.0);
+		let (index, time) = self.program.locate(t);
+		self.index = index;
+		self.time = time;
+	}
+
+	// Advance dt seconds of program time, also when paused, e.g. to single-step a paused job
+	pub fn step(&mut self, dt: f64) -> (f64, f64, f64) {
+		assert!(dt >= 0.0);
+		self.advance(dt);
+		self.get_state()
+	}
+
+	pub fn set_feed_override(&mut self, k: f64) {
+		assert!(k >= 0.0 && k.is_finite());
+		self.feed = k;
+	}
+
+	// Time into the program
+	pub fn time(&self) -> f64 {
+		self.program.inner.breaks[self.index.min(self.program.segments().len())] + self.time
+	}
+
+	pub fn is_done(&self) -> bool {
+		self.index >= self.program.segments().len()
+	}
+
+	fn rate(&self) -> f64 {
+		if self.playing { self.feed } else { 0.0 }
+	}
+
+	fn advance(&mut self, dt: f64) {
+		self.time += dt;
+		let segments = self.program.segments();
+		while self.index < segments.len() && self.time >= segments[self.index].get_duration() {
+			self.time -= segments[self.index].get_duration();
+			self.index += 1;
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::{Program, Move};
+	use crate::path::Limits;
+	use crate::scenario::{check_eq, check_states_eq};
+
+	#[test]
+	fn blended_moves() -> Result<(), String> {
+		// Cruise on into the second move, then brake and reverse into the third
+		let moves = [Move::new(0.04, 0.1), Move::new(0.1, 0.1), Move::new(0.05, 0.1)];
+		let program = Program::compile(&moves, Limits::acc_limited(0.5));
+		check_eq(0.0, program.duration(), 1.9)?;
+		check_states_eq(1.0, program.state_at(1.0), (0.09, 0.1, -0.5))?;
+
+		let (mut first, mut second) = (program.player(), program.clone().player());
+		let mut t = 0.0;
+		while !first.is_done() {
+			t += 0.001;
+			let state = first.update(0.001);
+			check_states_eq(t, state, second.update(0.001))?;
+			// The acceleration differs at the instants where it jumps
+			let (pos, vel, _) = program.state_at(t);
+			check_states_eq(t, (state.0, state.1, 0.0), (pos, vel, 0.0))?;
+		}
+		check_states_eq(t, first.get_state(), (0.05, 0.0, 0.0))
+	}
+
+	#[test]
+	fn pause_seek_and_feed() -> Result<(), String> {
+		let program = Program::compile(&[Move::new(0.04, 0.1)], Limits::acc_limited(0.5));
+		let mut player = program.player();
+		player.update(0.3);
+		player.pause();
+		check_states_eq(0.3, player.update(0.1), (0.02, 0.0, 0.0))?;
+		check_states_eq(0.3, player.step(0.05), (0.025, 0.0, 0.0))?;
+
+		player.play();
+		player.set_feed_override(0.5);
+		check_states_eq(0.35, player.get_state(), (0.025, 0.05, 0.0))?;
+		player.update(0.2);
+		check_eq(0.45, player.time(), 0.45)?;
+		check_states_eq(0.45, player.state().into(), (0.034375, 0.0375, -0.125))?;
+
+		player.seek(0.1);
+		check_eq(0.1, player.time(), 0.1)?;
+		check_states_eq(0.1, player.get_state(), (0.0025, 0.025, 0.125))?;
+		player.seek(1.0);
+		if !player.is_done() || player.get_state() != (0.04, 0.0, 0.0) {
+			return Err(format!("Expected done at the end, at {:?}", player.get_state()));
+		}
+		Ok(())
+	}
+}
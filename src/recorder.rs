@@ -0,0 +1,176 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::io::{BufRead, Write};
+
+/*
+ * Records (time, pos, vel, acc) frames, e.g. from Path::get_state() after
+ * each update(), for later playback or export. Frames must be recorded in
+ * time order.
+ */
+#[derive(Default)]
+pub struct Recorder {
+	frames: Vec<[f64; 4]>,
+}
+
+impl Recorder {
+	pub fn new() -> Self {
+		Self {
+			frames: Vec::new(),
+		}
+	}
+
+	pub fn record(&mut self, t: f64, state: (f64, f64, f64)) {
+		if let Some(last) = self.frames.last() {
+			assert!(t >= last[0]);
+		}
+		self.frames.push([t, state.0, state.1, state.2]);
+	}
+
+	pub fn len(&self) -> usize {
+		self.frames.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.frames.is_empty()
+	}
+
+	pub fn clear(&mut self) {
+		self.frames.clear();
+	}
+
+	/*
+	 * One frame per line: "time pos vel acc". Values are written with
+	 * enough precision to be read back bit-exact by import().
+	 */
+	pub fn export(&self, writer: &mut dyn Write) -> std::io::Result<()> {
+		for frame in &self.frames {
+			writeln!(writer, "{} {} {} {}", frame[0], frame[1], frame[2], frame[3])?;
+		}
+		Ok(())
+	}
+
+	pub fn import(reader: &mut dyn BufRead) -> std::io::Result<Self> {
+		let mut recorder = Self::new();
+		for line in reader.lines() {
+			let line = line?;
+			if line.trim().is_empty() {
+				continue;
+			}
+
+			let vals: Result<Vec<f64>, _> = line.split_whitespace().map(|val| val.parse::<f64>()).collect();
+			let vals = vals.map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+			if vals.len() != 4 {
+				return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Expected 4 values, got '{}'", line)));
+			}
+			recorder.record(vals[0], (vals[1], vals[2], vals[3]));
+		}
+		Ok(recorder)
+	}
+
+	pub fn playback(&self) -> Playback<'_> {
+		Playback {
+			frames: &self.frames,
+			time: self.frames.first().map_or(0.0, |frame| frame[0]),
+			index: 0,
+		}
+	}
+}
+
+/*
+ * Plays back a recording using the same update()/get_state()/is_active()
+ * calls as a Path. States between recorded frames are interpolated
+ * linearly.
+ */
+pub struct Playback<'a> {
+	frames: &'a [[f64; 4]],
+	time: f64,
+	index: usize,		// frames[index] is the last frame at or before time
+}
+
+impl<'a> Playback<'a> {
+	pub fn update(&mut self, dt: f64) {
+		assert!(dt >= 0.0);
+		self.time += dt;
+		while self.index + 1 < self.frames.len() && self.frames[self.index + 1][0] <= self.time {
+			self.index += 1;
+		}
+	}
+
+	pub fn get_state(&self) -> (f64, f64, f64) {
+		if self.frames.is_empty() {
+			return (0.0, 0.0, 0.0);
+		}
+
+		let frame = &self.frames[self.index];
+		if self.index + 1 == self.frames.len() {
+			return (frame[1], frame[2], frame[3]);
+		}
+
+		let next = &self.frames[self.index + 1];
+		let span = next[0] - frame[0];
+		let k = if span > 0.0 { ((self.time - frame[0])/span).max(0.0) } else { 0.0 };
+		let lerp = |n: usize| frame[n] + k*(next[n] - frame[n]);
+		(lerp(1), lerp(2), lerp(3))
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.index + 1 < self.frames.len()
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::Recorder;
+	use crate::path::Path;
+
+	#[test]
+	fn record_export_import_playback() -> Result<(), String> {
+		let dt = 0.001;
+		let mut path = Path::acc_limited(0.5);
+		let mut recorder = Recorder::new();
+		path.replan(0.04, 0.1);
+		let mut t = 0.0;
+		recorder.record(t, path.get_state());
+		while path.is_active() {
+			path.update(dt);
+			t += dt;
+			recorder.record(t, path.get_state());
+		}
+
+		let mut buf = Vec::new();
+		recorder.export(&mut buf).map_err(|err| err.to_string())?;
+		let imported = Recorder::import(&mut &buf[..]).map_err(|err| err.to_string())?;
+		if imported.frames != recorder.frames {
+			return Err("Imported recording differs from exported one".to_string());
+		}
+
+		let mut playback = imported.playback();
+		playback.update(0.3);
+		let state = playback.get_state();
+		if (state.0 - 0.02).abs() > 1e-9 || (state.1 - 0.1).abs() > 1e-9 {
+			return Err(format!("Playback state {:?} at 0.3 s is off", state));
+		}
+		while playback.is_active() {
+			playback.update(dt);
+		}
+		if playback.get_state() != path.get_state() {
+			return Err(format!("Playback ended at {:?}, path at {:?}", playback.get_state(), path.get_state()));
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::path::Path;
+
+/*
+ * One sample of a geometric path run through in time: arc length s with
+ * its time derivatives, and the pose given by the geometry at s.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct Sample {
+	pub t: f64,
+	pub s: f64,
+	pub vel: f64,
+	pub acc: f64,
+	pub pose: Vec<f64>,
+}
+
+/*
+ * Time parameterization of an N-D geometric path: let path (configured
+ * with the tangential acc/jerk limits) drive the arc length from 0 to
+ * length, and sample the result every dt.
+ *
+ * geometry(s) returns (pose, curvature) at arc length s, 0 <= s <= length.
+ * With lateral_acc set, the curvature is sampled along the path and the
+ * velocity is capped to sqrt(lateral_acc/curvature) for the sharpest
+ * point, so the centripetal acceleration stays within lateral_acc all the
+ * way. This is conservative for paths with a few sharp bends.
+ *
+ * The path should be at rest; its current position counts as s = 0.
+ */
+pub fn sample_along<const DEGREE: usize, F>(mut path: Path<DEGREE>, length: f64, geometry: F, v_max: f64, lateral_acc: Option<f64>, dt: f64) -> Vec<Sample>
+	where F: Fn(f64) -> (Vec<f64>, f64)
+{
+	assert!(length >= 0.0);
+	assert!(v_max > 0.0);
+	assert!(dt > 0.0);
+
+	let mut v_limit = v_max;
+	if let Some(lateral_acc) = lateral_acc {
+		assert!(lateral_acc > 0.0);
+		let steps = (length/(v_max*dt)).ceil().max(1.0) as usize;
+		let curvature = (0..=steps)
+			.map(|n| geometry(length*n as f64/steps as f64).1.abs())
+			.fold(0.0, f64::max);
+		if curvature > 0.0 {
+			v_limit = v_limit.min((lateral_acc/curvature).sqrt());
+		}
+	}
+
+	let s0 = path.get_state().0;
+	path.replan(s0 + length, v_limit);
+
+	let sample = |t: f64, state: (f64, f64, f64)| {
+		let s = (state.0 - s0).clamp(0.0, length);
+		Sample {
+			t,
+			s,
+			vel: state.1,
+			acc: state.2,
+			pose: geometry(s).0,
+		}
+	};
+
+	let mut t = 0.0;
+	let mut samples = vec![sample(t, path.get_state())];
+	while path.is_active() {
+		path.update(dt);
+		t += dt;
+		samples.push(sample(t, path.get_state()));
+	}
+	samples
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::sample_along;
+	use crate::path::Path;
+
+	#[test]
+	fn line_and_circle() -> Result<(), String> {
+		// Diagonal line: same timing as a plain 1D move
+		let line = |s: f64| (vec![s*0.6, s*0.8], 0.0);
+		let samples = sample_along(Path::acc_limited(0.5), 0.04, line, 0.1, Some(1.0), 0.001);
+		let last = samples.last().unwrap();
+		if (last.t - 0.6).abs() > 0.002 || (last.pose[0] - 0.024).abs() > 1e-12 || (last.pose[1] - 0.032).abs() > 1e-12 {
+			return Err(format!("Line ended in {:?}", last));
+		}
+
+		// Quarter circle with radius 0.01: v <= sqrt(0.25*0.01) = 0.05
+		let radius = 0.01;
+		let arc = |s: f64| (vec![radius*(s/radius).cos(), radius*(s/radius).sin()], 1.0/radius);
+		let samples = sample_along(Path::acc_limited(0.5), 0.5*std::f64::consts::PI*radius, arc, 0.1, Some(0.25), 0.001);
+		let peak = samples.iter().map(|sample| sample.vel).fold(0.0, f64::max);
+		if (peak - 0.05).abs() > 1e-9 {
+			return Err(format!("Peak velocity {} on the arc", peak));
+		}
+		let end = &samples.last().unwrap().pose;
+		if end[0].abs() > 1e-12 || (end[1] - radius).abs() > 1e-12 {
+			return Err(format!("Arc ended at {:?}", end));
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,394 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::fmt;
+use crate::segment::Segment;
+use crate::poly::Polynomial;
+use crate::path::Limits;
+use crate::error::Invariant;
+
+/*
+ * One phase (segment) of a plan. States are (pos, vel, acc) like
+ * Path::get_state(); peaks are absolute values.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PhaseReport {
+	pub duration: f64,
+	pub start: (f64, f64, f64),
+	pub end: (f64, f64, f64),
+	pub peak_vel: f64,
+	pub peak_acc: f64,
+	pub jerk: f64,
+}
+
+impl PhaseReport {
+	fn new(seg: &Segment) -> Self {
+		let peak = |range: (f64, f64)| range.0.abs().max(range.1.abs());
+		let duration = seg.get_duration();
+		Self {
+			duration,
+			start: Self::triple(&seg.get_state_at(0.0)),
+			end: Self::triple(&seg.get_end_state()),
+			peak_vel: peak(seg.get_range(1, 0.0, duration)),
+			peak_acc: peak(seg.get_range(2, 0.0, duration)),
+			jerk: seg.get_range(3, 0.0, duration).0,
+		}
+	}
+
+	fn triple(state: &[f64]) -> (f64, f64, f64) {
+		let pos_index = state.len() - 1;
+		(state[pos_index], state[pos_index - 1], state[pos_index - 2])
+	}
+
+	// What the phase does with the speed: accelerate, cruise, decelerate, or hold (never ends)
+	pub fn label(&self) -> &'static str {
+		const MIN_CHANGE: f64 = 1e-12;
+		if !self.duration.is_finite() {
+			"hold"
+		} else if self.end.1.abs() > self.start.1.abs() + MIN_CHANGE {
+			"accelerate"
+		} else if self.end.1.abs() < self.start.1.abs() - MIN_CHANGE {
+			"decelerate"
+		} else {
+			"cruise"
+		}
+	}
+}
+
+/*
+ * Furthest position the plan reaches beyond its end position, on the far
+ * side as seen from the start, and when it gets there. E.g. when replanning
+ * to a target closer than the current stopping distance.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Overshoot {
+	pub pos: f64,
+	pub time: f64,
+	pub distance: f64,		// Distance past the end position
+}
+
+/*
+ * What kind of move a plan is, from the shape of its velocity curve. The
+ * S-curves are jerk-limited plans, with or without a cruise at constant
+ * speed; a StopOnly plan only slows down.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum MoveShape {
+	Trapezoid,
+	Triangle,
+	SCurveFull,
+	SCurveNoCruise,
+	OvershootReversal,	// Passes the end position and comes back
+	StopOnly,
+}
+
+/*
+ * Snapshot of a planned trajectory, from the time it was taken until the
+ * end of the plan. Times are from the start of the report, which is at
+ * the timestamp start (see Path::set_epoch()).
+ */
+#[derive(Clone)]
+pub struct PlanReport {
+	pub start: f64,
+	pub phases: Vec<PhaseReport>,
+	pub overshoot: Option<Overshoot>,
+	pub shape: Option<MoveShape>,	// None for no plan, or one that isn't a single move to rest
+	segments: Vec<Segment>,
+}
+
+/*
+ * A limit broken by a plan, see PlanReport::verify(): the peak value in
+ * phase, at time from the start of the report.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Violation {
+	pub invariant: Invariant,
+	pub phase: usize,
+	pub time: f64,
+	pub value: f64,
+	pub limit: f64,
+}
+
+impl fmt::Display for Violation {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?} {} beyond limit {} in phase {} at {} s", self.invariant, self.value, self.limit, self.phase, self.time)
+	}
+}
+
+impl std::error::Error for Violation {}
+
+/*
+ * Differences between two plans, as other minus self. Phases present in
+ * only one of the plans are compared against an empty phase.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct PlanDiff {
+	pub phases: Vec<PhaseDiff>,
+	pub duration: f64,
+	pub max_deviation: f64,		// Largest position difference at equal times
+	pub max_deviation_time: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PhaseDiff {
+	pub duration: f64,
+	pub peak_vel: f64,
+	pub peak_acc: f64,
+	pub jerk: f64,
+}
+
+impl PlanReport {
+	pub fn new(segments: Vec<Segment>) -> Self {
+		let phases: Vec<PhaseReport> = segments.iter().map(PhaseReport::new).collect();
+		let overshoot = Self::find_overshoot(&phases, &segments);
+		let shape = Self::classify(&phases, overshoot.is_some());
+		Self {
+			start: 0.0,
+			phases,
+			overshoot,
+			shape,
+			segments,
+		}
+	}
+
+	/*
+	 * Position extremes are at the ends of segments or where the velocity
+	 * crosses zero inside one.
+	 */
+	fn find_overshoot(phases: &[PhaseReport], segments: &[Segment]) -> Option<Overshoot> {
+		const MIN_OVERSHOOT: f64 = 1e-9;
+
+		let (start, end) = match (phases.first(), phases.last()) {
+			(Some(first), Some(last)) => (first.start.0, last.end.0),
+			_ => return None,
+		};
+		let dir = if end > start { 1.0 } else if end < start { -1.0 } else { 0.0 };
+		let past_end = |pos: f64| if dir == 0.0 { (pos - end).abs() } else { (pos - end)*dir };
+
+		let mut best: Option<Overshoot> = None;
+		let mut t_start = 0.0;
+		for seg in segments {
+			let (count, times) = seg.extremum_times(0, 0.0, seg.get_duration());
+			for t in times[..count].iter().copied().chain(std::iter::once(seg.get_duration())) {
+				let pos = *seg.get_state_at(t).last().unwrap();
+				let distance = past_end(pos);
+				if distance > MIN_OVERSHOOT && best.is_none_or(|best| distance > best.distance) {
+					best = Some(Overshoot { pos, time: t_start + t, distance });
+				}
+			}
+			t_start += seg.get_duration();
+		}
+		best
+	}
+
+	fn classify(phases: &[PhaseReport], overshoot: bool) -> Option<MoveShape> {
+		const MIN_VEL: f64 = 1e-12;
+
+		let last = phases.last()?;
+		if !last.duration.is_finite() || last.end.1.abs() > MIN_VEL {
+			return None;
+		}
+		if overshoot {
+			return Some(MoveShape::OvershootReversal);
+		}
+		if phases.iter().all(|phase| phase.label() == "decelerate") {
+			return Some(MoveShape::StopOnly);
+		}
+		let cruise = phases.iter().any(|phase| phase.label() == "cruise" && phase.start.1.abs() > MIN_VEL);
+		let jerk = phases.iter().any(|phase| phase.jerk != 0.0);
+		Some(match (jerk, cruise) {
+			(false, true) => MoveShape::Trapezoid,
+			(false, false) => MoveShape::Triangle,
+			(true, true) => MoveShape::SCurveFull,
+			(true, false) => MoveShape::SCurveNoCruise,
+		})
+	}
+
+	pub fn get_duration(&self) -> f64 {
+		self.phases.iter().map(|phase| phase.duration).sum()
+	}
+
+	/*
+	 * Check that the plan stays within limits, from the exact extremes of
+	 * each segment's polynomials rather than from samples. Acceleration
+	 * is checked against limits.acc around zero, so not for a plan with an
+	 * acceleration bias. Segments that never end are not checked.
+	 */
+	pub fn verify(&self, limits: &Limits) -> Result<(), Violation> {
+		let checks = [(Invariant::Velocity, 1, limits.vel), (Invariant::Acceleration, 2, Some(limits.acc)), (Invariant::Jerk, 3, limits.jerk)];
+		let mut t_start = 0.0;
+		for (phase, seg) in self.segments.iter().enumerate() {
+			let duration = seg.get_duration();
+			if !duration.is_finite() {
+				continue;
+			}
+			for (invariant, order, limit) in checks.iter() {
+				let limit = match limit {
+					Some(limit) => *limit,
+					None => continue,
+				};
+				let poly = seg.derivative(*order);
+				let (count, times) = seg.extremum_times(*order, 0.0, duration);
+				let (time, value) = times[..count].iter().chain(&[0.0, duration])
+					.map(|t| (*t, poly.eval(*t).abs()))
+					.fold((0.0, 0.0), |best, cand| if cand.1 > best.1 { cand } else { best });
+				if value > limit*(1.0 + 1e-9) + 1e-9 {
+					return Err(Violation { invariant: *invariant, phase, time: t_start + time, value, limit });
+				}
+			}
+			t_start += duration;
+		}
+		Ok(())
+	}
+
+	/*
+	 * The plan as JSON, for dashboards and logs:
+	 *
+	 * {"start": 0, "duration": 0.6, "overshoot": null, "shape": "Trapezoid", "phases": [{"label": "accelerate",
+	 *  "duration": 0.2, "start": [0, 0, 0.5], "end": [0.01, 0.1, 0.5],
+	 *  "peak_vel": 0.1, "peak_acc": 0.5, "jerk": 0, "coeffs": [0, 0, 0.25]},
+	 *  ...]}
+	 *
+	 * States are [pos, vel, acc]; coeffs is the position polynomial in the
+	 * time into the phase, constant term first. Durations that never end
+	 * are null, and so is the shape if there is none. Written without serde_json, so no extra dependency.
+	 */
+	#[cfg(feature = "serde")]
+	pub fn to_json(&self) -> String {
+		let num = |val: f64| if val.is_finite() { format!("{}", val) } else { "null".to_string() };
+		let list = |vals: &[f64]| format!("[{}]", vals.iter().map(|val| num(*val)).collect::<Vec<_>>().join(", "));
+		let triple = |state: (f64, f64, f64)| list(&[state.0, state.1, state.2]);
+
+		let phases: Vec<String> = self.phases.iter().zip(&self.segments).map(|(phase, seg)| format!(
+			"{{\"label\": \"{}\", \"duration\": {}, \"start\": {}, \"end\": {}, \"peak_vel\": {}, \"peak_acc\": {}, \"jerk\": {}, \"coeffs\": {}}}",
+			phase.label(), num(phase.duration), triple(phase.start), triple(phase.end),
+			num(phase.peak_vel), num(phase.peak_acc), num(phase.jerk), list(seg.derivative(0).coeffs()))).collect();
+		let overshoot = match self.overshoot {
+			Some(overshoot) => format!("{{\"pos\": {}, \"time\": {}, \"distance\": {}}}", num(overshoot.pos), num(overshoot.time), num(overshoot.distance)),
+			None => "null".to_string(),
+		};
+		let shape = self.shape.map_or("null".to_string(), |shape| format!("\"{:?}\"", shape));
+		format!("{{\"start\": {}, \"duration\": {}, \"overshoot\": {}, \"shape\": {}, \"phases\": [{}]}}",
+			num(self.start), num(self.get_duration()), overshoot, shape, phases.join(", "))
+	}
+
+	/*
+	 * Full state (highest derivative first, like Segment) from time t from
+	 * the start of the report and onwards, i.e. taken from the segment that
+	 * starts at t if t is a segment boundary. After the end the plan is at
+	 * rest at its last position.
+	 */
+	fn state_after(&self, t: f64) -> Vec<f64> {
+		let mut t_seg = t.max(0.0);
+		for seg in &self.segments {
+			if t_seg < seg.get_duration() {
+				return seg.get_state_at(t_seg);
+			}
+			t_seg -= seg.get_duration();
+		}
+		vec![self.phases.last().map_or(0.0, |phase| phase.end.0)]
+	}
+
+	// Velocity polynomial in the time from t into the segment at t; zero at rest after the plan
+	fn vel_after(&self, t: f64) -> Polynomial {
+		let mut t_seg = t.max(0.0);
+		for seg in &self.segments {
+			if t_seg < seg.get_duration() {
+				return seg.part(t_seg, seg.get_duration()).derivative(1);
+			}
+			t_seg -= seg.get_duration();
+		}
+		Polynomial::new(vec![0.0])
+	}
+
+	pub fn pos_at(&self, t: f64) -> f64 {
+		*self.state_after(t).last().unwrap()
+	}
+
+	fn breakpoints(&self) -> Vec<f64> {
+		let mut t = 0.0;
+		let mut breakpoints = vec![0.0];
+		for phase in &self.phases {
+			t += phase.duration;
+			breakpoints.push(t);
+		}
+		breakpoints
+	}
+
+	pub fn diff(&self, other: &PlanReport) -> PlanDiff {
+		let empty = PhaseReport {
+			duration: 0.0,
+			start: (0.0, 0.0, 0.0),
+			end: (0.0, 0.0, 0.0),
+			peak_vel: 0.0,
+			peak_acc: 0.0,
+			jerk: 0.0,
+		};
+		let count = self.phases.len().max(other.phases.len());
+		let phases = (0..count).map(|n| {
+			let a = self.phases.get(n).unwrap_or(&empty);
+			let b = other.phases.get(n).unwrap_or(&empty);
+			PhaseDiff {
+				duration: b.duration - a.duration,
+				peak_vel: b.peak_vel - a.peak_vel,
+				peak_acc: b.peak_acc - a.peak_acc,
+				jerk: b.jerk - a.jerk,
+			}
+		}).collect();
+
+		let (max_deviation, max_deviation_time) = self.max_deviation(other);
+		PlanDiff {
+			phases,
+			duration: other.get_duration() - self.get_duration(),
+			max_deviation,
+			max_deviation_time,
+		}
+	}
+
+	/*
+	 * Between the segment boundaries of both plans the position difference
+	 * is a polynomial (up to a quintic, see Segment::from_poly()), so its
+	 * extremes are found among the interval ends and the real roots of the
+	 * velocity difference.
+	 */
+	fn max_deviation(&self, other: &PlanReport) -> (f64, f64) {
+		let mut times = self.breakpoints();
+		times.append(&mut other.breakpoints());
+		times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+		times.dedup();
+
+		let mut candidates = times.clone();
+		for w in times.windows(2) {
+			let a = self.vel_after(w[0]);
+			let b = other.vel_after(w[0]);
+			let len = a.coeffs().len().max(b.coeffs().len());
+			let diff = Polynomial::new((0..len).map(|n| b.coeff(n) - a.coeff(n)).collect());
+			for root in diff.real_roots_in(0.0, w[1] - w[0]) {
+				if root > 0.0 && root < w[1] - w[0] {
+					candidates.push(w[0] + root);
+				}
+			}
+		}
+
+		candidates.iter()
+			.map(|t| ((other.pos_at(*t) - self.pos_at(*t)).abs(), *t))
+			.fold((0.0, 0.0), |best, cand| if cand.0 > best.0 { cand } else { best })
+	}
+}
@@ -0,0 +1,167 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/*
+ * The remaining plan sampled at a fixed interval into one flat buffer of
+ * (time, pos, vel, acc, jerk) rows, for data analysis without a copy per
+ * element. Both layouts match a matrix type's own storage, so the buffer
+ * is taken over as is; with the "nalgebra" and "ndarray" features
+ * sample_dmatrix() and sample_array2() do that in one call:
+ *
+ *   let matrix = sample_dmatrix(&path, 0.001);	// nalgebra::DMatrix
+ *   let array = sample_array2(&path, 0.001);	// ndarray::Array2
+ *
+ * fill_samples() writes into an existing buffer instead, e.g.
+ * Array2::as_slice_mut() or DMatrix::as_mut_slice(). Time is from now;
+ * past the end of the plan the rows are at rest at the end position.
+ */
+
+use crate::path::Path;
+
+// Time, pos, vel, acc, jerk; jerk is zero for an acc-limited path
+pub const COLUMNS: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Layout {
+	RowMajor,	// One sample after the other, like ndarray's default
+	ColumnMajor,	// All times, then all positions, ..., like nalgebra
+}
+
+// Fill out, out.len()/COLUMNS rows, with samples dt apart starting now
+pub fn fill_samples<const DEGREE: usize>(path: &Path<DEGREE>, dt: f64, layout: Layout, out: &mut [f64]) {
+	assert!(dt > 0.0);
+	assert_eq!(out.len() % COLUMNS, 0);
+	let rows = out.len()/COLUMNS;
+
+	let pos = path.piecewise_poly();
+	let vel = pos.derivative();
+	let acc = vel.derivative();
+	let jerk = acc.derivative();
+	let duration = *pos.breaks.last().unwrap();
+	let s_end = if pos.pieces.is_empty() { path.get_state().0 } else { pos.eval(duration) };
+
+	for row in 0..rows {
+		let t = row as f64*dt;
+		let sample = if t < duration {
+			[t, pos.eval(t), vel.eval(t), acc.eval(t), jerk.eval(t)]
+		} else {
+			[t, s_end, 0.0, 0.0, 0.0]
+		};
+		for (col, val) in sample.iter().enumerate() {
+			let index = match layout {
+				Layout::RowMajor => row*COLUMNS + col,
+				Layout::ColumnMajor => col*rows + row,
+			};
+			out[index] = *val;
+		}
+	}
+}
+
+/*
+ * (rows, data) for the remaining plan, from now to the first sample at or
+ * after its end. The plan must end.
+ */
+pub fn sample_matrix<const DEGREE: usize>(path: &Path<DEGREE>, dt: f64, layout: Layout) -> (usize, Vec<f64>) {
+	assert!(dt > 0.0);
+	let remaining = *path.piecewise_poly().breaks.last().unwrap();
+	assert!(remaining.is_finite());
+	let rows = (remaining/dt).ceil() as usize + 1;
+	let mut data = vec![0.0; rows*COLUMNS];
+	fill_samples(path, dt, layout, &mut data);
+	(rows, data)
+}
+
+// sample_matrix() as a rows x COLUMNS nalgebra matrix
+#[cfg(feature = "nalgebra")]
+pub fn sample_dmatrix<const DEGREE: usize>(path: &Path<DEGREE>, dt: f64) -> nalgebra::DMatrix<f64> {
+	let (rows, data) = sample_matrix(path, dt, Layout::ColumnMajor);
+	nalgebra::DMatrix::from_vec(rows, COLUMNS, data)
+}
+
+// sample_matrix() as a rows x COLUMNS ndarray array
+#[cfg(feature = "ndarray")]
+pub fn sample_array2<const DEGREE: usize>(path: &Path<DEGREE>, dt: f64) -> ndarray::Array2<f64> {
+	let (rows, data) = sample_matrix(path, dt, Layout::RowMajor);
+	ndarray::Array2::from_shape_vec((rows, COLUMNS), data).expect("rows*COLUMNS samples")
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::{sample_matrix, Layout, COLUMNS};
+	use crate::path::Path;
+	use crate::scenario::check_states_eq;
+
+	#[test]
+	fn both_layouts_match_the_path() -> Result<(), String> {
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		path.replan(0.2, 0.1);
+		let dt = 0.01;
+		let (rows, by_row) = sample_matrix(&path, dt, Layout::RowMajor);
+		let (_, by_col) = sample_matrix(&path, dt, Layout::ColumnMajor);
+		if rows != (path.update(0.0).remaining/dt).ceil() as usize + 1 {
+			return Err(format!("{} rows for {} s", rows, path.update(0.0).remaining));
+		}
+
+		for row in 0..rows {
+			let sample = &by_row[row*COLUMNS..(row + 1)*COLUMNS];
+			if (0..COLUMNS).any(|col| by_col[col*rows + row] != sample[col]) {
+				return Err(format!("Row {} differs between layouts", row));
+			}
+			// The acceleration differs at the instants where it jumps
+			let state = path.get_state();
+			check_states_eq(sample[0], (sample[1], sample[2], 0.0), (state.0, state.1, 0.0))?;
+			if sample[4].abs() > 5.0 + 1e-9 {
+				return Err(format!("Jerk {} at {}", sample[4], sample[0]));
+			}
+			path.update(dt);
+		}
+		if by_row[(rows - 1)*COLUMNS + 1] != 0.2 {
+			return Err(format!("Ended at {}", by_row[(rows - 1)*COLUMNS + 1]));
+		}
+		Ok(())
+	}
+
+	#[cfg(feature = "nalgebra")]
+	#[test]
+	fn dmatrix_is_indexed_by_row_and_column() -> Result<(), String> {
+		use super::sample_dmatrix;
+
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		let (rows, by_row) = sample_matrix(&path, 0.01, Layout::RowMajor);
+		let matrix = sample_dmatrix(&path, 0.01);
+		if matrix.shape() != (rows, COLUMNS) || (0..rows).any(|row| (0..COLUMNS).any(|col| matrix[(row, col)] != by_row[row*COLUMNS + col])) {
+			return Err(format!("Matrix of shape {:?} differs from the samples", matrix.shape()));
+		}
+		Ok(())
+	}
+
+	#[cfg(feature = "ndarray")]
+	#[test]
+	fn array2_is_indexed_by_row_and_column() -> Result<(), String> {
+		use super::sample_array2;
+
+		let mut path = Path::acc_limited(0.5);
+		path.replan(0.04, 0.1);
+		let (rows, by_row) = sample_matrix(&path, 0.01, Layout::RowMajor);
+		let array = sample_array2(&path, 0.01);
+		if array.dim() != (rows, COLUMNS) || (0..rows).any(|row| (0..COLUMNS).any(|col| array[[row, col]] != by_row[row*COLUMNS + col])) {
+			return Err(format!("Array of shape {:?} differs from the samples", array.dim()));
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,500 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::io::Write;
+use crate::path::{Path, Limits, OvershootPolicy, CLOSE_ENOUGH};
+
+/*
+ * Scripted commands and checks against a Path, e.g.
+ *
+ * Scenario::new()
+ * 	.at(0.00).move_to(0.04, 0.1)
+ * 	.at(0.30).expect_state((0.02, 0.1, 0.0))
+ * 	.at(0.61).done()
+ * 	.run(&mut Path::acc_limited(0.5))
+ *
+ * run() steps the path through time with a dt of 1 ms, checking the
+ * acceleration and jerk limits at each step, and performs each action at
+ * its time. The first failed check is returned as an error.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ActionType {
+	MoveTo((f64, f64)),
+	CheckAcc(f64),
+	CheckVel(f64),
+	CheckPos(f64),
+	CheckState((f64, f64, f64)),
+	SetOvershootPolicy(OvershootPolicy),
+	SetAccBias(f64),
+	SetApproachAcc(Option<f64>),
+	SetMaxDec(Option<f64>),
+	Stop,
+	Done,		// Check that the path is at rest, and end the scenario
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Action {
+	pub t: f64,
+	pub action: ActionType,
+}
+
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Scenario {
+	actions: Vec<Action>,
+	t: f64,		// Time of actions added by the builder methods
+}
+
+impl Scenario {
+	pub fn new() -> Self {
+		Self {
+			actions: Vec::new(),
+			t: 0.0,
+		}
+	}
+
+	// Actions must be in time order
+	pub fn from_actions(actions: &[Action]) -> Self {
+		let mut scenario = Self::new();
+		for action in actions {
+			scenario = scenario.at(action.t).action(action.action);
+		}
+		scenario
+	}
+
+	// Time of the following actions, until the next at()
+	pub fn at(mut self, t: f64) -> Self {
+		assert!(t >= self.t);
+		self.t = t;
+		self
+	}
+
+	pub fn action(mut self, action: ActionType) -> Self {
+		self.actions.push(Action { t: self.t, action });
+		self
+	}
+
+	pub fn move_to(self, pos: f64, v_limit: f64) -> Self {
+		self.action(ActionType::MoveTo((pos, v_limit)))
+	}
+
+	pub fn stop(self) -> Self {
+		self.action(ActionType::Stop)
+	}
+
+	pub fn set_overshoot_policy(self, policy: OvershootPolicy) -> Self {
+		self.action(ActionType::SetOvershootPolicy(policy))
+	}
+
+	pub fn set_acc_bias(self, bias: f64) -> Self {
+		self.action(ActionType::SetAccBias(bias))
+	}
+
+	pub fn set_approach_acc(self, acc: Option<f64>) -> Self {
+		self.action(ActionType::SetApproachAcc(acc))
+	}
+
+	pub fn set_max_dec(self, dec: Option<f64>) -> Self {
+		self.action(ActionType::SetMaxDec(dec))
+	}
+
+	pub fn expect_acc(self, acc: f64) -> Self {
+		self.action(ActionType::CheckAcc(acc))
+	}
+
+	pub fn expect_vel(self, vel: f64) -> Self {
+		self.action(ActionType::CheckVel(vel))
+	}
+
+	pub fn expect_pos(self, pos: f64) -> Self {
+		self.action(ActionType::CheckPos(pos))
+	}
+
+	// (pos, vel, acc), like Path::get_state()
+	pub fn expect_state(self, state: (f64, f64, f64)) -> Self {
+		self.action(ActionType::CheckState(state))
+	}
+
+	pub fn done(self) -> Self {
+		self.action(ActionType::Done)
+	}
+
+	pub fn run<const DEGREE: usize>(&self, path: &mut Path<DEGREE>) -> Result<(), String> {
+		self.run_plotted(path, &mut std::io::sink())
+	}
+
+	/*
+	 * Like run(), also writing the trajectory as gnuplot data for
+	 * script.gnuplot: one "t pos vel acc jerk" line per step, then the
+	 * times of the replans.
+	 */
+	pub fn run_plotted<const DEGREE: usize>(&self, path: &mut Path<DEGREE>, out: &mut dyn Write) -> Result<(), String> {
+		let io_err = |err: std::io::Error| err.to_string();
+		let limits = path.limits();
+
+		let mut replans = Vec::new();
+
+		let mut result = Ok(());
+
+		let dt = 0.001;
+		let tolerance_fact = 1.01;
+
+		let mut t = 0.0;
+		let mut s_prev = 0.0;
+		let mut v_prev = 0.0;
+		let mut a_prev = 0.0;
+		let mut acc_bias = 0.0;
+		let mut dec_limit = limits.acc;
+		let mut action_index = 0;
+		let mut done = false;
+		while action_index < self.actions.len() && !done {
+			// Check limits
+			let state = path.get_state();
+			let v = (state.0 - s_prev)/dt;
+			let a = (v - v_prev)/dt;
+			let j = (a - a_prev)/dt;
+
+			// Velocity is variable so we don't have a reference to check against
+
+			let a_limit = if (a - acc_bias)*v < 0.0 { dec_limit } else { limits.acc };
+			if result.is_ok() && (a - acc_bias).abs() > a_limit*tolerance_fact {
+				result = Err(format!("Time {}: acceleration ({}) over limit ({})", t, a, a_limit));
+			}
+
+			if let Some(jerk) = limits.jerk {
+				if result.is_ok() && j.abs() > jerk*tolerance_fact {
+					result = Err(format!("Time {}: jerk ({}) over limit ({})", t, j, jerk));
+				}
+			}
+
+			s_prev = state.0;
+			v_prev = v;
+			a_prev = a;
+
+			// Perform the actions due during this step, at their exact times
+			let mut t_path = None;
+			while !done && action_index < self.actions.len() && t + dt >= self.actions[action_index].t {
+				let action = &self.actions[action_index];
+				path.update(action.t - t_path.unwrap_or(t));
+				t_path = Some(action.t);
+				match action.action {
+					ActionType::MoveTo((x, v)) => {
+						path.replan(x, v);
+						replans.push(action.t);
+					},
+					ActionType::Stop => {
+						path.stop();
+						replans.push(action.t);
+					},
+					ActionType::SetOvershootPolicy(policy) => {
+						path.set_overshoot_policy(policy);
+					},
+					ActionType::SetAccBias(bias) => {
+						path.set_acc_bias(bias);
+						acc_bias = bias;
+					},
+					ActionType::SetApproachAcc(acc) => {
+						path.set_approach_acc(acc);
+					},
+					ActionType::SetMaxDec(dec) => {
+						path.set_max_dec(dec);
+						dec_limit = dec.unwrap_or(limits.acc);
+					},
+					ActionType::CheckAcc(acc) => {
+						if result.is_ok() {
+							let state = path.get_state();
+							result = check_eq(action.t, state.2, acc);
+						}
+					},
+					ActionType::CheckVel(vel) => {
+						if result.is_ok() {
+							let state = path.get_state();
+							result = check_eq(action.t, state.1, vel);
+						}
+					},
+					ActionType::CheckPos(pos) => {
+						if result.is_ok() {
+							let state = path.get_state();
+							result = check_eq(action.t, state.0, pos);
+						}
+					},
+					ActionType::CheckState(goal) => {
+						if result.is_ok() {
+							let state = path.get_state();
+							result = check_states_eq(action.t, state, goal);
+						}
+					},
+					ActionType::Done => {
+						if result.is_ok() && path.is_active() {
+							result = Err(format!("Time {:.3}: Planner still active", action.t));
+						}
+						done = true;
+					},
+				}
+				action_index += 1;
+			}
+			if done {
+				break;
+			}
+			match t_path {
+				Some(t_path) => path.update(t + dt - t_path),
+				None => path.update(dt),
+			};
+
+			// Plot state to file
+			write!(out, "{:.6} ", t).map_err(io_err)?;
+			write_state(path.state(), out).map_err(io_err)?;
+			writeln!(out).map_err(io_err)?;
+
+			t += dt;
+		}
+
+		writeln!(out).map_err(io_err)?;
+		writeln!(out).map_err(io_err)?;
+		for t in replans {
+			write!(out, "{:.6} ", t).map_err(io_err)?;
+		}
+		writeln!(out).map_err(io_err)?;
+
+		result
+	}
+}
+
+/*
+ * Scenarios as text, for submitting a failing case as a file and for the
+ * regression corpus in corpus/. The limits come first, then one action
+ * per line at its time; # starts a comment:
+ *
+ * limits acc 0.5 jerk 5 vel 0.2
+ * 0.00 move-to 0.04 0.1
+ * 0.30 expect-state 0.02 0.1 0
+ * 0.61 done
+ *
+ * The other actions are stop, expect-pos/-vel/-acc VAL, set-acc-bias VAL,
+ * set-approach-acc VAL|none, set-max-dec VAL|none and
+ * set-overshoot-policy allow|forbid. Values are written exactly, so
+ * save() and load() round-trip.
+ */
+pub fn save(limits: Limits, scenario: &Scenario) -> String {
+	let opt = |val: Option<f64>| val.map_or("none".to_string(), |val| val.to_string());
+	let mut text = format!("limits acc {}", limits.acc);
+	if let Some(jerk) = limits.jerk {
+		text += &format!(" jerk {}", jerk);
+	}
+	if let Some(vel) = limits.vel {
+		text += &format!(" vel {}", vel);
+	}
+	text += "\n";
+	for action in &scenario.actions {
+		let args = match action.action {
+			ActionType::MoveTo((pos, vel)) => format!("move-to {} {}", pos, vel),
+			ActionType::CheckAcc(acc) => format!("expect-acc {}", acc),
+			ActionType::CheckVel(vel) => format!("expect-vel {}", vel),
+			ActionType::CheckPos(pos) => format!("expect-pos {}", pos),
+			ActionType::CheckState((pos, vel, acc)) => format!("expect-state {} {} {}", pos, vel, acc),
+			ActionType::SetOvershootPolicy(policy) => format!("set-overshoot-policy {}", if policy == OvershootPolicy::Allow { "allow" } else { "forbid" }),
+			ActionType::SetAccBias(bias) => format!("set-acc-bias {}", bias),
+			ActionType::SetApproachAcc(acc) => format!("set-approach-acc {}", opt(acc)),
+			ActionType::SetMaxDec(dec) => format!("set-max-dec {}", opt(dec)),
+			ActionType::Stop => "stop".to_string(),
+			ActionType::Done => "done".to_string(),
+		};
+		text += &format!("{} {}\n", action.t, args);
+	}
+	text
+}
+
+// Parse the text from save(); errors name the line
+pub fn load(text: &str) -> Result<(Limits, Scenario), String> {
+	let mut limits = None;
+	let mut scenario = Scenario::new();
+	for (n, line) in text.lines().enumerate() {
+		let line = line.split('#').next().unwrap_or("").trim();
+		if line.is_empty() {
+			continue;
+		}
+		let words: Vec<&str> = line.split_whitespace().collect();
+		let err = |msg: &str| format!("Line {}: {}: \"{}\"", n + 1, msg, line);
+		let num = |word: &str| word.parse::<f64>().map_err(|_| err("Expected a number"));
+		let opt = |word: &str| if word == "none" { Ok(None) } else { num(word).map(Some) };
+
+		if words[0] == "limits" {
+			let mut parsed = Limits { vel: None, acc: 0.0, jerk: None, snap: None };
+			for pair in words[1..].chunks(2) {
+				let val = num(pair.get(1).ok_or_else(|| err("Expected a value"))?)?;
+				match pair[0] {
+					"acc" => parsed.acc = val,
+					"jerk" => parsed.jerk = Some(val),
+					"vel" => parsed.vel = Some(val),
+					_ => return Err(err("Unknown limit")),
+				}
+			}
+			parsed.validate().map_err(|limit_err| err(&limit_err.to_string()))?;
+			limits = Some(parsed);
+			continue;
+		}
+
+		let t = num(words[0])?;
+		let args = &words[1..];
+		let arg = |index: usize| args.get(index + 1).copied().ok_or_else(|| err("Too few values"));
+		let action = match args.first().copied() {
+			Some("move-to") => ActionType::MoveTo((num(arg(0)?)?, num(arg(1)?)?)),
+			Some("expect-acc") => ActionType::CheckAcc(num(arg(0)?)?),
+			Some("expect-vel") => ActionType::CheckVel(num(arg(0)?)?),
+			Some("expect-pos") => ActionType::CheckPos(num(arg(0)?)?),
+			Some("expect-state") => ActionType::CheckState((num(arg(0)?)?, num(arg(1)?)?, num(arg(2)?)?)),
+			Some("set-overshoot-policy") => match arg(0)? {
+				"allow" => ActionType::SetOvershootPolicy(OvershootPolicy::Allow),
+				"forbid" => ActionType::SetOvershootPolicy(OvershootPolicy::Forbid),
+				_ => return Err(err("Expected allow or forbid")),
+			},
+			Some("set-acc-bias") => ActionType::SetAccBias(num(arg(0)?)?),
+			Some("set-approach-acc") => ActionType::SetApproachAcc(opt(arg(0)?)?),
+			Some("set-max-dec") => ActionType::SetMaxDec(opt(arg(0)?)?),
+			Some("stop") => ActionType::Stop,
+			Some("done") => ActionType::Done,
+			_ => return Err(err("Unknown action")),
+		};
+		let values = match action {
+			ActionType::MoveTo(_) => 2,
+			ActionType::CheckState(_) => 3,
+			ActionType::Stop | ActionType::Done => 0,
+			_ => 1,
+		};
+		if args.len() > values + 1 {
+			return Err(err("Too many values"));
+		}
+		if t < scenario.t {
+			return Err(err("Actions must be in time order"));
+		}
+		scenario = scenario.at(t).action(action);
+	}
+	let limits = limits.ok_or("No limits line")?;
+	Ok((limits, scenario))
+}
+
+// Load a scenario and run it on a path with its limits
+pub fn replay(text: &str) -> Result<(), String> {
+	let (limits, scenario) = load(text)?;
+	match limits.jerk {
+		None => scenario.run(&mut Path::<2>::with_limits(limits)),
+		Some(_) => scenario.run(&mut Path::<3>::with_limits(limits)),
+	}
+}
+
+/*
+ * Replay every *.scenario file in dir, in name order, e.g. the corpus/
+ * directory of the crate. Returns the number of files run, or the first
+ * failure with the name of its file.
+ */
+pub fn replay_corpus(dir: &std::path::Path) -> Result<usize, String> {
+	let mut files: Vec<_> = std::fs::read_dir(dir).map_err(|err| format!("{}: {}", dir.display(), err))?
+		.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+		.filter(|file| file.extension().is_some_and(|ext| ext == "scenario"))
+		.collect();
+	files.sort();
+	for file in &files {
+		let text = std::fs::read_to_string(file).map_err(|err| format!("{}: {}", file.display(), err))?;
+		replay(&text).map_err(|err| format!("{}: {}", file.display(), err))?;
+	}
+	Ok(files.len())
+}
+
+fn write_state(orig_state: &[f64], out: &mut dyn Write) -> std::io::Result<()> {
+	for val in orig_state.iter().rev() {
+		write!(out, "{:.6} ", *val)?;
+	}
+
+	// Print dummy jerk to keep gnuplot happy
+	if orig_state.len() < 4 {
+		write!(out, "{:.6} ", 0.0)?;
+	}
+	Ok(())
+}
+
+fn val_eq(val1: f64, val2: f64) -> bool {
+	(val1 - val2).abs() < CLOSE_ENOUGH
+}
+
+pub(crate) fn check_eq(time: f64, val: f64, goal: f64) -> Result<(), String> {
+	if val_eq(val, goal) {
+		return Ok(());
+	}
+
+	Err(format!("Time {:.3}: value {} differs from {}", time, val, goal))
+}
+
+pub(crate) fn check_states_eq(time: f64, state: (f64, f64, f64), goal: (f64, f64, f64)) -> Result<(), String> {
+	if val_eq(state.0, goal.0) && val_eq(state.1, goal.1) && val_eq(state.2, goal.2) {
+		return Ok(());
+	}
+
+	Err(format!("Time {:.3}: state {:?} differs from {:?}", time, state, goal))
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::Scenario;
+	use crate::path::{Path, Limits, OvershootPolicy};
+
+	#[test]
+	fn builder_move_and_failed_check() -> Result<(), String> {
+		let scenario = Scenario::new()
+			.at(0.00).move_to(0.04, 0.1)
+			.at(0.10).expect_acc(0.5)
+			.at(0.30).expect_state((0.02, 0.1, 0.0))
+			.at(0.61).expect_pos(0.04).done();
+		scenario.run(&mut Path::acc_limited(0.5))?;
+
+		let early = Scenario::new()
+			.at(0.00).move_to(0.04, 0.1)
+			.at(0.50).done();
+		if early.run(&mut Path::acc_limited(0.5)).is_ok() {
+			return Err("Expected the path to still be active at 0.5 s".to_string());
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn save_load_and_corpus() -> Result<(), String> {
+		use super::{save, load, replay_corpus};
+
+		let limits = Limits { vel: Some(0.2), ..Limits::jerk_limited(0.5, 5.0) };
+		let scenario = Scenario::new()
+			.at(0.0).set_overshoot_policy(OvershootPolicy::Forbid).set_max_dec(None).move_to(0.04, 0.1)
+			.at(0.1).expect_state((5.0*0.001/6.0, 0.025, 0.5)).stop()
+			.at(0.5).done();
+		let text = save(limits, &scenario);
+		if load(&text)? != (limits, scenario) {
+			return Err(format!("Didn't round-trip:\n{}", text));
+		}
+		if load("limits acc 0.5\n0.1 move-to 0.04\n") != Err("Line 2: Too few values: \"0.1 move-to 0.04\"".to_string()) {
+			return Err("Expected an error for line 2".to_string());
+		}
+		if load("limits acc 0.5\n0.1 stop 0.04\n") != Err("Line 2: Too many values: \"0.1 stop 0.04\"".to_string()) {
+			return Err("Expected an error for the extra value".to_string());
+		}
+		if load("limits acc 0.5 jerk -5\n") != Err("Line 1: Invalid jerk limit -5: \"limits acc 0.5 jerk -5\"".to_string()) {
+			return Err("Expected an error for the jerk limit".to_string());
+		}
+
+		let corpus = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+		match replay_corpus(&corpus)? {
+			0 => Err("Empty corpus".to_string()),
+			_ => Ok(()),
+		}
+	}
+}
@@ -14,23 +14,30 @@
  * along with this program. If not, see <http://www.gnu.org/licenses/>.
 */
 
+use std::fmt;
+use crate::poly::{Polynomial, MAX_FIXED_DEGREE};
+use crate::math;
+
+// Room for jerk, acc, vel and pos
+pub(crate) const MAX_STATE_LEN: usize = 4;
+// Room for a quintic position polynomial, see from_poly()
+const MAX_COEFFS: usize = 6;
+
+/*
+ * Fixed-size, so that planning doesn't allocate. Derivatives and ranges
+ * are computed from the position polynomial on demand.
+ */
+#[derive(Clone, Debug)]
 pub struct Segment {
-	initvals: Vec<f64>,
+	pos: [f64; MAX_COEFFS],	// Position polynomial in the time into the segment, constant term first
+	coeffs: usize,		// Number of coefficients used in pos
+	order: usize,		// Number of derivatives in the state, position included
 	duration: f64,
 	padto: usize,		// Return state of at least this length
+	tag: Option<u32>,	// User tag of the move, see Path::replan_tagged()
 }
 
 impl Segment {
-	pub fn new(initvals: &[f64], duration: f64, padto: usize) -> Self {
-		let seg = Self {
-			initvals: initvals.to_vec(),
-			duration: duration,
-			padto: padto,
-		};
-		seg.print();
-		seg
-	}
-
 	/*
 	 * Treat initvals as coefficients of the terms in integrals of some
 	 * derivative of position - that is, given
@@ -39,34 +46,129 @@ impl Segment {
 	 * v0 = initvals[1] (velocity)
 	 * a0 = initvals[0] (acceleration)
 	 *
-	 * return state at time t according to
+	 * the position is
 	 *
 	 * s = 1/6*j0*t³ + 1/2*a0*t² + v0*t + s0
-	 * v = 1/2*j0*t² + a0*t + v0
-	 * a = j0*t + a0
-	 * j = j0
+	 */
+	pub(crate) fn new(initvals: &[f64], duration: f64, padto: usize) -> Self {
+		assert!(initvals.len() <= MAX_STATE_LEN && padto <= MAX_STATE_LEN);
+
+		let mut pos = [0.0; MAX_COEFFS];
+		let mut fact = 1.0;
+		for (n, initval) in initvals.iter().rev().enumerate() {
+			if n > 0 {
+				fact *= n as f64;
+			}
+			pos[n] = initval/fact;
+		}
+		Self {
+			pos,
+			coeffs: initvals.len(),
+			order: initvals.len(),
+			duration,
+			padto,
+			tag: None,
+		}
+	}
+
+	/*
+	 * Segment from the position polynomial itself, constant term first, up
+	 * to degree 5. Unlike new() the highest derivative in the state can
+	 * vary over the segment. The state has state_len values, like from a
+	 * Path of degree state_len - 1.
+	 */
+	pub(crate) fn from_poly(coeffs: &[f64], duration: f64, state_len: usize) -> Self {
+		assert!(coeffs.len() <= MAX_COEFFS && state_len <= MAX_STATE_LEN);
+
+		let mut pos = [0.0; MAX_COEFFS];
+		pos[..coeffs.len()].copy_from_slice(coeffs);
+		Self {
+			pos,
+			coeffs: coeffs.len(),
+			order: state_len,
+			duration,
+			padto: state_len,
+			tag: None,
+		}
+	}
+
+	/*
+	 * State at time t, highest derivative first like the initvals, padded
+	 * with leading zeros to padto values.
 	 */
 	pub fn get_state_at(&self, t: f64) -> Vec<f64> {
+		self.state_at(t)[..self.state_len()].to_vec()
+	}
+
+	/*
+	 * Same as get_state_at(), writing into out instead of allocating.
+	 * Returns the number of values written; out must have room for them
+	 * (at most 4).
+	 */
+	pub fn state_into(&self, t: f64, out: &mut [f64]) -> usize {
+		let len = self.state_len();
+		assert!(out.len() >= len);
+		out[..len].copy_from_slice(&self.state_at(t)[..len]);
+		len
+	}
+
+	// Same as get_state_at(), without allocating; values past the state are zero
+	pub(crate) fn state_at(&self, t: f64) -> [f64; MAX_STATE_LEN] {
 		assert!(t >= 0.0);
 		assert!(t <= self.duration);
 
-		let mut terms = Vec::new();
-		let mut state = vec![0.0; self.padto - self.initvals.len()];
-		for initval in &self.initvals {
+		let len = self.state_len();
+		let mut state = [0.0; MAX_STATE_LEN];
+		for n in 0..self.order {
+			// n:th derivative of sum(pos[m]*t^m)
 			let mut val = 0.0;
-			let degree = terms.len();
-			for n in 0..degree {
-				terms[n] *= t/(degree - n) as f64;
-				val += terms[n];
+			for m in (n..self.coeffs).rev() {
+				let fact: f64 = ((m - n + 1)..=m).map(|k| k as f64).product();
+				val = val*t + fact*self.pos[m];
 			}
-			terms.push(*initval);
-			val += *initval;
-			state.push(val);
+			state[len - 1 - n] = val;
 		}
-
 		state
 	}
 
+	/*
+	 * The part of the segment between t_from and t_to as a segment of its
+	 * own, starting at time zero: the position polynomial Taylor-shifted by
+	 * t_from, so all of its coefficients are kept and not just the state
+	 * the way new() would from state_at(t_from).
+	 */
+	pub(crate) fn part(&self, t_from: f64, t_to: f64) -> Self {
+		assert!(t_from >= 0.0 && t_from <= t_to && t_to <= self.duration);
+
+		let mut pos = [0.0; MAX_COEFFS];
+		let mut fact = 1.0;
+		for (n, coeff) in pos.iter_mut().enumerate().take(self.coeffs) {
+			// n:th derivative at t_from, over n!
+			let mut val = 0.0;
+			for m in (n..self.coeffs).rev() {
+				let mfact: f64 = ((m - n + 1)..=m).map(|k| k as f64).product();
+				val = val*t_from + mfact*self.pos[m];
+			}
+			if n > 0 {
+				fact *= n as f64;
+			}
+			*coeff = val/fact;
+		}
+		Self {
+			pos,
+			duration: t_to - t_from,
+			..self.clone()
+		}
+	}
+
+	pub(crate) fn end_state(&self) -> [f64; MAX_STATE_LEN] {
+		self.state_at(self.duration)
+	}
+
+	fn state_len(&self) -> usize {
+		self.padto.max(self.order)
+	}
+
 	pub fn get_end_state(&self) -> Vec<f64> {
 		self.get_state_at(self.duration)
 	}
@@ -75,9 +177,159 @@ impl Segment {
 		self.duration
 	}
 
-	pub fn print(&self) {
-		eprintln!("Segment: duration {}", self.duration);
-		eprintln!("   Initvals: {:?}", self.initvals);
-		eprintln!("   Endstate: {:?}", self.get_end_state());
+	// Derivative `order` of position (0 = position, 1 = velocity, ...)
+	pub fn derivative(&self, order: usize) -> Polynomial {
+		let pos = Polynomial::new(self.pos[..self.coeffs].to_vec());
+		(0..order).fold(pos, |poly, _| poly.derivative())
+	}
+
+	// Same as derivative(), without allocating: the coefficients, constant term first
+	fn derivative_coeffs(&self, order: usize) -> [f64; MAX_COEFFS] {
+		let mut coeffs = [0.0; MAX_COEFFS];
+		for m in order..self.coeffs {
+			let fact: f64 = ((m - order + 1)..=m).map(|k| k as f64).product();
+			coeffs[m - order] = fact*self.pos[m];
+		}
+		coeffs
+	}
+
+	/*
+	 * Times in (t_from, t_to) where derivative `order` has a local extremum,
+	 * in increasing order: (number of times, times)
+	 */
+	pub(crate) fn extremum_times(&self, order: usize, t_from: f64, t_to: f64) -> (usize, [f64; MAX_FIXED_DEGREE]) {
+		let (count, roots) = Polynomial::real_roots_into(&self.derivative_coeffs(order + 1), t_from, t_to);
+		let mut times = [0.0; MAX_FIXED_DEGREE];
+		let mut inside = 0;
+		for t in roots[..count].iter().copied().filter(|t| *t > t_from && *t < t_to) {
+			times[inside] = t;
+			inside += 1;
+		}
+		(inside, times)
+	}
+
+	/*
+	 * (min, max) of derivative `order` of position (0 = position,
+	 * 1 = velocity, ...) between t_from and t_to.
+	 */
+	pub fn get_range(&self, order: usize, t_from: f64, t_to: f64) -> (f64, f64) {
+		assert!(t_from <= t_to);
+
+		let coeffs = self.derivative_coeffs(order);
+		let (count, times) = self.extremum_times(order, t_from, t_to);
+		times[..count].iter().chain(&[t_from, t_to]).map(|t| Polynomial::eval_coeffs(&coeffs, *t))
+			.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), val| (min.min(val), max.max(val)))
+	}
+
+	fn pos_at(&self, t: f64) -> f64 {
+		self.pos.iter().rev().fold(0.0, |acc, coeff| acc*t + coeff)
+	}
+
+	/*
+	 * The same motion in space, run factor times slower in time: durations
+	 * scale by factor, velocity by 1/factor, acceleration by 1/factor² and
+	 * so on.
+	 */
+	pub fn scaled(&self, factor: f64) -> Self {
+		assert!(factor > 0.0);
+		let mut pos = self.pos;
+		for (n, coeff) in pos.iter_mut().enumerate() {
+			*coeff /= math::powi(factor, n as i32);
+		}
+		Self {
+			pos,
+			coeffs: self.coeffs,
+			order: self.order,
+			duration: self.duration*factor,
+			padto: self.padto,
+			tag: self.tag,
+		}
+	}
+
+	// The same timing with every position times factor, e.g. a change of unit
+	pub(crate) fn scaled_positions(&self, factor: f64) -> Self {
+		let mut seg = self.clone();
+		for coeff in seg.pos.iter_mut() {
+			*coeff *= factor;
+		}
+		seg
+	}
+
+	// The same motion moved by offset
+	pub(crate) fn offset_positions(&self, offset: f64) -> Self {
+		let mut seg = self.clone();
+		seg.pos[0] += offset;
+		seg
+	}
+
+	// The same motion moved by offset and carried along at vel, as seen from a frame moving at -vel
+	pub(crate) fn with_frame_motion(&self, offset: f64, vel: f64) -> Self {
+		let mut seg = self.offset_positions(offset);
+		seg.pos[1] += vel;
+		seg.coeffs = seg.coeffs.max(2);
+		seg
+	}
+
+	/*
+	 * The same segment starting from start instead, a state with the
+	 * highest derivative first: each lower derivative moves by its
+	 * difference, carried through the polynomial, and the highest one, the
+	 * input driving the segment, and any terms above it are kept.
+	 */
+	pub(crate) fn rebased(&self, start: &[f64]) -> Self {
+		let len = self.state_len();
+		assert!(start.len() >= len);
+
+		let now = self.state_at(0.0);
+		let mut seg = self.clone();
+		let mut fact = 1.0;
+		for n in 0..len - 1 {
+			if n > 0 {
+				fact *= n as f64;
+			}
+			seg.pos[n] += (start[len - 1 - n] - now[len - 1 - n])/fact;
+		}
+		seg.coeffs = seg.coeffs.max(len - 1);
+		seg
+	}
+
+	// Tag of the move the segment was planned for, if any
+	pub fn tag(&self) -> Option<u32> {
+		self.tag
+	}
+
+	pub(crate) fn with_tag(mut self, tag: Option<u32>) -> Self {
+		self.tag = tag;
+		self
+	}
+
+	// Net change in position over the whole segment
+	pub fn displacement(&self) -> f64 {
+		self.pos_at(self.duration) - self.pos_at(0.0)
+	}
+
+	/*
+	 * Total distance traveled between t_from and t_to, counting travel in
+	 * both directions if the velocity changes sign in between.
+	 */
+	pub fn distance(&self, t_from: f64, t_to: f64) -> f64 {
+		assert!(t_from <= t_to);
+
+		let (count, times) = self.extremum_times(0, t_from, t_to);
+		let mut distance = 0.0;
+		let mut from = t_from;
+		for t in times[..count].iter().copied().chain(std::iter::once(t_to)) {
+			distance += (self.pos_at(t) - self.pos_at(from)).abs();
+			from = t;
+		}
+		distance
+	}
+}
+
+impl fmt::Display for Segment {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "Segment: duration {}", self.duration)?;
+		writeln!(f, "   Initvals: {:?}", self.get_state_at(0.0))?;
+		write!(f, "   Endstate: {:?}", self.get_end_state())
 	}
 }
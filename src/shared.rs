@@ -0,0 +1,176 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use crate::path::{Path, Progress};
+
+/*
+ * A Path shared between threads, typically replanned from an application
+ * thread and sampled from a control loop:
+ *
+ * let shared = SharedPath::new(Path::acc_limited(0.5));
+ * let planner = shared.clone();
+ * std::thread::spawn(move || planner.replan(0.04, 0.1));
+ * // Control loop:
+ * shared.update(0.001);
+ * let (pos, vel, acc) = shared.get_state();
+ *
+ * Each call holds a mutex for its duration only, so the control loop
+ * may have to wait for a replan in progress. A loop that must never wait
+ * can use try_update(), which skips the update instead if the path is
+ * locked; the skipped time is made up at the next successful one. The
+ * mutex isn't priority-inheriting; on a real-time system give the threads
+ * suitable priorities.
+ *
+ * A call that panics with the path locked can leave its plan half made.
+ * The next call to get the lock drops that plan and stops from the
+ * current state, with the path faulted as FaultKind::Panicked until
+ * Path::clear_fault().
+ */
+#[derive(Clone)]
+pub struct SharedPath<const DEGREE: usize> {
+	path: Arc<Mutex<Path<DEGREE>>>,
+	skipped: Arc<Mutex<f64>>,	// Time of updates skipped by try_update()
+}
+
+impl<const DEGREE: usize> SharedPath<DEGREE> {
+	pub fn new(path: Path<DEGREE>) -> Self {
+		Self {
+			path: Arc::new(Mutex::new(path)),
+			skipped: Arc::new(Mutex::new(0.0)),
+		}
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Path<DEGREE>> {
+		self.path.lock().unwrap_or_else(|err| self.recover(err.into_inner()))
+	}
+
+	// A holder panicked mid-call; the lock is ours again once the path is safe to use
+	fn recover<'a>(&self, mut path: MutexGuard<'a, Path<DEGREE>>) -> MutexGuard<'a, Path<DEGREE>> {
+		path.recover_from_panic();
+		self.path.clear_poison();
+		path
+	}
+
+	// Only a plain value, valid even if a holder panicked
+	fn take_skipped(&self) -> f64 {
+		let mut skipped = self.skipped.lock().unwrap_or_else(|err| err.into_inner());
+		std::mem::take(&mut *skipped)
+	}
+
+	pub fn replan(&self, s_target: f64, v_limit: f64) {
+		self.lock().replan(s_target, v_limit);
+	}
+
+	pub fn stop(&self) {
+		self.lock().stop();
+	}
+
+	pub fn update(&self, dt: f64) -> Progress {
+		let dt = dt + self.take_skipped();
+		self.lock().update(dt)
+	}
+
+	// Like update(), but returns None instead of waiting if the path is locked
+	pub fn try_update(&self, dt: f64) -> Option<Progress> {
+		match self.path.try_lock() {
+			Ok(mut path) => Some(path.update(dt + self.take_skipped())),
+			Err(TryLockError::Poisoned(err)) => Some(self.recover(err.into_inner()).update(dt + self.take_skipped())),
+			Err(TryLockError::WouldBlock) => {
+				*self.skipped.lock().unwrap_or_else(|err| err.into_inner()) += dt;
+				None
+			},
+		}
+	}
+
+	pub fn get_state(&self) -> (f64, f64, f64) {
+		self.lock().get_state()
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.lock().is_active()
+	}
+
+	// Run f with the path locked, for everything else
+	pub fn with<R>(&self, f: impl FnOnce(&mut Path<DEGREE>) -> R) -> R {
+		f(&mut self.lock())
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::SharedPath;
+	use crate::path::Path;
+
+	#[test]
+	fn replan_from_another_thread() -> Result<(), String> {
+		let shared = SharedPath::new(Path::acc_limited(0.5));
+		let planner = shared.clone();
+		std::thread::spawn(move || planner.replan(0.04, 0.1)).join().map_err(|_| "Planner thread panicked".to_string())?;
+
+		// A skipped update is made up by the next one
+		let guard = shared.path.lock().unwrap();
+		if shared.try_update(0.1).is_some() {
+			return Err("Updated while locked".to_string());
+		}
+		drop(guard);
+		shared.try_update(0.1).ok_or("Not updated when unlocked")?;
+		if (shared.get_state().0 - 0.01).abs() > 1e-12 {
+			return Err(format!("State after 0.2 s: {:?}", shared.get_state()));
+		}
+
+		while shared.is_active() {
+			shared.update(0.01);
+		}
+		if shared.with(|path| path.get_state()) != (0.04, 0.0, 0.0) {
+			return Err(format!("Ended at {:?}", shared.get_state()));
+		}
+		Ok(())
+	}
+
+	// A panic mid-call faults the path and stops it instead of running on whatever plan it left
+	#[test]
+	fn panic_while_locked() -> Result<(), String> {
+		use crate::path::{FaultKind, PathStatus};
+
+		let shared = SharedPath::new(Path::jerk_limited(0.5, 5.0));
+		shared.replan(1.0, 0.1);
+		shared.update(0.5);
+		let planner = shared.clone();
+		let result = std::thread::spawn(move || planner.with(|path| {
+			path.replan(-1.0, 0.1);
+			panic!("Mid-call");
+		})).join();
+		if result.is_ok() {
+			return Err("Expected the planner thread to panic".to_string());
+		}
+
+		shared.update(0.001);
+		if shared.with(|path| path.status()) != PathStatus::Faulted(FaultKind::Panicked) || shared.path.is_poisoned() {
+			return Err(format!("Not faulted and recovered: {:?}", shared.with(|path| path.status())));
+		}
+		let s_stop = shared.with(|path| path.plan_report().phases.last().map(|phase| phase.end.0)).ok_or("No stop planned")?;
+		while shared.is_active() {
+			shared.with(|path| path.checked_invariants()).map_err(|err| err.to_string())?;
+			shared.update(0.01);
+		}
+		if shared.get_state() != (s_stop, 0.0, 0.0) || s_stop < 0.0 {
+			return Err(format!("Stopped at {:?}, planned {}", shared.get_state(), s_stop));
+		}
+		Ok(())
+	}
+}
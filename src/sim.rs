@@ -0,0 +1,177 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::VecDeque;
+use crate::path::Path;
+
+const MAX_STEP: f64 = 1e-4;	// Integration step
+
+/*
+ * Simulated axis for trying out a plan against something physical: a mass
+ * with viscous damping, driven by a PD position controller with
+ * feedforward from the commanded (pos, vel, acc). Each command takes
+ * effect after the delay, as with a drive on a fieldbus. Meant for seeing
+ * how tracking error depends on the limits, e.g.
+ *
+ * let mut plant = Plant::new(2.0, 5.0);
+ * plant.set_delay(0.004);
+ * path.replan(0.04, 0.1);
+ * let error = plant.max_tracking_error(&mut path, 0.001);
+ */
+#[derive(Clone, Debug)]
+pub struct Plant {
+	mass: f64,
+	damping: f64,
+	gains: (f64, f64),	// (kp, kd) of the position controller
+	feedforward: bool,
+	delay: f64,
+	commands: VecDeque<(f64, (f64, f64, f64))>,	// (time due, command) not yet in effect
+	command: (f64, f64, f64),	// Command in effect
+	time: f64,
+	state: (f64, f64, f64),
+}
+
+impl Plant {
+	// Controller gains default to critically damped at 50 rad/s
+	pub fn new(mass: f64, damping: f64) -> Self {
+		assert!(mass > 0.0);
+		assert!(damping >= 0.0);
+		Self {
+			mass,
+			damping,
+			gains: (2500.0*mass, 100.0*mass - damping),
+			feedforward: true,
+			delay: 0.0,
+			commands: VecDeque::new(),
+			command: (0.0, 0.0, 0.0),
+			time: 0.0,
+			state: (0.0, 0.0, 0.0),
+		}
+	}
+
+	pub fn set_gains(&mut self, kp: f64, kd: f64) {
+		assert!(kp >= 0.0 && kd >= 0.0);
+		self.gains = (kp, kd);
+	}
+
+	// Force from the commanded velocity and acceleration, on by default
+	pub fn set_feedforward(&mut self, enabled: bool) {
+		self.feedforward = enabled;
+	}
+
+	// Time from a command until it takes effect
+	pub fn set_delay(&mut self, delay: f64) {
+		assert!(delay >= 0.0);
+		self.delay = delay;
+	}
+
+	/*
+	 * Give the command for now, then run the plant dt seconds. Returns the
+	 * measured (pos, vel, acc) at the end.
+	 */
+	pub fn update(&mut self, dt: f64, command: (f64, f64, f64)) -> (f64, f64, f64) {
+		assert!(dt >= 0.0);
+		self.commands.push_back((self.time + self.delay, command));
+
+		let steps = (dt/MAX_STEP).ceil().max(1.0);
+		let h = dt/steps;
+		for _ in 0..steps as usize {
+			while let Some(&(due, command)) = self.commands.front() {
+				if due > self.time {
+					break;
+				}
+				self.command = command;
+				self.commands.pop_front();
+			}
+
+			// Semi-implicit Euler
+			let (pos, vel, _) = self.state;
+			let (cmd_pos, cmd_vel, cmd_acc) = self.command;
+			let mut force = self.gains.0*(cmd_pos - pos) + self.gains.1*(cmd_vel - vel);
+			if self.feedforward {
+				force += self.mass*cmd_acc + self.damping*cmd_vel;
+			}
+			let acc = (force - self.damping*vel)/self.mass;
+			let vel = vel + acc*h;
+			self.state = (pos + vel*h, vel, acc);
+			self.time += h;
+		}
+		self.state
+	}
+
+	pub fn measured(&self) -> (f64, f64, f64) {
+		self.state
+	}
+
+	/*
+	 * Drive the plant from path, dt at a time, until the path is at rest.
+	 * Returns the largest difference between commanded and measured
+	 * position on the way.
+	 */
+	pub fn max_tracking_error<const DEGREE: usize>(&mut self, path: &mut Path<DEGREE>, dt: f64) -> f64 {
+		assert!(dt > 0.0);
+		let mut max_error = 0.0f64;
+		while path.is_active() {
+			let command = path.get_state();
+			let measured = self.update(dt, command);
+			path.update(dt);
+			max_error = max_error.max((command.0 - measured.0).abs());
+		}
+		max_error
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::Plant;
+	use crate::path::Path;
+
+	#[test]
+	fn tracking_error() -> Result<(), String> {
+		let error_with = |delay: f64, feedforward: bool| {
+			let mut path = Path::acc_limited(0.5);
+			let mut plant = Plant::new(2.0, 5.0);
+			plant.set_delay(delay);
+			plant.set_feedforward(feedforward);
+			path.replan(0.04, 0.1);
+			let error = plant.max_tracking_error(&mut path, 0.001);
+			for _ in 0..500 {
+				plant.update(0.001, path.get_state());
+			}
+			(error, plant.measured().0)
+		};
+
+		// Feedforward tracks all but the acceleration steps with no delay
+		let (error, pos) = error_with(0.0, true);
+		if error > 1e-4 || (pos - 0.04).abs() > 1e-6 {
+			return Err(format!("No delay: error {}, settled at {}", error, pos));
+		}
+
+		// A delay lags by about delay*velocity
+		let (error, pos) = error_with(0.01, true);
+		if !(0.5e-3..2e-3).contains(&error) || (pos - 0.04).abs() > 1e-6 {
+			return Err(format!("10 ms delay: error {}, settled at {}", error, pos));
+		}
+
+		// PD alone lags more
+		let (pd_error, _) = error_with(0.01, false);
+		if pd_error <= error {
+			return Err(format!("Without feedforward: error {}, with {}", pd_error, error));
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,113 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+/*
+ * Minimal streaming front end: reads moves from any BufRead, one per line,
+ *
+ *   # Comments and blank lines are skipped
+ *   move-to 0.04 0.1
+ *   move-to 0.0 0.05
+ *
+ * (target position and velocity limit), runs them in order on a Path with
+ * queue_move() and writes the state every dt as "t,pos,vel,acc" lines
+ * (after a header) to any Write, until the last move is done. t is the
+ * path's timestamp, see Path::set_epoch(). The next line is read as soon
+ * as the queue is empty, so with QueuePolicy::Blend consecutive moves run
+ * on without stopping.
+ */
+
+use std::io::{self, BufRead, Write};
+use crate::path::Path;
+
+pub fn run_stream<const DEGREE: usize>(path: &mut Path<DEGREE>, input: impl BufRead, mut output: impl Write, dt: f64) -> io::Result<()> {
+	assert!(dt > 0.0);
+	let mut lines = input.lines().enumerate();
+	let mut eof = false;
+	let mut step = 0;
+	let start = path.timestamp();
+	writeln!(output, "t,pos,vel,acc")?;
+	loop {
+		while !eof && path.queued_moves() == 0 {
+			match lines.next() {
+				Some((n, line)) => if let Some((pos, vel)) = parse_line(n + 1, &line?)? {
+					path.queue_move(pos, vel);
+				},
+				None => eof = true,
+			}
+		}
+		let (pos, vel, acc) = path.get_output_state();
+		writeln!(output, "{},{},{},{}", start + step as f64*dt, pos, vel, acc)?;
+		if eof && !path.is_active() && path.queued_moves() == 0 {
+			return Ok(());
+		}
+		path.update(dt);
+		step += 1;
+	}
+}
+
+// (pos, vel) of a move command, or None for a blank or comment line
+fn parse_line(number: usize, line: &str) -> io::Result<Option<(f64, f64)>> {
+	let bad = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", number, msg));
+	let words: Vec<&str> = line.split_whitespace().collect();
+	match words.as_slice() {
+		[] => Ok(None),
+		[first, ..] if first.starts_with('#') => Ok(None),
+		["move-to", pos, vel] => {
+			let num = |val: &str| val.parse::<f64>().map_err(|err| bad(format!("Bad number '{}': {}", val, err)));
+			let (pos, vel) = (num(pos)?, num(vel)?);
+			if !pos.is_finite() || !vel.is_finite() || vel <= 0.0 {
+				return Err(bad(format!("Expected a finite position and a positive velocity, got {} {}", pos, vel)));
+			}
+			Ok(Some((pos, vel)))
+		},
+		_ => Err(bad(format!("Expected 'move-to POS VEL', got '{}'", line.trim()))),
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::run_stream;
+	use crate::path::Path;
+	use crate::scenario::check_states_eq;
+
+	#[test]
+	fn moves_from_text() -> Result<(), String> {
+		let input = "# Out and back\nmove-to 0.04 0.1\n\nmove-to 0.0 0.1\n";
+		let mut output = Vec::new();
+		run_stream(&mut Path::acc_limited(0.5), input.as_bytes(), &mut output, 0.01).map_err(|err| err.to_string())?;
+
+		let output = String::from_utf8(output).map_err(|err| err.to_string())?;
+		let rows: Vec<Vec<f64>> = output.lines().skip(1)
+			.map(|line| line.split(',').map(|val| val.parse().unwrap()).collect())
+			.collect();
+		let state = |row: &Vec<f64>| (row[1], row[2], row[3]);
+		// Two moves of 0.6 s
+		check_states_eq(0.6, state(&rows[60]), (0.04, 0.0, 0.0))?;
+		let last = rows.last().unwrap();
+		check_states_eq(last[0], state(last), (0.0, 0.0, 0.0))?;
+		if rows.len() != 121 {
+			return Err(format!("Expected 121 samples, got {}", rows.len()));
+		}
+
+		let err = run_stream(&mut Path::acc_limited(0.5), "move-to 0.04 0.1\nmove 1\n".as_bytes(), Vec::new(), 0.01);
+		if !err.is_err_and(|err| err.to_string().starts_with("Line 2:")) {
+			return Err("Expected an error on line 2".to_string());
+		}
+		Ok(())
+	}
+}
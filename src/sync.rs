@@ -0,0 +1,239 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::path::{Path, Limits};
+use crate::segment::Segment;
+
+/*
+ * Axes of one machine, e.g. the X and Y of a gantry, updated together and
+ * stopped together by stop_all().
+ */
+#[derive(Clone, Debug)]
+pub struct SyncGroup<const DEGREE: usize> {
+	axes: Vec<Path<DEGREE>>,
+}
+
+impl<const DEGREE: usize> SyncGroup<DEGREE> {
+	pub fn new(axes: Vec<Path<DEGREE>>) -> Self {
+		assert!(!axes.is_empty());
+		Self {
+			axes,
+		}
+	}
+
+	pub fn axes(&self) -> &[Path<DEGREE>] {
+		&self.axes
+	}
+
+	pub fn axis_mut(&mut self, index: usize) -> &mut Path<DEGREE> {
+		&mut self.axes[index]
+	}
+
+	pub fn update(&mut self, dt: f64) {
+		for axis in self.axes.iter_mut() {
+			axis.update(dt);
+		}
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.axes.iter().any(|axis| axis.is_active())
+	}
+
+	/*
+	 * Stop all axes at the same time, each with the velocity profile of
+	 * the same unit-velocity stop scaled by its current velocity. Axes
+	 * moving along a straight line in joint space (velocities, and
+	 * accelerations, in proportion) then stay on it until they stop. The
+	 * unit stop is planned with the lowest of each axis's limits divided
+	 * by its speed, so that each scaled stop is within that axis's limits.
+	 * Acceleration biases and braking limits are not taken into account.
+	 *
+	 * On jerk-limited axes the acceleration can't jump, so an axis whose
+	 * acceleration isn't in proportion to the fastest axis's (it isn't on
+	 * the line, e.g. still speeding up towards its own cruise velocity, or
+	 * at rest with an acceleration) can't follow the scaled stop. It stops
+	 * on its own instead, as do all axes if none is moving. Either way the
+	 * stops all take as long as the longest of them, the shorter ones
+	 * planned with a lower acceleration limit to fit; only an axis already
+	 * braking to rest at its jerk limit keeps its shorter stop. Returns the
+	 * indices of the axes that stopped on their own while moving.
+	 */
+	pub fn stop_all(&mut self) -> Vec<usize> {
+		let states: Vec<(f64, f64, f64)> = self.axes.iter().map(|axis| axis.get_state()).collect();
+		let lead = (0..states.len()).max_by(|a, b| states[*a].1.abs().total_cmp(&states[*b].1.abs())).unwrap();
+		let v_lead = states[lead].1;
+		let a_unit = if v_lead == 0.0 { 0.0 } else { states[lead].2/v_lead };
+		let on_line: Vec<bool> = self.axes.iter().zip(&states)
+			.map(|(axis, state)| v_lead != 0.0 && (DEGREE == 2 || (state.2 - state.1*a_unit).abs() <= 1e-9*axis.limits().acc))
+			.collect();
+		let off_line: Vec<usize> = (0..states.len())
+			.filter(|n| !on_line[*n] && (states[*n].1 != 0.0 || (DEGREE == 3 && states[*n].2 != 0.0)))
+			.collect();
+
+		let ratio = |limit: fn(&Limits) -> f64| self.axes.iter().zip(&states).zip(&on_line)
+			.filter(|((_, state), on_line)| **on_line && state.1 != 0.0)
+			.map(|((axis, state), _)| limit(&axis.limits())/state.1.abs())
+			.fold(f64::INFINITY, f64::min);
+		let unit_limits = Limits {
+			vel: None,
+			acc: ratio(|limits| limits.acc),
+			jerk: self.axes[0].limits().jerk.map(|_| ratio(|limits| limits.jerk.unwrap())),
+			snap: None,
+		};
+		let unit_state = (0.0, 1.0, a_unit);
+
+		// The longest stop sets the duration of all of them
+		let mut duration = off_line.iter()
+			.map(|n| plan_duration(&stop_within::<DEGREE>(self.axes[*n].limits(), states[*n], 0.0)))
+			.fold(0.0, f64::max);
+		if v_lead != 0.0 {
+			duration = duration.max(plan_duration(&stop_within::<DEGREE>(unit_limits, unit_state, 0.0)));
+		}
+		let unit_stop = if v_lead == 0.0 { Vec::new() } else { stop_within::<DEGREE>(unit_limits, unit_state, duration) };
+
+		for (n, (axis, state)) in self.axes.iter_mut().zip(&states).enumerate() {
+			if off_line.contains(&n) {
+				axis.set_plan(stop_within::<DEGREE>(axis.limits(), *state, duration));
+				continue;
+			}
+			if !on_line[n] {
+				// At rest
+				axis.stop();
+				continue;
+			}
+			let k = state.1;
+			let segments = unit_stop.iter().map(|seg| {
+				let mut vals = seg.get_state_at(0.0);
+				let pos_index = vals.len() - 1;
+				for val in vals.iter_mut() {
+					*val *= k;
+				}
+				vals[pos_index] += state.0;
+				Segment::new(&vals, seg.get_duration(), DEGREE + 1)
+			}).collect();
+			axis.set_plan(segments);
+		}
+		off_line
+	}
+}
+
+fn plan_duration(segments: &[Segment]) -> f64 {
+	segments.iter().map(|seg| seg.get_duration()).sum()
+}
+
+/*
+ * Stop from state within limits taking duration, by bisection on the
+ * acceleration limit. The jerk limit stays as it is: with less jerk, an
+ * axis still speeding up would end up faster before it starts to brake.
+ * A stop that takes longer at the full limits is planned at them.
+ */
+fn stop_within<const DEGREE: usize>(limits: Limits, state: (f64, f64, f64), duration: f64) -> Vec<Segment> {
+	let stop = |factor: f64| Path::<DEGREE>::with_limits(Limits { acc: factor*limits.acc, ..limits }).stop_from(state);
+
+	let full = stop(1.0);
+	if plan_duration(&full) >= duration {
+		return full;
+	}
+	let (mut lo, mut hi) = (0.0, 1.0);
+	for _ in 0..60 {
+		let mid = 0.5*(lo + hi);
+		if plan_duration(&stop(mid)) > duration {
+			lo = mid;
+		} else {
+			hi = mid;
+		}
+	}
+	if lo == 0.0 {
+		// Already braking to rest at the jerk limit, which no lower acceleration limit slows down
+		return full;
+	}
+	stop(hi)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::SyncGroup;
+	use crate::path::Path;
+
+	// Joint-space line of a stop, from positions at the start of it
+	fn check_line(start: &[(f64, f64, f64)], now: &[(f64, f64, f64)], t: f64) -> Result<(), String> {
+		let d0 = now[0].0 - start[0].0;
+		let d1 = now[1].0 - start[1].0;
+		if (d0*start[1].1 - d1*start[0].1).abs() > 1e-12 {
+			return Err(format!("Time {:.3}: off the line, moved {} and {}", t, d0, d1));
+		}
+		Ok(())
+	}
+
+	#[test]
+	fn straight_stop() -> Result<(), String> {
+		fn run<const DEGREE: usize>(mut group: SyncGroup<DEGREE>) -> Result<(), String> {
+			group.axis_mut(0).replan(1.0, 0.1);
+			group.axis_mut(1).replan(-0.5, 0.05);
+			group.update(2.0);
+			let start: Vec<_> = group.axes().iter().map(|axis| axis.get_state()).collect();
+			group.stop_all();
+			let durations: Vec<f64> = group.axes().iter().map(|axis| axis.plan_report().get_duration()).collect();
+			if (durations[0] - durations[1]).abs() > 1e-12 {
+				return Err(format!("Stop durations {:?}", durations));
+			}
+
+			let mut t = 2.0;
+			while group.is_active() {
+				group.update(0.001);
+				t += 0.001;
+				let now: Vec<_> = group.axes().iter().map(|axis| axis.get_state()).collect();
+				check_line(&start, &now, t)?;
+				for axis in group.axes() {
+					axis.checked_invariants().map_err(|err| format!("Time {:.3}: {}", t, err))?;
+				}
+			}
+			Ok(())
+		}
+		run(SyncGroup::new(vec![Path::acc_limited(0.5), Path::acc_limited(0.5)]))?;
+		run(SyncGroup::new(vec![Path::jerk_limited(0.5, 5.0), Path::jerk_limited(0.4, 5.0)]))
+	}
+
+	// A jerk-limited axis still speeding up isn't on the line; it stops on its own, without a jump in acceleration, but at the same time
+	#[test]
+	fn stop_off_the_line() -> Result<(), String> {
+		let mut group = SyncGroup::new(vec![Path::jerk_limited(0.5, 5.0), Path::jerk_limited(0.5, 5.0)]);
+		group.axis_mut(0).replan(1.0, 0.1);
+		group.update(0.5);
+		group.axis_mut(1).replan(-1.0, 0.1);
+		group.update(0.15);
+		let before: Vec<_> = group.axes().iter().map(|axis| axis.get_state()).collect();
+		if (before[1].2 - before[1].1*before[0].2/before[0].1).abs() < 0.1 {
+			return Err(format!("Expected accelerations out of proportion: {:?}", before));
+		}
+
+		let off_line = group.stop_all();
+		if off_line != [1] {
+			return Err(format!("Expected axis 1 off the line, got {:?}", off_line));
+		}
+		let durations: Vec<f64> = group.axes().iter().map(|axis| axis.plan_report().get_duration()).collect();
+		if (durations[0] - durations[1]).abs() > 1e-9 {
+			return Err(format!("Stop durations {:?}", durations));
+		}
+		while group.is_active() {
+			for axis in group.axes() {
+				axis.checked_invariants().map_err(|err| err.to_string())?;
+			}
+			group.update(0.001);
+		}
+		Ok(())
+	}
+}
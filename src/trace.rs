@@ -0,0 +1,124 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/*
+ * Which way the planner went. calc_path_2() plans one level, position with
+ * acc-limited phases or velocity with jerk-limited phases, and ends in one
+ * of the first six; calc_path_3() adds a coast at the velocity limit, or
+ * has to remove it and lower the peak velocity.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum Branch {
+	Trapezoid,		// Reaches the limit and cruises at it
+	Triangle,		// Never reaches the limit
+	OvershootReversal,	// Passes the target and comes back in the same plan
+	StopFirst,		// Overshoots; full stop first, then a new plan from rest
+	ApproachBrake,		// Too fast for the approach acceleration; brakes harder first
+	RampDown,		// Braking straight to rest ends within rounding of the target
+	Coast,			// Jerk-limited move coasting at the velocity limit
+	CoastRemoved,		// Jerk-limited move too short to reach the velocity limit
+	PeakLowered,		// The peak velocity of such a move, or of the way back if it reverses
+	Stop,
+}
+
+/*
+ * One decision, with the quantities that led to it by name, e.g. s_diff,
+ * v0, t0, a0 as in the source of calc_path_2().
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct TraceStep {
+	pub branch: Branch,
+	pub quantities: Vec<(&'static str, f64)>,
+}
+
+impl TraceStep {
+	pub fn get(&self, name: &str) -> Option<f64> {
+		self.quantities.iter().find(|(n, _)| *n == name).map(|(_, val)| *val)
+	}
+}
+
+/*
+ * The decisions behind the latest replan() or stop(), in the order they
+ * were made, see Path::set_trace(). Nested plans (the velocity changes of
+ * a jerk-limited move, the move back after StopFirst) add their own steps.
+ */
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct PlanTrace {
+	pub steps: Vec<TraceStep>,
+}
+
+impl PlanTrace {
+	pub fn new() -> Self {
+		Self {
+			steps: Vec::new(),
+		}
+	}
+
+	pub fn branches(&self) -> Vec<Branch> {
+		self.steps.iter().map(|step| step.branch).collect()
+	}
+
+	pub(crate) fn push(&mut self, branch: Branch, quantities: &[(&'static str, f64)]) {
+		self.steps.push(TraceStep {
+			branch,
+			quantities: quantities.to_vec(),
+		});
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::Branch;
+	use crate::path::{Path, OvershootPolicy};
+
+	#[test]
+	fn branches_taken() -> Result<(), String> {
+		let mut path = Path::acc_limited(0.5);
+		if path.plan_trace().is_some() {
+			return Err("Trace on by default".to_string());
+		}
+		path.set_trace(true);
+
+		path.replan(0.04, 0.1);
+		let trace = path.plan_trace().unwrap();
+		if trace.branches() != [Branch::Trapezoid] || (trace.steps[0].get("t1").unwrap_or(0.0) - 0.2).abs() > 1e-9 {
+			return Err(format!("Long move: {:?}", trace));
+		}
+
+		path.update(0.3);
+		path.replan(0.021, 0.1);
+		if path.plan_trace().unwrap().branches() != [Branch::OvershootReversal] {
+			return Err(format!("Overshooting move: {:?}", path.plan_trace()));
+		}
+
+		path.set_overshoot_policy(OvershootPolicy::Forbid);
+		path.replan(0.021, 0.1);
+		if path.plan_trace().unwrap().branches() != [Branch::StopFirst, Branch::Triangle] {
+			return Err(format!("Forbidden overshoot: {:?}", path.plan_trace()));
+		}
+
+		let mut path = Path::jerk_limited(0.5, 5.0);
+		path.set_trace(true);
+		path.replan(1.0, 0.1);
+		let branches = path.plan_trace().unwrap().branches();
+		if branches != [Branch::Trapezoid, Branch::Trapezoid, Branch::Coast, Branch::Trapezoid] {
+			return Err(format!("Jerk-limited move: {:?}", branches));
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,191 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/*
+ * Lightweight acc-limited planner for when jerk limiting isn't needed. Same
+ * trajectories as Path::acc_limited() with the default settings (overshoot
+ * allowed, no bias, approach or braking limits), but the plan is just the
+ * three phase times of the trapezoid (or triangle): speed up or slow down
+ * to the cruise velocity, cruise, brake. These are computed in closed form
+ * and the state is evaluated directly from them.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TrapezoidPlanner {
+	max_acc: f64,
+	start: (f64, f64),	// (pos, vel) at the start of the plan
+	target: f64,
+	acc: [f64; 3],		// Acceleration of each phase
+	ends: [f64; 3],		// End time of each phase
+	pos: [f64; 3],		// Position at the end of each phase
+	vel: [f64; 3],		// Velocity at the end of each phase
+	time: f64,			// Time into the plan
+}
+
+impl TrapezoidPlanner {
+	pub fn new(max_acc: f64) -> Self {
+		assert!(max_acc > 0.0);
+		Self {
+			max_acc,
+			start: (0.0, 0.0),
+			target: 0.0,
+			acc: [0.0; 3],
+			ends: [0.0; 3],
+			pos: [0.0; 3],
+			vel: [0.0; 3],
+			time: 0.0,
+		}
+	}
+
+	pub fn replan(&mut self, s_target: f64, v_limit: f64) {
+		assert!(v_limit >= 0.0);
+		if v_limit == 0.0 {
+			self.stop();
+			return;
+		}
+
+		let (s0, v0) = self.get_state2();
+		let a = self.max_acc;
+		let s_diff = s_target - s0;
+
+		// Cruise on the way back if we can't stop before the target
+		let s_stop = 0.5*v0*v0.abs()/a;
+		let dir = if (s_stop - s_diff)*v0.signum() > 1e-12 { -v0.signum() } else { s_diff.signum() };
+		self.start = (s0, v0);
+		self.target = s_target;
+		if s_diff == 0.0 && v0 == 0.0 {
+			self.set_phases([0.0; 3], [0.0; 3]);
+			return;
+		}
+
+		let mut v1 = dir*v_limit;
+		let mut a0 = (v1 - v0).signum()*a;
+		let a2 = -dir*a;
+		let mut t0 = (v1 - v0)/a0;
+		let mut t1 = (s_diff - 0.5*(v0 + v1)*t0 - 0.5*v1*v_limit/a)/v1;
+		if t1 < 0.0 {
+			// Triangle, peak velocity from dir*s_diff = (2*vp² - v0²)/(2*a)
+			let vp = (a*dir*s_diff + 0.5*v0*v0).max(0.0).sqrt();
+			v1 = dir*vp;
+			a0 = dir*a;
+			t0 = (v1 - v0)/a0;
+			t1 = 0.0;
+		}
+		self.set_phases([a0, 0.0, a2], [t0, t1, v1.abs()/a]);
+	}
+
+	pub fn stop(&mut self) {
+		let (s0, v0) = self.get_state2();
+		let a0 = -v0.signum()*self.max_acc;
+		self.start = (s0, v0);
+		self.set_phases([a0, 0.0, 0.0], [v0.abs()/self.max_acc, 0.0, 0.0]);
+		self.target = self.pos[2];
+	}
+
+	fn set_phases(&mut self, acc: [f64; 3], durations: [f64; 3]) {
+		let (mut s, mut v) = self.start;
+		let mut t = 0.0;
+		for (n, (dt, a)) in durations.iter().zip(acc.iter()).enumerate() {
+			s += v*dt + 0.5*a*dt*dt;
+			v += a*dt;
+			t += dt;
+			self.pos[n] = s;
+			self.vel[n] = v;
+			self.ends[n] = t;
+		}
+		self.acc = acc;
+		self.time = 0.0;
+	}
+
+	pub fn update(&mut self, dt: f64) {
+		assert!(dt >= 0.0);
+		self.time += dt;
+	}
+
+	pub fn get_state(&self) -> (f64, f64, f64) {
+		if !self.is_active() {
+			return (self.target, 0.0, 0.0);
+		}
+
+		let phase = self.ends.iter().position(|end| self.time < *end).unwrap();
+		let (s, v, t) = match phase {
+			0 => (self.start.0, self.start.1, self.time),
+			n => (self.pos[n - 1], self.vel[n - 1], self.time - self.ends[n - 1]),
+		};
+		let a = self.acc[phase];
+		(s + v*t + 0.5*a*t*t, v + a*t, a)
+	}
+
+	fn get_state2(&self) -> (f64, f64) {
+		let state = self.get_state();
+		(state.0, state.1)
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.time < self.ends[2]
+	}
+
+	// Time left of the plan
+	pub fn remaining(&self) -> f64 {
+		(self.ends[2] - self.time).max(0.0)
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::TrapezoidPlanner;
+	use crate::path::Path;
+	use crate::scenario::check_states_eq;
+
+	#[test]
+	fn same_as_path() -> Result<(), String> {
+		let mut planner = TrapezoidPlanner::new(0.5);
+		let mut path = Path::acc_limited(0.5);
+
+		// Long move, shortened with overshoot, reversed, stopped
+		let moves: [(f64, Option<f64>); 4] = [(0.0, Some(0.04)), (0.3, Some(0.021)), (0.8, Some(-0.003)), (1.2, None)];
+		let dt = 0.001;
+		let mut t = 0.0;
+		for step in 0..2000 {
+			for (t_move, target) in moves.iter() {
+				if step == (t_move/dt).round() as usize {
+					match target {
+						Some(s_target) => {
+							planner.replan(*s_target, 0.1);
+							path.replan(*s_target, 0.1);
+						},
+						None => {
+							planner.stop();
+							path.stop();
+						},
+					}
+				}
+			}
+			planner.update(dt);
+			path.update(dt);
+			t += dt;
+
+			// The acceleration differs at the instants where it jumps
+			let p = path.get_state();
+			let q = planner.get_state();
+			check_states_eq(t, (q.0, q.1, 0.0), (p.0, p.1, 0.0))?;
+		}
+		if planner.is_active() {
+			return Err(format!("Still active at {:?}", planner.get_state()));
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,114 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::path::{Limits, Path};
+use crate::report::{PlanReport, PhaseReport};
+
+/*
+ * Grid of limits to try: every acceleration with every jerk. No jerk
+ * values means acc-limited paths.
+ */
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct LimitsGrid {
+	pub acc: Vec<f64>,
+	pub jerk: Vec<f64>,
+}
+
+// A move from rest to rest over distance
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Move {
+	pub distance: f64,
+	pub v_limit: f64,
+}
+
+/*
+ * How a set of moves comes out with one set of limits. The durations are
+ * per move, in the order given; the peaks are over all of them.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct SweepResult {
+	pub limits: Limits,
+	pub durations: Vec<f64>,
+	pub total_duration: f64,
+	pub peak_vel: f64,
+	pub peak_acc: f64,
+}
+
+/*
+ * Plan each move with each set of limits in the grid, e.g. to find the
+ * gentlest limits that still meet a cycle time:
+ *
+ * sweep(&grid, &moves).into_iter().filter(|res| res.total_duration < 1.2)
+ */
+pub fn sweep(grid: &LimitsGrid, moves: &[Move]) -> Vec<SweepResult> {
+	let mut results = Vec::new();
+	for &acc in &grid.acc {
+		if grid.jerk.is_empty() {
+			results.push(evaluate(&Path::acc_limited(acc), moves));
+		}
+		for &jerk in &grid.jerk {
+			results.push(evaluate(&Path::jerk_limited(acc, jerk), moves));
+		}
+	}
+	results
+}
+
+fn evaluate<const DEGREE: usize>(path: &Path<DEGREE>, moves: &[Move]) -> SweepResult {
+	let reports: Vec<PlanReport> = moves.iter()
+		.map(|mv| PlanReport::new(path.plan_from((0.0, 0.0, 0.0), mv.distance, mv.v_limit)))
+		.collect();
+	let durations: Vec<f64> = reports.iter().map(|report| report.get_duration()).collect();
+	let peak = |value: fn(&PhaseReport) -> f64| reports.iter()
+		.flat_map(|report| report.phases.iter().map(value))
+		.fold(0.0, f64::max);
+	SweepResult {
+		limits: path.limits(),
+		total_duration: durations.iter().sum(),
+		durations,
+		peak_vel: peak(|phase| phase.peak_vel),
+		peak_acc: peak(|phase| phase.peak_acc),
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::{sweep, LimitsGrid, Move};
+
+	#[test]
+	fn grid_of_limits() -> Result<(), String> {
+		let moves = [Move { distance: 0.04, v_limit: 0.1 }, Move { distance: -0.5, v_limit: 0.2 }];
+		let grid = LimitsGrid { acc: vec![0.5, 1.0], jerk: Vec::new() };
+		let results = sweep(&grid, &moves);
+		if results.len() != 2 || (results[0].durations[0] - 0.6).abs() > 1e-9 || (results[0].peak_acc - 0.5).abs() > 1e-9 {
+			return Err(format!("Acc-limited sweep: {:?}", results));
+		}
+		if results[1].total_duration >= results[0].total_duration || (results[1].peak_vel - 0.2).abs() > 1e-9 {
+			return Err(format!("Higher acceleration should be faster: {:?}", results));
+		}
+
+		let grid = LimitsGrid { acc: vec![0.5, 1.0], jerk: vec![5.0, 10.0, 20.0] };
+		let results = sweep(&grid, &moves);
+		if results.len() != 6 || results.iter().any(|res| res.limits.jerk.is_none() || res.peak_acc > res.limits.acc + 1e-9) {
+			return Err(format!("Jerk-limited sweep: {:?}", results));
+		}
+		// Higher jerk, shorter moves
+		if results[0].total_duration <= results[1].total_duration || results[1].total_duration <= results[2].total_duration {
+			return Err(format!("Durations over jerk: {:?}", results.iter().map(|res| res.total_duration).collect::<Vec<_>>()));
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::path::{Path, Limits};
+use crate::program::Move;
+
+const STEPS: usize = 6;		// Jerk limits tried, each JERK_FACTOR times the one before
+const JERK_FACTOR: f64 = 4.0;
+const SAMPLES: f64 = 2000.0;	// Samples over the acc-limited move
+
+/*
+ * How far the jerk-limited plans of a move were from the acc-limited one,
+ * for each jerk limit tried: the difference in duration and the largest
+ * difference in position at the same time.
+ */
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Consistency {
+	pub jerks: Vec<f64>,
+	pub duration_gaps: Vec<f64>,
+	pub pos_gaps: Vec<f64>,
+}
+
+/*
+ * Check that jerk-limited plans of a move from rest converge to the
+ * acc-limited plan as the jerk limit grows: both gaps must shrink with
+ * each step up in jerk, and the jerk-limited move must take at most
+ * 2*a/j longer, a/j for each of its two velocity changes. Starts from
+ * limits.jerk, or from a jerk that ramps in a tenth of the acc-limited
+ * move without one; the other limits are used for both degrees. Like the
+ * jerk-limited planner, the move must be long enough to reach v_limit.
+ */
+pub fn degree_consistency(limits: Limits, mv: Move) -> Result<Consistency, String> {
+	let acc_limits = Limits { jerk: None, ..limits };
+	let mut reference = Path::<2>::with_limits(acc_limits);
+	reference.replan(mv.target, mv.v_limit);
+	let duration = reference.update(0.0).remaining;
+	let dt = duration/SAMPLES;
+
+	let mut jerk = limits.jerk.unwrap_or(10.0*limits.acc/duration);
+	let mut result = Consistency::default();
+	for step in 0..STEPS {
+		let mut acc_path = Path::<2>::with_limits(acc_limits);
+		let mut jerk_path = Path::<3>::with_limits(Limits { jerk: Some(jerk), ..limits });
+		acc_path.replan(mv.target, mv.v_limit);
+		jerk_path.replan(mv.target, mv.v_limit);
+		let duration_gap = jerk_path.update(0.0).remaining - duration;
+
+		let mut pos_gap: f64 = 0.0;
+		while acc_path.is_active() || jerk_path.is_active() {
+			acc_path.update(dt);
+			jerk_path.update(dt);
+			pos_gap = pos_gap.max((jerk_path.get_state().0 - acc_path.get_state().0).abs());
+		}
+
+		let bound = 2.0*limits.acc/jerk;
+		if duration_gap < -1e-9 || duration_gap > bound + 1e-9 {
+			return Err(format!("Jerk {}: duration {} off the acc-limited {}, allowed up to {}", jerk, duration + duration_gap, duration, bound));
+		}
+		if step > 0 && (duration_gap >= result.duration_gaps[step - 1] || pos_gap >= result.pos_gaps[step - 1]) {
+			return Err(format!("Jerk {}: gaps {} s and {} didn't shrink from {} s and {}",
+				jerk, duration_gap, pos_gap, result.duration_gaps[step - 1], result.pos_gaps[step - 1]));
+		}
+		result.jerks.push(jerk);
+		result.duration_gaps.push(duration_gap);
+		result.pos_gaps.push(pos_gap);
+		jerk *= JERK_FACTOR;
+	}
+	Ok(result)
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::degree_consistency;
+	use crate::path::Limits;
+	use crate::program::Move;
+	use crate::scenario::check_eq;
+
+	#[test]
+	fn jerk_limited_converges() -> Result<(), String> {
+		let limits = Limits::jerk_limited(0.5, 5.0);
+		let result = degree_consistency(limits, Move::new(1.0, 0.1))?;
+		// A trapezoid in velocity: each ramp adds half its duration a/j
+		for (jerk, gap) in result.jerks.iter().zip(&result.duration_gaps) {
+			check_eq(*jerk, *gap, 0.5/jerk)?;
+		}
+		Ok(())
+	}
+}
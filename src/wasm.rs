@@ -0,0 +1,66 @@
+/*
+ * Copyright (c) 2021 Arvid Brodin
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but
+ * WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+ * General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <http://www.gnu.org/licenses/>.
+*/
+
+
+/*
+ * JavaScript bindings, built with the "wasm" feature:
+ *
+ *   const path = new Path(new Float64Array([0.5, 5.0]));
+ *   path.replan(0.04, 0.1);
+ *   path.update(0.001);
+ *   const [pos, vel, acc] = path.get_state();
+ */
+
+use wasm_bindgen::prelude::*;
+use crate::path::{DynPath, Limits};
+
+#[wasm_bindgen(js_name = Path)]
+pub struct WasmPath {
+	path: DynPath,
+}
+
+#[wasm_bindgen(js_class = Path)]
+impl WasmPath {
+	// Limits: (MAX_)ACC, (JERK)
+	#[wasm_bindgen(constructor)]
+	pub fn new(limits: Vec<f64>) -> WasmPath {
+		WasmPath {
+			path: DynPath::with_limits(Limits::from_slice(&limits)),
+		}
+	}
+
+	pub fn replan(&mut self, s_target: f64, v_limit: f64) {
+		self.path.replan(s_target, v_limit);
+	}
+
+	pub fn stop(&mut self) {
+		self.path.stop();
+	}
+
+	pub fn update(&mut self, dt: f64) {
+		self.path.update(dt);
+	}
+
+	// [pos, vel, acc]
+	pub fn get_state(&self) -> Vec<f64> {
+		let state = self.path.get_state();
+		vec![state.0, state.1, state.2]
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.path.is_active()
+	}
+}